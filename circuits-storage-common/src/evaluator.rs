@@ -0,0 +1,51 @@
+//! Evaluate a garbled display circuit(ie a `garble_and_strip` output) like the phone does.
+//! Shared by the self-check in `pallet-ocw-garble`(`LibGarbleRs::evaluate`) and by `reference-evaluator`.
+//! Requires the feature "lib-garble-rs".
+use crate::seven_segments::Frame;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// `nb_frames` frames, each with random evaluator inputs("rndswitch"), merged; cf `Frame::merge`
+///
+/// param: expected_size: (width, height) of the layout the circuit was generated with; an Err if
+///   the circuit does not match, else the digits bboxes would be read on the wrong pixels
+/// param: rng: the phone uses a random one per frame; a seeded one is fine for a self-check
+pub fn evaluate_display<R: rand::Rng>(
+    serialized_package_for_eval: &[u8],
+    nb_frames: u32,
+    expected_size: (u32, u32),
+    rng: &mut R,
+) -> Result<Frame, String> {
+    let (garb, encoded_garbler_inputs) =
+        lib_garble_rs::deserialize_for_evaluator(serialized_package_for_eval)
+            .map_err(|err| format!("deserialize_for_evaluator: {}", err.to_string()))?;
+    let display_config = garb
+        .get_display_config()
+        .ok_or_else(|| "not a display circuit".to_string())?;
+    if (display_config.width, display_config.height) != expected_size {
+        return Err(format!(
+            "the circuit is {}x{}, the layout is {}x{}",
+            display_config.width, display_config.height, expected_size.0, expected_size.1
+        ));
+    }
+
+    let mut frame = Frame::new(display_config.width, display_config.height);
+    let mut outputs = Vec::new();
+    let mut eval_cache = lib_garble_rs::EvalCache::new();
+    for _ in 0..nb_frames {
+        let evaluator_inputs: Vec<u8> = (0..garb.num_evaluator_inputs())
+            .map(|_| rng.gen_range(0..=1))
+            .collect();
+        garb.eval(
+            &encoded_garbler_inputs,
+            &evaluator_inputs,
+            &mut outputs,
+            &mut eval_cache,
+        )
+        .map_err(|err| format!("eval: {}", err.to_string()))?;
+        frame.merge(&outputs);
+    }
+
+    Ok(frame)
+}
@@ -0,0 +1,230 @@
+//! Shared by `pallet-ocw-circuits` and `pallet-ocw-garble`: a list of IPFS API endpoints
+//! tried in priority order, skipping the ones that recently failed.
+//!
+//! The health of each endpoint is tracked via `FailoverEnv`; typically the offchain local storage
+//! so that it is shared between the offchain worker runs.
+use alloc::string::String;
+use alloc::vec::Vec;
+use codec::{Decode, Encode};
+use frame_support::RuntimeDebug;
+
+/// Per endpoint health
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, Default)]
+pub struct EndpointHealth {
+    pub consecutive_failures: u32,
+    /// cf `FailoverEnv::now_ms`; only meaningful if `consecutive_failures > 0`
+    pub last_failure_ms: u64,
+}
+
+#[derive(Clone, Eq, PartialEq, RuntimeDebug)]
+pub struct FailoverConfig {
+    /// After this many failures in a row an endpoint is considered down...
+    pub max_consecutive_failures: u32,
+    /// ...for this long; after that it is tried again in its normal priority
+    pub cooldown_ms: u64,
+    /// Deadline of each request: it is given to the caller's closure(cf `with_failover`) to set
+    /// on its HTTP client. An answer arriving after it counts as a failure; and it is only used
+    /// if no other endpoint answers in time.
+    pub request_timeout_ms: u64,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: 3,
+            cooldown_ms: 60_000,
+            request_timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Where to get the time, and where to store the `EndpointHealth`
+pub trait FailoverEnv {
+    fn now_ms() -> u64;
+    fn get_health(endpoint: &str) -> EndpointHealth;
+    fn set_health(endpoint: &str, health: EndpointHealth);
+}
+
+/// No health tracking: the endpoints are always tried in order.
+/// eg when the offchain API is not available(ie inside the enclave)
+impl FailoverEnv for () {
+    fn now_ms() -> u64 {
+        0
+    }
+
+    fn get_health(_endpoint: &str) -> EndpointHealth {
+        EndpointHealth::default()
+    }
+
+    fn set_health(_endpoint: &str, _health: EndpointHealth) {}
+}
+
+/// Health tracking in memory(ie per process); so it can be used from a dispatchable
+/// NOTE: NOT shared with the offchain worker, and lost on restart; that is fine for an optimisation.
+#[cfg(feature = "std")]
+pub struct InMemoryFailoverEnv;
+
+#[cfg(feature = "std")]
+static IN_MEMORY_HEALTH: std::sync::Mutex<alloc::collections::BTreeMap<String, EndpointHealth>> =
+    std::sync::Mutex::new(alloc::collections::BTreeMap::new());
+
+#[cfg(feature = "std")]
+impl InMemoryFailoverEnv {
+    fn with_health<R>(
+        f: impl FnOnce(&mut alloc::collections::BTreeMap<String, EndpointHealth>) -> R,
+    ) -> R {
+        // a panic while holding the lock can not leave the map in an invalid state
+        let mut health = IN_MEMORY_HEALTH
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut health)
+    }
+
+    /// Forget everything; eg between tests
+    pub fn reset() {
+        Self::with_health(|health| health.clear());
+    }
+}
+
+#[cfg(feature = "std")]
+impl FailoverEnv for InMemoryFailoverEnv {
+    fn now_ms() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    fn get_health(endpoint: &str) -> EndpointHealth {
+        Self::with_health(|health| health.get(endpoint).cloned().unwrap_or_default())
+    }
+
+    fn set_health(endpoint: &str, endpoint_health: EndpointHealth) {
+        Self::with_health(|health| health.insert(endpoint.into(), endpoint_health));
+    }
+}
+
+/// Health tracking in the offchain local storage(PERSISTENT)
+/// MUST only be used from an offchain context; NOT from a dispatchable(it would panic during block import)
+#[cfg(feature = "offchain")]
+pub struct OffchainFailoverEnv;
+
+#[cfg(feature = "offchain")]
+impl OffchainFailoverEnv {
+    const HEALTH_KEY_PREFIX: &'static [u8] = b"ipfs-failover::health::";
+
+    fn health_key(endpoint: &str) -> Vec<u8> {
+        let mut key = Self::HEALTH_KEY_PREFIX.to_vec();
+        key.extend_from_slice(endpoint.as_bytes());
+        key
+    }
+}
+
+#[cfg(feature = "offchain")]
+impl FailoverEnv for OffchainFailoverEnv {
+    fn now_ms() -> u64 {
+        sp_io::offchain::timestamp().unix_millis()
+    }
+
+    fn get_health(endpoint: &str) -> EndpointHealth {
+        sp_io::offchain::local_storage_get(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            &Self::health_key(endpoint),
+        )
+        .and_then(|encoded| EndpointHealth::decode(&mut encoded.as_slice()).ok())
+        .unwrap_or_default()
+    }
+
+    fn set_health(endpoint: &str, health: EndpointHealth) {
+        sp_io::offchain::local_storage_set(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            &Self::health_key(endpoint),
+            &health.encode(),
+        );
+    }
+}
+
+/// Parse a comma separated list of endpoints eg "http://127.0.0.1:5001,http://10.0.0.2:5001"
+/// The order is the priority.
+pub fn parse_endpoints(endpoints: &str) -> Vec<String> {
+    endpoints
+        .split(',')
+        .map(|endpoint| endpoint.trim())
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn is_healthy<Env: FailoverEnv>(endpoint: &str, config: &FailoverConfig) -> bool {
+    let health = Env::get_health(endpoint);
+    health.consecutive_failures < config.max_consecutive_failures
+        || Env::now_ms().saturating_sub(health.last_failure_ms) >= config.cooldown_ms
+}
+
+/// The order in which the endpoints will be tried: the healthy ones first(in priority order),
+/// then the ones considered down; as a last resort.
+pub fn order_endpoints<Env: FailoverEnv>(
+    endpoints: &[String],
+    config: &FailoverConfig,
+) -> Vec<String> {
+    let (mut healthy, down): (Vec<String>, Vec<String>) = endpoints
+        .iter()
+        .cloned()
+        .partition(|endpoint| is_healthy::<Env>(endpoint, config));
+    healthy.extend(down);
+    healthy
+}
+
+fn record_result<Env: FailoverEnv>(endpoint: &str, is_success: bool, now_ms: u64) {
+    let health = if is_success {
+        EndpointHealth::default()
+    } else {
+        let previous = Env::get_health(endpoint);
+        EndpointHealth {
+            consecutive_failures: previous.consecutive_failures.saturating_add(1),
+            last_failure_ms: now_ms,
+        }
+    };
+    Env::set_health(endpoint, health);
+}
+
+/// Call `f` on each endpoint(cf `order_endpoints`) until one succeeds in time.
+///
+/// `f` is given the endpoint, and the request timeout(cf `FailoverConfig::request_timeout_ms`)
+/// that it SHOULD set on its HTTP client. If the client has no deadline, a late answer is kept
+/// aside: it is only returned if none of the next endpoints succeeds in time.
+///
+/// return: the first success; or None if `endpoints` is empty, else the last error
+pub fn with_failover<Env, R, E, F>(
+    endpoints: &[String],
+    config: &FailoverConfig,
+    mut f: F,
+) -> Result<R, Option<E>>
+where
+    Env: FailoverEnv,
+    F: FnMut(&str, core::time::Duration) -> Result<R, E>,
+{
+    let timeout = core::time::Duration::from_millis(config.request_timeout_ms);
+    let mut late_result = None;
+    let mut last_error = None;
+    for endpoint in order_endpoints::<Env>(endpoints, config) {
+        let start_ms = Env::now_ms();
+        let result = f(&endpoint, timeout);
+        let end_ms = Env::now_ms();
+        let is_too_slow = end_ms.saturating_sub(start_ms) > config.request_timeout_ms;
+
+        record_result::<Env>(&endpoint, result.is_ok() && !is_too_slow, end_ms);
+
+        match result {
+            Ok(value) if !is_too_slow => return Ok(value),
+            Ok(value) => {
+                if late_result.is_none() {
+                    late_result = Some(value);
+                }
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    late_result.ok_or(last_error)
+}
@@ -1,11 +1,324 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "lib-garble-rs")]
+pub mod evaluator;
+pub mod ipfs_failover;
+pub mod seven_segments;
+
 use codec::{Decode, Encode};
 use frame_support::pallet_prelude::ConstU32;
+use frame_support::pallet_prelude::Get;
 use frame_support::pallet_prelude::MaxEncodedLen;
 use frame_support::BoundedVec;
 use frame_support::RuntimeDebug;
 
+/// One symbol per 7 segments glyph; cf `seven_segments::SEGMENTS_BY_SYMBOL`
+pub const MAX_ALPHABET_LEN: u32 = 16;
+/// Upper bound for `DisplayProfile::message_nb_digits`
+pub const MAX_MESSAGE_NB_DIGITS: u32 = 8;
+/// Upper bound for `PinpadLayout::nb_cells`: every symbol, plus the blank and "cancel" cells
+pub const MAX_PINPAD_NB_CELLS: u32 = MAX_ALPHABET_LEN + 2;
+
+pub type Alphabet = BoundedVec<u8, ConstU32<MAX_ALPHABET_LEN>>;
+
+/// The message circuits only draw ASCII; cf `MessageCapacity::glyphs`
+pub const MAX_MESSAGE_GLYPHS: u32 = 128;
+/// cf `MessageCapacity::default`
+pub const DEFAULT_MESSAGE_MAX_CHARS: u32 = 64;
+
+pub type MessageGlyphs = BoundedVec<u8, ConstU32<MAX_MESSAGE_GLYPHS>>;
+
+/// What the display circuits show: the OTP length, and the symbols it is made of.
+///
+/// The symbols are indexes in `seven_segments::SEGMENTS_BY_SYMBOL` ie 0-9 then "A b C d E F".
+/// The message shows `message_nb_digits` random symbols from `alphabet`; and the pinpad shows
+/// each of them exactly ONCE(shuffled) so the pinpad size is `alphabet.len()`.
+///
+/// IMPORTANT: "digits" everywhere else(ocw-garble, tx-validation) means "symbols", NOT only [0;9]
+#[derive(
+    Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, scale_info::TypeInfo, MaxEncodedLen,
+)]
+pub struct DisplayProfile {
+    pub alphabet: Alphabet,
+    pub message_nb_digits: u32,
+    pub pinpad_layout: PinpadLayout,
+}
+
+impl DisplayProfile {
+    /// [0;9]; the historical profile
+    pub fn decimal(message_nb_digits: u32) -> Self {
+        Self::new(0..10, message_nb_digits)
+    }
+
+    /// [0;9] + [A;F]
+    pub fn hexadecimal(message_nb_digits: u32) -> Self {
+        Self::new(0..MAX_ALPHABET_LEN as u8, message_nb_digits)
+    }
+
+    fn new(alphabet: core::ops::Range<u8>, message_nb_digits: u32) -> Self {
+        Self {
+            pinpad_layout: PinpadLayout::row(alphabet.len() as u32),
+            alphabet: BoundedVec::truncate_from(alphabet.collect()),
+            message_nb_digits,
+        }
+    }
+
+    /// eg `DisplayProfile::decimal(6).with_pinpad_layout(PinpadLayout::phone())`
+    pub fn with_pinpad_layout(self, pinpad_layout: PinpadLayout) -> Self {
+        Self {
+            pinpad_layout,
+            ..self
+        }
+    }
+
+    pub fn pinpad_nb_digits(&self) -> u32 {
+        self.alphabet.len() as u32
+    }
+
+    /// - at least 2 symbols, each with a glyph, no duplicates(else the pinpad would be ambiguous)
+    /// - [1;MAX_MESSAGE_NB_DIGITS] digits for the message
+    /// - one pinpad cell per symbol; cf `PinpadLayout::is_valid`
+    pub fn is_valid(&self) -> bool {
+        self.alphabet.len() >= 2
+            && self
+                .alphabet
+                .iter()
+                .all(|symbol| (*symbol as usize) < seven_segments::SEGMENTS_BY_SYMBOL.len())
+            && self
+                .alphabet
+                .iter()
+                .enumerate()
+                .all(|(idx, symbol)| !self.alphabet[..idx].contains(symbol))
+            && (1..=MAX_MESSAGE_NB_DIGITS).contains(&self.message_nb_digits)
+            && self.pinpad_layout.is_valid(self.pinpad_nb_digits())
+    }
+}
+
+/// 2 digits [0;9]; cf `DecimalDisplayProfile`
+impl Default for DisplayProfile {
+    fn default() -> Self {
+        Self::decimal(2)
+    }
+}
+
+/// eg `type DisplayProfile = DecimalDisplayProfile<6>;` for 6-digit codes
+pub struct DecimalDisplayProfile<const MESSAGE_NB_DIGITS: u32>;
+impl<const MESSAGE_NB_DIGITS: u32> Get<DisplayProfile>
+    for DecimalDisplayProfile<MESSAGE_NB_DIGITS>
+{
+    fn get() -> DisplayProfile {
+        DisplayProfile::decimal(MESSAGE_NB_DIGITS)
+    }
+}
+
+/// Same as `DecimalDisplayProfile` but [0;9] + [A;F], with a 16 cells pinpad
+pub struct HexadecimalDisplayProfile<const MESSAGE_NB_DIGITS: u32>;
+impl<const MESSAGE_NB_DIGITS: u32> Get<DisplayProfile>
+    for HexadecimalDisplayProfile<MESSAGE_NB_DIGITS>
+{
+    fn get() -> DisplayProfile {
+        DisplayProfile::hexadecimal(MESSAGE_NB_DIGITS)
+    }
+}
+
+/// What a pinpad cell shows; cf `PinpadLayout::cell`
+#[derive(Clone, Copy, Eq, PartialEq, RuntimeDebug)]
+pub enum PinpadCell {
+    /// the index of the digit in the pinpad ie the position to send to `check_input`
+    Digit(u32),
+    /// nothing drawn; taps SHOULD be ignored
+    Blank,
+    /// nothing drawn by the circuit; the wallet draws its own "cancel" button
+    Cancel,
+}
+
+/// How the pinpad cells are arranged: a grid of `nb_rows` x `nb_cols` cells.
+///
+/// The digits are placed in the cells row by row(left to right, top to bottom),
+/// skipping `blank_cell` and `cancel_cell`. Those are NOT drawn by the circuit.
+/// So the N-th digit cell is the N-th digit of the pinpad, ie what `check_input` calls its "position".
+///
+/// eg `phone()`:
+///   1 2 3
+///   4 5 6
+///   7 8 9
+///   _ 0 X
+/// with the digits(shuffled) in cells [0;8] and 10; 9 is blank and 11 is "cancel"
+#[derive(
+    Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, scale_info::TypeInfo, MaxEncodedLen,
+)]
+pub struct PinpadLayout {
+    pub nb_rows: u32,
+    pub nb_cols: u32,
+    /// the space b/w 2 cells; in thousandths of a cell, split on both sides of it
+    pub gutter_permille: u32,
+    /// index of the cell(row by row) left empty, if any
+    pub blank_cell: Option<u32>,
+    /// index of the cell(row by row) used as a "cancel" button, if any
+    pub cancel_cell: Option<u32>,
+}
+
+impl PinpadLayout {
+    /// 1 row, no gutter; the historical layout
+    pub fn row(nb_cells: u32) -> Self {
+        Self::grid(1, nb_cells)
+    }
+
+    /// eg `grid(4, 4)` for `DisplayProfile::hexadecimal`
+    pub fn grid(nb_rows: u32, nb_cols: u32) -> Self {
+        Self {
+            nb_rows,
+            nb_cols,
+            gutter_permille: 0,
+            blank_cell: None,
+            cancel_cell: None,
+        }
+    }
+
+    /// 4 rows x 3 cols like a phone keypad; for `DisplayProfile::decimal`
+    pub fn phone() -> Self {
+        Self {
+            gutter_permille: 100,
+            blank_cell: Some(9),
+            cancel_cell: Some(11),
+            ..Self::grid(4, 3)
+        }
+    }
+
+    pub fn nb_cells(&self) -> u32 {
+        self.nb_rows.saturating_mul(self.nb_cols)
+    }
+
+    fn special_cells(&self) -> impl Iterator<Item = u32> {
+        self.blank_cell.into_iter().chain(self.cancel_cell)
+    }
+
+    /// The number of cells showing a digit
+    pub fn nb_digit_cells(&self) -> u32 {
+        self.nb_cells()
+            .saturating_sub(self.special_cells().count() as u32)
+    }
+
+    /// - at most MAX_PINPAD_NB_CELLS cells; and each digit has its own cell, with no cell left over
+    /// - the blank and "cancel" cells are inside the grid, and NOT the same one
+    /// - the gutter leaves at least half of each cell for the digit
+    pub fn is_valid(&self, nb_digits: u32) -> bool {
+        self.nb_rows >= 1
+            && self.nb_cols >= 1
+            && self.nb_cells() <= MAX_PINPAD_NB_CELLS
+            && self.special_cells().all(|cell| cell < self.nb_cells())
+            && (self.blank_cell.is_none() || self.blank_cell != self.cancel_cell)
+            && self.nb_digit_cells() == nb_digits
+            && self.gutter_permille <= 500
+    }
+
+    /// Map a cell(eg where the user tapped) to what it shows; None if outside the grid
+    pub fn cell(&self, row: u32, col: u32) -> Option<PinpadCell> {
+        if row >= self.nb_rows || col >= self.nb_cols {
+            return None;
+        }
+        let cell_index = row * self.nb_cols + col;
+
+        if self.blank_cell == Some(cell_index) {
+            return Some(PinpadCell::Blank);
+        }
+        if self.cancel_cell == Some(cell_index) {
+            return Some(PinpadCell::Cancel);
+        }
+        let nb_special_cells_before = self
+            .special_cells()
+            .filter(|cell| *cell < cell_index)
+            .count() as u32;
+        Some(PinpadCell::Digit(cell_index - nb_special_cells_before))
+    }
+
+    /// The (row, col) of each digit cell; in pinpad order ie the reverse of `cell`
+    pub fn digit_cells(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (0..self.nb_cells())
+            .filter(|cell_index| !self.special_cells().any(|cell| cell == *cell_index))
+            .map(|cell_index| (cell_index / self.nb_cols, cell_index % self.nb_cols))
+    }
+}
+
+/// cf `DisplayProfile::default`
+impl Default for PinpadLayout {
+    fn default() -> Self {
+        Self::row(10)
+    }
+}
+
+/// What the "message" circuit can show besides the OTP ie the `tx_msg` given to the garbler
+/// Reported by the generator; cf `pallet_ocw_circuits::CircuitGenerator::message_capacity`
+///
+/// IMPORTANT: the garbler does NOT check it! A longer `tx_msg` would be truncated on screen,
+/// and an unsupported character fails deep in the garbler; cf `check`
+#[derive(
+    Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, scale_info::TypeInfo, MaxEncodedLen,
+)]
+pub struct MessageCapacity {
+    /// in characters, NOT bytes
+    pub max_chars: u32,
+    /// the ASCII characters that have a glyph
+    pub glyphs: MessageGlyphs,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, RuntimeDebug)]
+pub enum MessageCapacityError {
+    TooLong,
+    UnsupportedCharacter,
+}
+
+impl MessageCapacity {
+    /// The `tx_msg` is drawn on ONE line, across the whole message circuit
+    /// param: width: of the message circuit, in pixels
+    /// param: glyph_width: of one character, in pixels
+    pub fn for_width(width: u32, glyph_width: u32, glyphs: MessageGlyphs) -> Self {
+        Self {
+            max_chars: width.checked_div(glyph_width).unwrap_or(0),
+            glyphs,
+        }
+    }
+
+    /// [' ';'~']
+    pub fn printable_ascii() -> MessageGlyphs {
+        BoundedVec::truncate_from((b' '..=b'~').collect())
+    }
+
+    /// A capacity that can NOT show anything is a generator bug; cf `pallet_ocw_circuits`
+    pub fn is_valid(&self) -> bool {
+        self.max_chars > 0 && !self.glyphs.is_empty() && self.glyphs.iter().all(u8::is_ascii)
+    }
+
+    pub fn check(&self, tx_msg: &str) -> Result<(), MessageCapacityError> {
+        if tx_msg.chars().count() > self.max_chars as usize {
+            return Err(MessageCapacityError::TooLong);
+        }
+        if !tx_msg
+            .chars()
+            .all(|c| c.is_ascii() && self.glyphs.contains(&(c as u8)))
+        {
+            return Err(MessageCapacityError::UnsupportedCharacter);
+        }
+
+        Ok(())
+    }
+}
+
+/// Printable ASCII ie [' ';'~'], and DEFAULT_MESSAGE_MAX_CHARS
+/// ONLY for the packages generated before it was recorded(cf `pallet_ocw_circuits::migrations::v4`);
+/// the generators MUST report the real one. It is conservative: eg a `TxIntent` with a big amount
+/// and a full memo does NOT fit.
+impl Default for MessageCapacity {
+    fn default() -> Self {
+        Self {
+            max_chars: DEFAULT_MESSAGE_MAX_CHARS,
+            glyphs: Self::printable_ascii(),
+        }
+    }
+}
+
 /// Easy way to make a link b/w a "message" and "pinpad" circuits
 /// that way we can have ONE extrinsic that generates both in one call
 ///
@@ -21,6 +334,12 @@ pub struct DisplayStrippedCircuitsPackage {
     /// needed for UI/UX purposes
     /// Used in repo `wallet-app`; DO NOT remove "pub"!
     pub message_nb_digits: u32,
+    /// the number of cells of the pinpad; cf `DisplayProfile::pinpad_nb_digits`
+    /// Used in repo `wallet-app`(texture atlas); DO NOT remove "pub"!
+    pub pinpad_nb_digits: u32,
+    /// where each pinpad digit is; to map a tap to the position expected by `check_input`
+    /// Used in repo `wallet-app`; DO NOT remove "pub"!
+    pub pinpad_layout: PinpadLayout,
 }
 
 /// Easy way to make a link b/w a "message" and "pinpad" circuits
@@ -35,4 +354,21 @@ pub struct DisplaySkcdPackage {
     pub message_skcd_server_metadata_nb_digits: u32,
     pub pinpad_skcd_cid: BoundedVec<u8, ConstU32<64>>,
     pub pinpad_skcd_server_metadata_nb_digits: u32,
+    /// the symbols the circuits were generated for; cf `DisplayProfile`
+    /// NOTE: `pinpad_skcd_server_metadata_nb_digits == alphabet.len()`
+    pub alphabet: Alphabet,
+    /// the pinpad circuit was generated with this layout; cf `DisplayProfile`
+    pub pinpad_layout: PinpadLayout,
+    /// what the message circuit can show; the `tx_msg` MUST be checked against it before garbling
+    pub message_capacity: MessageCapacity,
+}
+
+impl DisplaySkcdPackage {
+    pub fn display_profile(&self) -> DisplayProfile {
+        DisplayProfile {
+            alphabet: self.alphabet.clone(),
+            message_nb_digits: self.message_skcd_server_metadata_nb_digits,
+            pinpad_layout: self.pinpad_layout.clone(),
+        }
+    }
 }
@@ -0,0 +1,140 @@
+//! Read back the digits shown by a display circuit(ie the evaluator outputs).
+//! Used by the self-check in `pallet-ocw-garble`; it mirrors what the phone displays.
+//!
+//! Each digit is drawn in its bbox with 7 segments:
+//!    _a_
+//!  f|   |b
+//!   |_g_|
+//!  e|   |c
+//!   |_d_|
+//!
+//! NOTE: we only sample a small window around the center of each segment; so this is NOT a
+//! general purpose OCR, it only works with the bboxes used to generate the circuits.
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The evaluator outputs: row-major, one byte per pixel, 0 = OFF
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// A pixel is ON if it was ON in ANY of the frames
+    /// The phone does the same: each frame only shows some of the segments(cf the evaluator inputs)
+    pub fn merge(&mut self, other_pixels: &[u8]) {
+        for (pixel, other) in self.pixels.iter_mut().zip(other_pixels) {
+            *pixel |= *other;
+        }
+    }
+}
+
+/// Segments ON for each symbol; bit 0 = a, ..., bit 6 = g
+/// The "digits" of the display circuits are indexes in this table, cf `crate::DisplayProfile::alphabet`
+pub const SEGMENTS_BY_SYMBOL: [u8; 16] = [
+    0b011_1111, // 0
+    0b000_0110, // 1
+    0b101_1011, // 2
+    0b100_1111, // 3
+    0b110_0110, // 4
+    0b110_1101, // 5
+    0b111_1101, // 6
+    0b000_0111, // 7
+    0b111_1111, // 8
+    0b110_1111, // 9
+    0b111_0111, // A
+    0b111_1100, // b
+    0b011_1001, // C
+    0b101_1110, // d
+    0b111_1001, // E
+    0b111_0001, // F
+];
+
+/// Center of each segment(a..g) relative to the digit bbox
+const SEGMENTS_CENTERS: [(f32, f32); 7] = [
+    (0.5, 0.06),
+    (0.88, 0.28),
+    (0.88, 0.72),
+    (0.5, 0.94),
+    (0.12, 0.72),
+    (0.12, 0.28),
+    (0.5, 0.5),
+];
+
+/// Half size of the window sampled around a segment center; relative to the digit bbox
+const SAMPLE_HALF_SIZE: f32 = 0.04;
+
+/// Pixels(x_min, y_min, x_max, y_max) sampled for `segment` in `bbox`; bbox in [0,1] ie same as `digits_bboxes`
+fn segment_window(frame: &Frame, bbox: &[f32], segment: usize) -> (u32, u32, u32, u32) {
+    let (center_x, center_y) = SEGMENTS_CENTERS[segment];
+    let to_pixels = |relative: f32, min: f32, max: f32, size: u32| -> u32 {
+        let value = (min + relative * (max - min)) * size as f32;
+        (value.max(0.0) as u32).min(size.saturating_sub(1))
+    };
+
+    (
+        to_pixels(center_x - SAMPLE_HALF_SIZE, bbox[0], bbox[2], frame.width),
+        to_pixels(center_y - SAMPLE_HALF_SIZE, bbox[1], bbox[3], frame.height),
+        to_pixels(center_x + SAMPLE_HALF_SIZE, bbox[0], bbox[2], frame.width),
+        to_pixels(center_y + SAMPLE_HALF_SIZE, bbox[1], bbox[3], frame.height),
+    )
+}
+
+/// Bitmask of the segments ON in `bbox`; cf `SEGMENTS_BY_SYMBOL`
+pub fn read_segments(frame: &Frame, bbox: &[f32]) -> u8 {
+    let mut segments = 0;
+    for segment in 0..SEGMENTS_CENTERS.len() {
+        let (x_min, y_min, x_max, y_max) = segment_window(frame, bbox, segment);
+        let is_on = (y_min..=y_max).any(|y| {
+            (x_min..=x_max).any(|x| frame.pixels[(y * frame.width + x) as usize] != 0)
+        });
+        if is_on {
+            segments |= 1 << segment;
+        }
+    }
+
+    segments
+}
+
+/// return: the symbol(cf `SEGMENTS_BY_SYMBOL`) shown in each bbox; None if the segments do not match any
+///
+/// param: digits_bboxes: one bbox(4 floats) per digit; same as `pallet_ocw_circuits::display_layout`
+pub fn read_digits(frame: &Frame, digits_bboxes: &[f32]) -> Vec<Option<u8>> {
+    digits_bboxes
+        .chunks_exact(4)
+        .map(|bbox| {
+            let segments = read_segments(frame, bbox);
+            SEGMENTS_BY_SYMBOL
+                .iter()
+                .position(|digit_segments| *digit_segments == segments)
+                .map(|digit| digit as u8)
+        })
+        .collect()
+}
+
+/// The reverse of `read_digits`: light the sampled windows of `digit`
+/// TEST ONLY: a fake evaluator; the real frames come from the garbled circuits
+pub fn draw_digit(frame: &mut Frame, bbox: &[f32], digit: u8) {
+    let segments = SEGMENTS_BY_SYMBOL[digit as usize % SEGMENTS_BY_SYMBOL.len()];
+    for segment in 0..SEGMENTS_CENTERS.len() {
+        if segments & (1 << segment) == 0 {
+            continue;
+        }
+        let (x_min, y_min, x_max, y_max) = segment_window(frame, bbox, segment);
+        for y in y_min..=y_max {
+            for x in x_min..=x_max {
+                frame.pixels[(y * frame.width + x) as usize] = 1;
+            }
+        }
+    }
+}
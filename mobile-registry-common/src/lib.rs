@@ -0,0 +1,15 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+/// Check the signatures made by a registered mobile;
+/// implemented by pallet-mobile-registry, and used by pallet-tx-validation for `check_input_unsigned`
+pub trait MobileKeyVerifier<AccountId> {
+    /// return: true iff `signature` of `message` was made by the mobile registered for `who`
+    fn verify(who: &AccountId, message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// No mobile registry: every signature is rejected
+impl<AccountId> MobileKeyVerifier<AccountId> for () {
+    fn verify(_who: &AccountId, _message: &[u8], _signature: &[u8]) -> bool {
+        false
+    }
+}
@@ -14,6 +14,8 @@ mod tests;
 #[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
 
+pub mod migrations;
+
 #[frame_support::pallet]
 pub mod pallet {
     use frame_support::pallet_prelude::*;
@@ -62,8 +64,9 @@ pub mod pallet {
     >;
 
     /// The current storage version.
-    const STORAGE_VERSION: frame_support::traits::StorageVersion =
-        frame_support::traits::StorageVersion::new(1);
+    /// v2: only keys `verify_signature` can check are registered; cf `migrations::v2`
+    pub(crate) const STORAGE_VERSION: frame_support::traits::StorageVersion =
+        frame_support::traits::StorageVersion::new(2);
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
@@ -84,14 +87,117 @@ pub mod pallet {
         /// register_mobile: pub_key SHOULD be at least 32 bytes(for ECC)
         // TODO support 2048(for RSA)?
         InvalidKeySize,
+        /// register_mobile: the signatures made with this pub_key could NOT be checked;
+        /// cf `verify_signature` for the supported formats
+        UnsupportedKeyFormat,
+    }
+
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_runtime_upgrade() -> Weight {
+            crate::migrations::v2::migrate::<T>()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn pre_upgrade() -> Result<Vec<u8>, &'static str> {
+            crate::migrations::v2::pre_upgrade::<T>()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn post_upgrade(state: Vec<u8>) -> Result<(), &'static str> {
+            crate::migrations::v2::post_upgrade::<T>(state)
+        }
     }
 
     impl<T: Config> Pallet<T> {
-        /// Check the pub_key is at least 32 bytes in length
+        /// Check the pub_key is at least 32 bytes in length, and in a format `verify_signature` supports
         pub fn ensure_pub_key_valid(pub_key: &[u8]) -> Result<(), Error<T>> {
             match pub_key.len() {
-                32.. => Ok(()),
-                _ => Err(Error::<T>::InvalidKeySize),
+                0..=31 => Err(Error::<T>::InvalidKeySize),
+                _ if is_pub_key_supported(pub_key) => Ok(()),
+                _ => Err(Error::<T>::UnsupportedKeyFormat),
+            }
+        }
+    }
+
+    /// ie a key `verify_signature` can check the signatures of; every format is parsed:
+    /// a key which is NOT a valid point(or the identity) is NOT supported
+    pub(crate) fn is_pub_key_supported(pub_key: &[u8]) -> bool {
+        match pub_key.len() {
+            32 => sr25519_public_key(pub_key).is_some(),
+            33 => k256::PublicKey::from_sec1_bytes(pub_key).is_ok(),
+            _ => p256_verifying_key(pub_key).is_some(),
+        }
+    }
+
+    /// Check a signature made by a registered mobile
+    ///
+    /// Supported keys:
+    /// - 32 bytes: raw sr25519
+    /// - 33 bytes: raw compressed ecdsa(secp256k1); the message is hashed with blake2_256
+    /// - else: DER SubjectPublicKeyInfo P-256(ie what Android KeyStore exports, cf tests);
+    ///   the signature is "SHA256withECDSA", either DER(Android) or raw r|s(64 bytes)
+    pub fn verify_signature(pub_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        match pub_key.len() {
+            32 => match (
+                sp_core::sr25519::Public::try_from(pub_key),
+                sp_core::sr25519::Signature::try_from(signature),
+            ) {
+                (Ok(pub_key), Ok(signature)) => {
+                    sp_io::crypto::sr25519_verify(&signature, message, &pub_key)
+                }
+                _ => false,
+            },
+            33 => match (
+                sp_core::ecdsa::Public::try_from(pub_key),
+                sp_core::ecdsa::Signature::try_from(signature),
+            ) {
+                (Ok(pub_key), Ok(signature)) => {
+                    sp_io::crypto::ecdsa_verify(&signature, message, &pub_key)
+                }
+                _ => false,
+            },
+            _ => match p256_verifying_key(pub_key) {
+                Some(verifying_key) => {
+                    use p256::ecdsa::signature::Verifier;
+
+                    let signature = match p256::ecdsa::Signature::from_der(signature) {
+                        Ok(signature) => Ok(signature),
+                        Err(_) => p256::ecdsa::Signature::from_slice(signature),
+                    };
+                    match signature {
+                        Ok(signature) => verifying_key.verify(message, &signature).is_ok(),
+                        Err(_) => false,
+                    }
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Parse a raw sr25519 key; None if it is not a valid ristretto point
+    /// NOTE: the identity(all zeroes) is rejected: anyone could forge a signature for it
+    fn sr25519_public_key(pub_key: &[u8]) -> Option<schnorrkel::PublicKey> {
+        if pub_key.iter().all(|byte| *byte == 0) {
+            return None;
+        }
+
+        schnorrkel::PublicKey::from_bytes(pub_key).ok()
+    }
+
+    /// Parse a DER SubjectPublicKeyInfo P-256; None if it is not one
+    fn p256_verifying_key(pub_key: &[u8]) -> Option<p256::ecdsa::VerifyingKey> {
+        use p256::pkcs8::DecodePublicKey;
+
+        p256::ecdsa::VerifyingKey::from_public_key_der(pub_key).ok()
+    }
+
+    /// Used by pallet-tx-validation for `check_input_unsigned`
+    impl<T: Config> mobile_registry_common::MobileKeyVerifier<T::AccountId> for Pallet<T> {
+        fn verify(who: &T::AccountId, message: &[u8], signature: &[u8]) -> bool {
+            match <MobileRegistryMap<T>>::get(who) {
+                Some(mobile) => verify_signature(&mobile.pub_key, message, signature),
+                None => false,
             }
         }
     }
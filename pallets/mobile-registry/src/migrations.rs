@@ -0,0 +1,85 @@
+//! Storage migrations; called from `Hooks::on_runtime_upgrade`
+
+/// v1 -> v2: `register_mobile` used to accept any key of at least 32 bytes; the keys
+/// `verify_signature` can NOT check(ie `is_pub_key_supported`) are removed.
+/// The accounts concerned MUST call `register_mobile` again; cf CHANGELOG.md
+pub mod v2 {
+    use crate::pallet::*;
+    use frame_support::pallet_prelude::*;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+    #[cfg(feature = "try-runtime")]
+    use sp_std::vec::Vec;
+
+    /// NOTE: the value is unchanged, so `translate_values` is only used to remove the entries
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 2 {
+            log::info!(
+                "[mobile-registry] migration v2: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let mut nb_entries: u64 = 0;
+        let mut nb_removed: u64 = 0;
+        <MobileRegistryMap<T>>::translate_values::<MobilePackage, _>(|mobile| {
+            nb_entries += 1;
+            if is_pub_key_supported(&mobile.pub_key) {
+                Some(mobile)
+            } else {
+                nb_removed += 1;
+                None
+            }
+        });
+        StorageVersion::new(2).put::<Pallet<T>>();
+
+        log::info!(
+            "[mobile-registry] migration v2: {} keys kept, {} removed",
+            nb_entries - nb_removed,
+            nb_removed
+        );
+
+        T::DbWeight::get().reads_writes(1 + nb_entries, 1 + nb_removed)
+    }
+
+    /// return: the number of entries that SHOULD be left after the migration; None if skipped
+    #[cfg(feature = "try-runtime")]
+    pub fn pre_upgrade<T: Config>() -> Result<Vec<u8>, &'static str> {
+        let expected_nb_entries: Option<u64> = if Pallet::<T>::on_chain_storage_version() < 2 {
+            Some(
+                <MobileRegistryMap<T>>::iter_values()
+                    .filter(|mobile| is_pub_key_supported(&mobile.pub_key))
+                    .count() as u64,
+            )
+        } else {
+            None
+        };
+
+        Ok(expected_nb_entries.encode())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    pub fn post_upgrade<T: Config>(state: Vec<u8>) -> Result<(), &'static str> {
+        ensure!(
+            Pallet::<T>::on_chain_storage_version() >= 2,
+            "[mobile-registry] migration v2: wrong on-chain version"
+        );
+
+        let expected_nb_entries: Option<u64> =
+            Decode::decode(&mut state.as_slice()).map_err(|_err| "invalid pre_upgrade state")?;
+        if let Some(expected_nb_entries) = expected_nb_entries {
+            ensure!(
+                <MobileRegistryMap<T>>::iter_values().count() as u64 == expected_nb_entries,
+                "[mobile-registry] migration v2: some unsupported keys were not removed"
+            );
+            ensure!(
+                <MobileRegistryMap<T>>::iter_values()
+                    .all(|mobile| is_pub_key_supported(&mobile.pub_key)),
+                "[mobile-registry] migration v2: an unsupported key is still registered"
+            );
+        }
+
+        Ok(())
+    }
+}
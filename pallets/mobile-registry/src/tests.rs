@@ -17,7 +17,42 @@ fn test_register_mobile_ok(pub_key: Vec<u8>) {
 
 #[test]
 fn test_register_mobile_basic_ok() {
-    test_register_mobile_ok(vec![0; 32])
+    use sp_core::Pair;
+
+    let pair = sp_core::sr25519::Pair::from_seed(&[42; 32]);
+    test_register_mobile_ok(pair.public().0.to_vec())
+}
+
+#[test]
+fn test_register_mobile_ecdsa_ok() {
+    use sp_core::Pair;
+
+    let pair = sp_core::ecdsa::Pair::from_seed(&[42; 32]);
+    test_register_mobile_ok(pair.public().0.to_vec())
+}
+
+/// The raw keys MUST be valid points; else NO signature could ever be checked
+#[test]
+fn test_register_mobile_invalid_point_err() {
+    new_test_ext().execute_with(|| {
+        let account_id = 1;
+
+        for pub_key in [
+            // sr25519: the identity
+            vec![0; 32],
+            // sr25519: NOT a canonical encoding
+            vec![0xff; 32],
+            // ecdsa: x >= p
+            [vec![2], vec![0xff; 32]].concat(),
+            // ecdsa: NOT a compressed point
+            vec![5; 33],
+        ] {
+            assert_noop!(
+                MobileRegistry::register_mobile(RuntimeOrigin::signed(account_id), pub_key),
+                Error::<Test>::UnsupportedKeyFormat
+            );
+        }
+    });
 }
 
 /// Android is apparently using a serialization(PKCS8?)
@@ -45,3 +80,138 @@ fn test_register_mobile_pub_key_too_small_err() {
         );
     });
 }
+
+/// cf pallet-tx-validation `check_input_unsigned`
+#[test]
+fn test_mobile_key_verifier_sr25519() {
+    use mobile_registry_common::MobileKeyVerifier;
+    use sp_core::Pair;
+
+    new_test_ext().execute_with(|| {
+        let account_id = 1;
+        let pair = sp_core::sr25519::Pair::from_seed(&[42; 32]);
+        assert_ok!(MobileRegistry::register_mobile(
+            RuntimeOrigin::signed(account_id),
+            pair.public().0.to_vec()
+        ));
+        let signature = pair.sign(b"message").0;
+
+        assert!(MobileRegistry::verify(&account_id, b"message", &signature));
+        assert!(!MobileRegistry::verify(&account_id, b"other message", &signature));
+        // no mobile registered for this account
+        assert!(!MobileRegistry::verify(&2, b"message", &signature));
+    });
+}
+
+#[test]
+fn test_register_mobile_unsupported_key_format_err() {
+    new_test_ext().execute_with(|| {
+        let account_id = 1;
+
+        // right size for a SubjectPublicKeyInfo P-256, but garbage
+        assert_noop!(
+            MobileRegistry::register_mobile(RuntimeOrigin::signed(account_id), vec![0; 91]),
+            Error::<Test>::UnsupportedKeyFormat
+        );
+    });
+}
+
+/// SubjectPublicKeyInfo P-256 with its "SHA256withECDSA" signature of b"message"
+/// (generated with the p256 crate; same format as Android KeyStore)
+const P256_PUB_KEY_DER: [u8; 91] = [
+    48, 89, 48, 19, 6, 7, 42, 134, 72, 206, 61, 2, 1, 6, 8, 42, 134, 72, 206, 61, 3, 1, 7, 3, 66,
+    0, 4, 12, 144, 29, 66, 60, 131, 28, 168, 94, 39, 199, 60, 38, 59, 161, 50, 114, 27, 185, 215,
+    168, 76, 79, 3, 128, 178, 166, 117, 111, 214, 1, 51, 28, 136, 112, 35, 77, 236, 135, 133, 4,
+    193, 116, 20, 79, 164, 177, 75, 102, 166, 81, 105, 22, 6, 216, 23, 62, 85, 189, 55, 227, 129,
+    86, 158,
+];
+const P256_SIGNATURE_DER: [u8; 71] = [
+    48, 69, 2, 33, 0, 155, 144, 139, 7, 246, 68, 12, 132, 254, 181, 75, 8, 233, 34, 61, 185, 178,
+    227, 158, 81, 175, 14, 71, 183, 104, 52, 20, 246, 247, 188, 204, 237, 2, 32, 55, 255, 19, 235,
+    82, 20, 2, 104, 126, 229, 178, 162, 147, 171, 211, 117, 36, 41, 235, 21, 19, 194, 125, 127, 95,
+    213, 185, 171, 50, 98, 227, 63,
+];
+/// the same signature as `P256_SIGNATURE_DER`, as raw r|s
+const P256_SIGNATURE_RAW: [u8; 64] = [
+    155, 144, 139, 7, 246, 68, 12, 132, 254, 181, 75, 8, 233, 34, 61, 185, 178, 227, 158, 81, 175,
+    14, 71, 183, 104, 52, 20, 246, 247, 188, 204, 237, 55, 255, 19, 235, 82, 20, 2, 104, 126, 229,
+    178, 162, 147, 171, 211, 117, 36, 41, 235, 21, 19, 194, 125, 127, 95, 213, 185, 171, 50, 98,
+    227, 63,
+];
+
+/// cf pallet-tx-validation `check_input_unsigned`
+#[test]
+fn test_mobile_key_verifier_android_p256() {
+    use mobile_registry_common::MobileKeyVerifier;
+
+    new_test_ext().execute_with(|| {
+        let account_id = 1;
+        assert_ok!(MobileRegistry::register_mobile(
+            RuntimeOrigin::signed(account_id),
+            P256_PUB_KEY_DER.to_vec()
+        ));
+
+        assert!(MobileRegistry::verify(
+            &account_id,
+            b"message",
+            &P256_SIGNATURE_DER
+        ));
+        assert!(MobileRegistry::verify(
+            &account_id,
+            b"message",
+            &P256_SIGNATURE_RAW
+        ));
+        assert!(!MobileRegistry::verify(
+            &account_id,
+            b"other message",
+            &P256_SIGNATURE_DER
+        ));
+    });
+}
+
+/// Unsupported key format MUST NOT panic
+#[test]
+fn test_mobile_key_verifier_garbage_key() {
+    assert!(!crate::verify_signature(&[0; 91], b"message", &[0; 64]));
+    assert!(!crate::verify_signature(
+        &P256_PUB_KEY_DER,
+        b"message",
+        &[0; 3]
+    ));
+}
+
+/// v1 accepted any key of at least 32 bytes; only the supported ones are kept
+#[test]
+fn test_migration_v2_removes_unsupported_keys() {
+    use crate::pallet::{MobilePackage, MobileRegistryMap};
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    new_test_ext().execute_with(|| {
+        StorageVersion::new(1).put::<MobileRegistry>();
+        MobileRegistryMap::<Test>::insert(
+            1,
+            MobilePackage {
+                pub_key: P256_PUB_KEY_DER.to_vec().try_into().unwrap(),
+            },
+        );
+        MobileRegistryMap::<Test>::insert(
+            2,
+            MobilePackage {
+                pub_key: vec![0; 32].try_into().unwrap(),
+            },
+        );
+        MobileRegistryMap::<Test>::insert(
+            3,
+            MobilePackage {
+                pub_key: vec![42; 64].try_into().unwrap(),
+            },
+        );
+
+        crate::migrations::v2::migrate::<Test>();
+
+        assert_eq!(MobileRegistry::on_chain_storage_version(), 2);
+        assert!(MobileRegistry::circuit_server_metadata_map(1).is_some());
+        assert!(MobileRegistry::circuit_server_metadata_map(2).is_none());
+        assert!(MobileRegistry::circuit_server_metadata_map(3).is_none());
+    });
+}
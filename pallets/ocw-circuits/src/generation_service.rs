@@ -0,0 +1,202 @@
+//! Client for an external circuit-generation service(the successor of "api_circuits")
+//! HTTP/JSON; the service generates the circuit, uploads the .skcd to IPFS and returns the CID:
+//! - POST {url}/generic `{"verilog_cid": "Qm..."}` -> `{"skcd_cid": "Qm..."}`
+//! - POST {url}/display `{"width": 590, "height": 50, "digits_bboxes": [...], "alphabet": [0, 1, ...]}` -> `{"skcd_cid": "Qm...", "nb_digits": 10}`
+//!   "alphabet": the symbols to draw, cf `circuits_storage_common::DisplayProfile`
+//!   REQUIRED in the response for the message: `"max_chars": 32, "glyphs": "0123456789abcdef #x"`; cf `circuits_storage_common::MessageCapacity`
+//!
+//! The HTTP client is a type param: the offchain worker uses `ClientSpOffchain`, the tests `ClientHttpReq`(real requests to httpmock).
+
+use crate::{display_layout_for_profile, Config, Error, GrpcCallKind, LibCircuitsRsResultKind};
+use circuits_storage_common::{DisplayProfile, MessageCapacity};
+use alloc::format;
+use alloc::string::String;
+use interstellar_http_client::SendRequest;
+use serde::{Deserialize, Serialize};
+use sp_std::vec::Vec;
+
+/// Offchain local storage(PERSISTENT) key for the service root url; cf `get_generation_service_url`
+pub const GENERATION_SERVICE_URL_LOCAL_STORAGE_KEY: &[u8] =
+    b"ocw-circuits::config::generation_service_url";
+
+/// Generating the display circuits takes a few minutes...
+/// NOTE: `LOCK_TIMEOUT_EXPIRATION` is sized from it
+pub(crate) const GENERATION_SERVICE_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+
+#[derive(Serialize)]
+struct GenericRequest<'a> {
+    verilog_cid: &'a str,
+}
+
+#[derive(Serialize)]
+struct DisplayRequest<'a> {
+    width: u32,
+    height: u32,
+    digits_bboxes: &'a [f32],
+    alphabet: &'a [u8],
+}
+
+#[derive(Deserialize)]
+struct GenericResponse {
+    skcd_cid: String,
+}
+
+#[derive(Deserialize)]
+struct DisplayResponse {
+    skcd_cid: String,
+    nb_digits: u32,
+    /// cf `MessageCapacity`; only for the message, so missing for the pinpad
+    #[serde(default)]
+    max_chars: Option<u32>,
+    #[serde(default)]
+    glyphs: Option<String>,
+}
+
+/// The service root url eg "http://127.0.0.1:3000", in priority order:
+/// - offchain local storage(PERSISTENT) at GENERATION_SERVICE_URL_LOCAL_STORAGE_KEY; raw utf8 url
+/// - `Config::GenerationServiceUrl`
+///
+/// None => the service is NOT used; cf `crate::generate`
+pub(crate) fn get_generation_service_url<T: Config>() -> Option<String> {
+    sp_io::offchain::local_storage_get(
+        sp_core::offchain::StorageKind::PERSISTENT,
+        GENERATION_SERVICE_URL_LOCAL_STORAGE_KEY,
+    )
+    .filter(|value| !value.is_empty())
+    .or_else(|| T::GenerationServiceUrl::get().filter(|value| !value.is_empty()))
+    .and_then(|value| {
+        String::from_utf8(value)
+            .map_err(|_err| log::error!("[ocw-circuits] generation service url is not valid utf8!"))
+            .ok()
+    })
+}
+
+/// Same results as `call_grpc_generic`/`call_grpc_display` but from the service
+pub(crate) fn call_generation_service<T: Config, C: SendRequest>(
+    url: &str,
+    grpc_kind: GrpcCallKind,
+) -> Result<LibCircuitsRsResultKind, Error<T>> {
+    match grpc_kind {
+        GrpcCallKind::Generic { verilog_cid } => {
+            let verilog_cid =
+                sp_std::str::from_utf8(&verilog_cid).map_err(|_err| <Error<T>>::DeserializeError)?;
+            let response: GenericResponse = post_json::<T, C, _, _>(
+                &format!("{url}/generic"),
+                &GenericRequest { verilog_cid },
+            )?;
+
+            Ok(LibCircuitsRsResultKind::Generic {
+                ipfs_hash: response.skcd_cid,
+            })
+        }
+        GrpcCallKind::Display { profile } => {
+            let message_response =
+                call_generation_service_display_one::<T, C>(url, &profile, true)?;
+            let message_capacity = message_capacity::<T>(&message_response)?;
+            let pinpad_response =
+                call_generation_service_display_one::<T, C>(url, &profile, false)?;
+
+            Ok(LibCircuitsRsResultKind::Display {
+                message_ipfs_hash: message_response.skcd_cid,
+                message_nb_digits: message_response.nb_digits,
+                pinpad_ipfs_hash: pinpad_response.skcd_cid,
+                pinpad_nb_digits: pinpad_response.nb_digits,
+                alphabet: profile.alphabet.into_inner(),
+                pinpad_layout: profile.pinpad_layout,
+                message_capacity,
+            })
+        }
+    }
+}
+
+fn call_generation_service_display_one<T: Config, C: SendRequest>(
+    url: &str,
+    profile: &DisplayProfile,
+    is_message: bool,
+) -> Result<DisplayResponse, Error<T>> {
+    let (width, height, digits_bboxes) = display_layout_for_profile(profile, is_message);
+    let response: DisplayResponse = post_json::<T, C, _, _>(
+        &format!("{url}/display"),
+        &DisplayRequest {
+            width,
+            height,
+            digits_bboxes: &digits_bboxes,
+            alphabet: &profile.alphabet,
+        },
+    )?;
+
+    // CHECK: the service MUST have used our layout; cf `call_grpc_display_one`
+    // else pallet-ocw-garble would generate the wrong number of random digits
+    if response.nb_digits as usize != digits_bboxes.len() / 4 {
+        log::error!(
+            "[ocw-circuits] generation service: nb_digits mismatch: {} vs {}",
+            response.nb_digits,
+            digits_bboxes.len() / 4
+        );
+        return Err(<Error<T>>::DeserializeError);
+    }
+
+    Ok(response)
+}
+
+/// Both MUST be reported: there is no sensible default, it depends on the circuit
+/// The glyphs MUST be ASCII; cf `MessageCapacity::is_valid`
+fn message_capacity<T: Config>(response: &DisplayResponse) -> Result<MessageCapacity, Error<T>> {
+    let (max_chars, glyphs) = match (response.max_chars, &response.glyphs) {
+        (Some(max_chars), Some(glyphs)) => (max_chars, glyphs),
+        _ => {
+            log::error!("[ocw-circuits] generation service: no max_chars/glyphs for the message!");
+            return Err(<Error<T>>::InvalidMessageCapacity);
+        }
+    };
+    let message_capacity = MessageCapacity {
+        max_chars,
+        glyphs: glyphs
+            .as_bytes()
+            .to_vec()
+            .try_into()
+            .map_err(|_err| <Error<T>>::InvalidMessageCapacity)?,
+    };
+
+    if !message_capacity.is_valid() {
+        log::error!(
+            "[ocw-circuits] generation service: invalid message capacity: {} {}",
+            max_chars,
+            glyphs
+        );
+        return Err(<Error<T>>::InvalidMessageCapacity);
+    }
+
+    Ok(message_capacity)
+}
+
+fn post_json<T: Config, C: SendRequest, Req: Serialize, Resp: for<'de> Deserialize<'de>>(
+    uri: &str,
+    request: &Req,
+) -> Result<Resp, Error<T>> {
+    let body: Vec<u8> = serde_json::to_vec(request).map_err(|err| {
+        log::error!("[ocw-circuits] generation service encode error: {:?}", err);
+        <Error<T>>::SerializeError
+    })?;
+
+    let (resp_bytes, _resp_content_type) = C::send_request(
+        Some(bytes::Bytes::from(body)),
+        uri,
+        &interstellar_http_client::MyRequestMethod::Post,
+        Some(&interstellar_http_client::MyContentType::Json),
+        core::time::Duration::from_millis(GENERATION_SERVICE_TIMEOUT_MS),
+    )
+    .map_err(|err| {
+        log::error!(
+            "[ocw-circuits] generation service error: {:?} [{:?}]",
+            err,
+            uri
+        );
+        <Error<T>>::HttpFetchingError
+    })?;
+
+    serde_json::from_slice(&resp_bytes).map_err(|err| {
+        log::error!("[ocw-circuits] generation service decode error: {:?}", err);
+        <Error<T>>::DeserializeError
+    })
+}
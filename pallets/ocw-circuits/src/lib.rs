@@ -6,57 +6,156 @@ mod mock;
 #[cfg(test)]
 mod tests;
 
+mod generation_service;
+pub mod migrations;
+
 extern crate alloc;
 
 use frame_system::offchain::AppCrypto;
 use frame_system::offchain::CreateSignedTransaction;
+use frame_system::offchain::{SignedPayload, SigningTypes};
 use scale_info::prelude::*;
 use sp_core::crypto::KeyTypeId;
 use sp_runtime::traits::BlockNumberProvider;
+use sp_runtime::traits::IdentifyAccount;
+use sp_runtime::RuntimeAppPublic;
 use sp_runtime::transaction_validity::InvalidTransaction;
 use sp_std::prelude::*;
 use sp_std::str;
 use sp_std::vec::Vec;
 
-// NOTE: "cf MUST NOT try to compile "lib_circuits" for WASM" in Cargo.toml
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
 use alloc::string::String;
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-use alloc::string::ToString;
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+use alloc::vec;
 use frame_system::ensure_signed;
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-use frame_system::offchain::{SendSignedTransaction, Signer};
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-use interstellar_ipfs_client::IpfsClient;
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+use frame_system::offchain::{SendUnsignedTransaction, Signer};
 use sp_runtime::offchain::{
     storage::StorageValueRef,
     storage_lock::{BlockAndTime, StorageLock},
     Duration,
 };
-#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+use interstellar_ipfs_client::IpfsClient;
+use circuits_storage_common::MessageCapacity;
 use sp_std::borrow::ToOwned;
 
+// NOTE: "cf MUST NOT try to compile "lib_circuits" for WASM" in Cargo.toml
+#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+use alloc::string::ToString;
+
+pub use generation_service::GENERATION_SERVICE_URL_LOCAL_STORAGE_KEY;
 pub use pallet::*;
 
-/// TEST ONLY "hook"
-/// Because the tests need the full body bytes to mock correctly...
-/// It is used to overwrite the result of `lib_circuits_rs::serialize`
-///
-/// cf https://github.com/paritytech/substrate/pull/12307/files
-///
-#[cfg(test)]
-pub trait MyTestCallback {
-    fn my_test_hook(input: Vec<u8>) -> Vec<u8> {
-        input
+/// Notified when `DisplaySkcdPackageValue` is replaced
+pub trait OnNewDisplaySkcdPackage {
+    fn on_new_display_skcd_package(message_skcd_cid: &[u8], pinpad_skcd_cid: &[u8]);
+}
+
+impl OnNewDisplaySkcdPackage for () {
+    fn on_new_display_skcd_package(_message_skcd_cid: &[u8], _pinpad_skcd_cid: &[u8]) {}
+}
+
+/// Generate a circuit and serialize it(ie the .skcd uploaded to IPFS)
+/// cf `Config::CircuitGenerator`; the errors are only logged
+pub trait CircuitGenerator {
+    /// false: the jobs are left queued, unless a generation service is configured
+    fn is_available() -> bool {
+        true
+    }
+    fn generate_generic(verilog_buf: &[u8]) -> Result<Vec<u8>, String>;
+    /// param: digits_bboxes: one bbox(4 floats) per digit; cf `display_layout`
+    /// param: alphabet: the symbols the circuit MUST be able to draw; cf `DisplayProfile`
+    fn generate_display(
+        width: u32,
+        height: u32,
+        digits_bboxes: &[f32],
+        alphabet: &[u8],
+    ) -> Result<Vec<u8>, String>;
+    /// What the message circuits from `generate_display` can show; recorded in `DisplaySkcdPackage`
+    /// NO default: it depends on the circuit, eg its width; cf `MessageCapacity::for_width`
+    /// param: width, height: the message circuit; cf `display_layout`
+    fn message_capacity(width: u32, height: u32) -> Result<MessageCapacity, String>;
+}
+
+/// No in-process generator; eg the WASM runtime
+impl CircuitGenerator for () {
+    fn is_available() -> bool {
+        false
+    }
+
+    fn generate_generic(_verilog_buf: &[u8]) -> Result<Vec<u8>, String> {
+        Err("no CircuitGenerator".into())
+    }
+
+    fn generate_display(
+        _width: u32,
+        _height: u32,
+        _digits_bboxes: &[f32],
+        _alphabet: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        Err("no CircuitGenerator".into())
+    }
+
+    fn message_capacity(_width: u32, _height: u32) -> Result<MessageCapacity, String> {
+        Err("no CircuitGenerator".into())
+    }
+}
+
+/// The width of one character of the `tx_msg` on the display circuits of lib_circuits_rs, in pixels
+/// IMPORTANT: MUST match the font lib_garble_rs draws the `tx_msg` with;
+/// cf `garbled_display_circuit_prepare_garbler_inputs`
+pub const LIB_CIRCUITS_RS_GLYPH_WIDTH: u32 = 5;
+
+/// What a message circuit of lib_circuits_rs can show: printable ASCII, on one line
+/// NOT gated by "circuit-gen-rs": pallet-ocw-garble uses it in its tests
+pub fn lib_circuits_rs_message_capacity(width: u32) -> MessageCapacity {
+    MessageCapacity::for_width(
+        width,
+        LIB_CIRCUITS_RS_GLYPH_WIDTH,
+        MessageCapacity::printable_ascii(),
+    )
+}
+
+/// The default `CircuitGenerator`; requires the feature "circuit-gen-rs"
+#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+pub struct LibCircuitsRs;
+
+#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+impl CircuitGenerator for LibCircuitsRs {
+    fn generate_generic(verilog_buf: &[u8]) -> Result<Vec<u8>, String> {
+        let circuit = lib_circuits_rs::new_from_verilog(verilog_buf)
+            .map_err(|err| alloc::format!("new_from_verilog: {}", err.to_string()))?;
+
+        lib_circuits_rs::serialize(&circuit)
+            .map_err(|err| alloc::format!("serialize: {}", err.to_string()))
+    }
+
+    /// NOTE: the display circuits of lib_circuits_rs only draw the digits [0;9]
+    /// For `DisplayProfile::hexadecimal` use a generation service.
+    fn generate_display(
+        width: u32,
+        height: u32,
+        digits_bboxes: &[f32],
+        alphabet: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        if alphabet.iter().any(|symbol| *symbol >= 10) {
+            return Err(alloc::format!("unsupported alphabet: {:?}", alphabet));
+        }
+
+        let circuit = lib_circuits_rs::generate_display_circuit(width, height, digits_bboxes)
+            .map_err(|err| alloc::format!("generate_display_circuit: {}", err.to_string()))?;
+
+        lib_circuits_rs::serialize(&circuit)
+            .map_err(|err| alloc::format!("serialize: {}", err.to_string()))
+    }
+
+    fn message_capacity(width: u32, _height: u32) -> Result<MessageCapacity, String> {
+        Ok(lib_circuits_rs_message_capacity(width))
     }
 }
 
 #[frame_support::pallet]
 pub mod pallet {
     use super::*;
-    use circuits_storage_common::DisplaySkcdPackage;
+    use circuits_storage_common::{DisplayProfile, DisplaySkcdPackage, PinpadLayout};
     use frame_support::pallet_prelude::*;
     use frame_system::pallet_prelude::*;
 
@@ -69,25 +168,27 @@ pub mod pallet {
     /// The keys can be inserted manually via RPC (see `author_insertKey`).
     pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"circ");
 
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-    const LOCK_TIMEOUT_EXPIRATION: u64 = 10000; // in milli-seconds
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+    /// Offchain local storage(PERSISTENT) key for the IPFS endpoint; cf `get_ipfs_uri`
+    /// It can be set at runtime with eg the `offchain_localStorageSet` RPC
+    pub const IPFS_ROOT_URL_LOCAL_STORAGE_KEY: &[u8] = b"ocw-circuits::config::ipfs_root_url";
+
+    /// in milli-seconds; it MUST cover a whole `generate`, else another run would start the same job.
+    /// The generation service is called twice for the display circuits(message + pinpad), so
+    /// 2x its timeout; plus a margin for the IPFS calls.
+    /// NOTE: `BlockAndTime` only expires once BOTH deadlines have passed
+    const LOCK_TIMEOUT_EXPIRATION: u64 =
+        2 * generation_service::GENERATION_SERVICE_TIMEOUT_MS + 60 * 1000;
     const LOCK_BLOCK_EXPIRATION: u32 = 3; // in block number
 
     const ONCHAIN_TX_KEY: &[u8] = b"ocw-circuits::storage::tx";
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
     const LOCK_KEY: &[u8] = b"ocw-circuits::lock";
 
     // Resolutions for the "message" mode and the "pinpad" mode
     // There are no good/bad ones, it is only trial and error.
     // You SHOULD use lib_circuits's cli_display_skcd to try and find good ones.
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
     const DEFAULT_MESSAGE_WIDTH: u32 = 1280 / 2;
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
     const DEFAULT_MESSAGE_HEIGHT: u32 = 720 / 2;
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
     const DEFAULT_PINPAD_WIDTH: u32 = 590;
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
     const DEFAULT_PINPAD_HEIGHT: u32 = 50;
 
     /// Based on the above `KeyTypeId` we need to generate a pallet-specific crypto type wrapper.
@@ -141,8 +242,35 @@ pub mod pallet {
         //     + From<frame_system::Call<Self>>;
         /// The identifier type for an offchain worker.
         type AuthorityId: AppCrypto<Self::Public, Self::Signature>;
-        #[cfg(test)]
-        type HookCallPostSerializedPackage: MyTestCallback;
+        /// IPFS API root url eg "http://127.0.0.1:5001"; utf8
+        /// Only used if NOT set in the offchain local storage; `()` to use the env var IPFS_ROOT_URL
+        type IpfsRootUrl: Get<Option<Vec<u8>>>;
+        /// A IPFS request taking longer than this(in ms) marks the endpoint as unhealthy
+        /// cf `circuits_storage_common::ipfs_failover::FailoverConfig`
+        #[pallet::constant]
+        type IpfsRequestTimeoutMs: Get<u64>;
+        /// Called along `Event::NewDisplaySkcdPackage`; eg to invalidate caches in pallet-ocw-garble
+        type OnNewDisplaySkcdPackage: OnNewDisplaySkcdPackage;
+        /// Circuit-generation service root url eg "http://127.0.0.1:3000"; utf8
+        /// Only used if NOT set in the offchain local storage; `()` to only use `Config::CircuitGenerator`
+        /// cf `generation_service`
+        type GenerationServiceUrl: Get<Option<Vec<u8>>>;
+        /// Are there offchain workers able to process the jobs queued by `submit_config_*`?
+        /// ie nodes with a `CircuitGenerator`(eg built with the feature `circuit-gen-rs`), or configured with a circuit-generation service.
+        /// NOTE: this is NOT `cfg!(feature = "circuit-gen-rs")`: the WASM runtime is never built with it
+        /// but the native offchain workers can be.
+        /// If `false`: `submit_config_*` fail with `Error::GeneratorUnavailable`
+        #[pallet::constant]
+        type CircuitGeneratorAvailable: Get<bool>;
+        /// Priority of the unsigned callbacks sent by the offchain worker; cf `validate_unsigned`
+        #[pallet::constant]
+        type UnsignedPriority: Get<TransactionPriority>;
+        /// Typically `LibCircuitsRs` with "circuit-gen-rs", else `()`; cf `CircuitGenerator`
+        type CircuitGenerator: CircuitGenerator;
+        /// The OTP length and symbols of the display circuits generated by `submit_config_display_circuits_package_signed`
+        /// eg `circuits_storage_common::DecimalDisplayProfile<6>` for 6-digit codes
+        /// The profile is recorded in `DisplaySkcdPackage`: changing it only applies to the next generated package.
+        type DisplayProfile: Get<DisplayProfile>;
     }
 
     /// For now it will be stored as a StorageValue but later we could use
@@ -151,9 +279,86 @@ pub mod pallet {
     pub(super) type DisplaySkcdPackageValue<T: Config> =
         StorageValue<_, DisplaySkcdPackage, ValueQuery>;
 
+    /// The next `request_id`; cf `Event::CircuitsGenerationRequested`
+    #[pallet::storage]
+    pub(super) type NextRequestId<T: Config> = StorageValue<_, u64, ValueQuery>;
+
+    pub const MAX_OCW_AUTHORITIES: u32 = 32;
+
+    /// The accounts allowed to sign the callbacks(`callback_new_*_unsigned`)
+    /// ie the `KEY_TYPE` keys of the offchain workers; cf `set_ocw_authorities`
+    #[pallet::storage]
+    #[pallet::getter(fn ocw_authorities)]
+    pub(super) type OcwAuthorities<T: Config> =
+        StorageValue<_, BoundedVec<T::AccountId, ConstU32<MAX_OCW_AUTHORITIES>>, ValueQuery>;
+
+    #[pallet::genesis_config]
+    pub struct GenesisConfig<T: Config> {
+        pub ocw_authorities: Vec<T::AccountId>,
+    }
+
+    #[cfg(feature = "std")]
+    impl<T: Config> Default for GenesisConfig<T> {
+        fn default() -> Self {
+            Self {
+                ocw_authorities: Vec::new(),
+            }
+        }
+    }
+
+    #[pallet::genesis_build]
+    impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+        fn build(&self) {
+            let ocw_authorities: BoundedVec<T::AccountId, ConstU32<MAX_OCW_AUTHORITIES>> = self
+                .ocw_authorities
+                .clone()
+                .try_into()
+                .expect("too many ocw_authorities");
+            <OcwAuthorities<T>>::put(ocw_authorities);
+        }
+    }
+
+    /// Signed by one of the `OcwAuthorities`; cf `callback_new_skcd_unsigned`
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, scale_info::TypeInfo)]
+    pub struct NewSkcdPayload<Public> {
+        pub skcd_cid: Vec<u8>,
+        pub request_id: u64,
+        pub public: Public,
+    }
+
+    impl<T: SigningTypes> SignedPayload<T> for NewSkcdPayload<T::Public> {
+        fn public(&self) -> T::Public {
+            self.public.clone()
+        }
+    }
+
+    /// Signed by one of the `OcwAuthorities`; cf `callback_new_display_circuits_package_unsigned`
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, scale_info::TypeInfo)]
+    pub struct NewDisplayCircuitsPackagePayload<Public> {
+        pub message_skcd_cid: Vec<u8>,
+        pub message_nb_digits: u32,
+        pub pinpad_skcd_cid: Vec<u8>,
+        pub pinpad_nb_digits: u32,
+        /// cf `DisplaySkcdPackage::alphabet`
+        pub alphabet: Vec<u8>,
+        pub pinpad_layout: PinpadLayout,
+        pub message_capacity: MessageCapacity,
+        pub request_id: u64,
+        pub public: Public,
+    }
+
+    impl<T: SigningTypes> SignedPayload<T> for NewDisplayCircuitsPackagePayload<T::Public> {
+        fn public(&self) -> T::Public {
+            self.public.clone()
+        }
+    }
+
     /// The current storage version.
+    /// v2: `DisplaySkcdPackage::alphabet`; cf `migrations::v2`
+    /// v3: `DisplaySkcdPackage::pinpad_layout`; cf `migrations::v3`
+    /// v4: `DisplaySkcdPackage::message_capacity`; cf `migrations::v4`
     const STORAGE_VERSION: frame_support::traits::StorageVersion =
-        frame_support::traits::StorageVersion::new(1);
+        frame_support::traits::StorageVersion::new(4);
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
@@ -163,10 +368,24 @@ pub mod pallet {
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
-        // Sent at the end of the offchain_worker(ie it is an OUTPUT)
-        NewSkcdIpfsCid(Vec<u8>),
-        // Display version: one IPFS cid for the message, one IPFS cid for the pinpad
-        NewDisplaySkcdPackageIpfsCid(Vec<u8>, Vec<u8>),
+        /// `submit_config_*`: a job was queued for the offchain worker
+        /// The result(`NewSkcdIpfsCid` or `NewDisplaySkcdPackage`) will have the same `request_id`
+        CircuitsGenerationRequested {
+            account_id: T::AccountId,
+            request_id: u64,
+        },
+        /// Sent at the end of the offchain_worker(ie it is an OUTPUT)
+        NewSkcdIpfsCid {
+            skcd_cid: Vec<u8>,
+            created_at: T::BlockNumber,
+            request_id: u64,
+        },
+        /// Display version: the new `DisplaySkcdPackageValue`(both the message and the pinpad)
+        NewDisplaySkcdPackage {
+            package: DisplaySkcdPackage,
+            created_at: T::BlockNumber,
+            request_id: u64,
+        },
     }
 
     // Errors inform users that something went wrong.
@@ -185,10 +404,46 @@ pub mod pallet {
         StorageGetError,
         /// Special case: not really an error
         OffchainNothingToDoWarning,
+        /// no IPFS endpoint in the offchain local storage, nor in the Config, nor in the env
+        EndpointNotConfigured,
+        /// set_ocw_authorities: more than MAX_OCW_AUTHORITIES
+        TooManyOcwAuthorities,
+        /// submit_config_*: `Config::CircuitGeneratorAvailable` is false
+        /// or offchain_worker: this node can not generate circuits(no `CircuitGenerator` nor generation service)
+        GeneratorUnavailable,
+        /// generation service: the request failed
+        HttpFetchingError,
+        /// generation service: invalid response
+        DeserializeError,
+        /// `Config::DisplayProfile` is not valid(cf `DisplayProfile::is_valid`)
+        /// or callback_new_display_circuits_package_unsigned: the alphabet or the layout do not match the pinpad
+        InvalidDisplayProfile,
+        /// generation service: the request could not be encoded
+        SerializeError,
+        /// the generator(or the generation service) did NOT report what the message circuit can show;
+        /// or it can NOT show anything; cf `MessageCapacity::is_valid`
+        InvalidMessageCapacity,
     }
 
     #[pallet::hooks]
     impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        /// IMPORTANT: in order; each one translates from the previous version
+        fn on_runtime_upgrade() -> Weight {
+            crate::migrations::v2::migrate::<T>()
+                .saturating_add(crate::migrations::v3::migrate::<T>())
+                .saturating_add(crate::migrations::v4::migrate::<T>())
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn pre_upgrade() -> Result<Vec<u8>, &'static str> {
+            crate::migrations::v4::pre_upgrade::<T>()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn post_upgrade(state: Vec<u8>) -> Result<(), &'static str> {
+            crate::migrations::v4::post_upgrade::<T>(state)
+        }
+
         /// Offchain Worker entry point.
         ///
         /// By implementing `fn offchain_worker` you declare a new offchain worker.
@@ -202,19 +457,10 @@ pub mod pallet {
             log::info!("[ocw-circuits] Hello from pallet-ocw-circuits.");
 
             // TODO proper job queue; eg use last_run_block_number and process all the needed ones
-            #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-            let result = Self::process_if_needed(block_number);
-
-            #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-            if let Err(e) = result {
-                log::error!("[ocw-circuits] offchain_worker error: {:?}", e);
+            match Self::process_if_needed(block_number) {
+                Ok(()) | Err(Error::<T>::OffchainNothingToDoWarning) => {}
+                Err(e) => log::error!("[ocw-circuits] offchain_worker error: {:?}", e),
             }
-
-            #[cfg(not(all(feature = "circuit-gen-rs", not(target_family = "wasm"))))]
-            log::info!(
-                "[ocw-circuits] Running without feature `circuit-gen-rs; no-op! {:?}`",
-                block_number
-            );
         }
     }
 
@@ -228,6 +474,8 @@ pub mod pallet {
         // CHECK: error-out if both fields are not set
         if display_circuit_package.message_skcd_server_metadata_nb_digits == 0
             || display_circuit_package.pinpad_skcd_server_metadata_nb_digits == 0
+            || !display_circuit_package.display_profile().is_valid()
+            || !display_circuit_package.message_capacity.is_valid()
         {
             return Err(<Error<T>>::DisplaySkcdPackageValueError);
         }
@@ -235,6 +483,15 @@ pub mod pallet {
         Ok(display_circuit_package)
     }
 
+    /// If the given cid is one of the current display .skcd; even if the package is incomplete.
+    ///
+    /// This is called by `pallet-ocw-garble`: it MUST NOT unpin them!
+    pub fn is_display_skcd_cid<T: Config>(cid: &[u8]) -> bool {
+        let display_circuit_package = <DisplaySkcdPackageValue<T>>::get();
+        display_circuit_package.message_skcd_cid.as_slice() == cid
+            || display_circuit_package.pinpad_skcd_cid.as_slice() == cid
+    }
+
     #[pallet::validate_unsigned]
     impl<T: Config> ValidateUnsigned for Pallet<T> {
         type Call = Call<T>;
@@ -244,107 +501,247 @@ pub mod pallet {
         /// By default unsigned transactions are disallowed, but implementing the validator
         /// here we make sure that some particular calls (the ones produced by offchain worker)
         /// are being whitelisted and marked as valid.
+        ///
+        /// Only the callbacks signed by one of the `OcwAuthorities` are valid.
+        /// NOTE: the "provides" tag does NOT contain the signer: the same result sent by several
+        /// offchain workers(or several keys) is deduplicated by the pool.
         fn validate_unsigned(
             _source: TransactionSource,
-            _call: &Self::Call,
+            call: &Self::Call,
         ) -> TransactionValidity {
-            // TODO?
-            InvalidTransaction::Call.into()
+            match call {
+                Call::callback_new_skcd_unsigned { payload, signature } => {
+                    Self::validate_callback(payload, signature, (b"skcd", payload.request_id))
+                }
+                Call::callback_new_display_circuits_package_unsigned { payload, signature } => {
+                    Self::validate_callback(payload, signature, (b"display", payload.request_id))
+                }
+                _ => InvalidTransaction::Call.into(),
+            }
+        }
+    }
+
+    impl<T: Config> Pallet<T> {
+        fn validate_callback<P: SignedPayload<T>>(
+            payload: &P,
+            signature: &T::Signature,
+            provides: impl Encode,
+        ) -> TransactionValidity {
+            if !SignedPayload::<T>::verify::<T::AuthorityId>(payload, signature.clone()) {
+                return InvalidTransaction::BadProof.into();
+            }
+            let signer = payload.public().into_account();
+            if !<OcwAuthorities<T>>::get().contains(&signer) {
+                return InvalidTransaction::BadSigner.into();
+            }
+
+            ValidTransaction::with_tag_prefix("OcwCircuitsCallback")
+                .priority(T::UnsignedPriority::get())
+                .and_provides(provides)
+                // the result is only useful if included "soon"
+                .longevity(5)
+                .propagate(true)
+                .build()
         }
     }
 
-    // cf https://substrate.stackexchange.com/questions/7804/how-to-conditionally-compile-functions-in-palletcall
-    // for why we need an indirection...
     #[pallet::call]
     impl<T: Config> Pallet<T> {
+        /// Queue a job for the offchain worker; the result is sent back with `callback_new_skcd_unsigned`
         #[pallet::call_index(0)]
         #[pallet::weight(10000)]
         pub fn submit_config_generic_signed(
             origin: OriginFor<T>,
             verilog_cid: Vec<u8>,
         ) -> DispatchResult {
-            Self::submit_config_generic_signed_impl(origin, verilog_cid)
+            let who = ensure_signed(origin)?;
+            // NOTE: NOT in the log! args: they are not evaluated if the level is disabled
+            let verilog_cid_str = sp_std::str::from_utf8(&verilog_cid)
+                .map_err(|_err| <Error<T>>::DeserializeError)?;
+            log::info!(
+                "[ocw-circuits] submit_config_generic_signed: ({:?}, {:?})",
+                verilog_cid_str,
+                who
+            );
+            ensure!(
+                T::CircuitGeneratorAvailable::get(),
+                <Error<T>>::GeneratorUnavailable
+            );
+
+            Self::add_job_to_queue(who, GrpcCallKind::Generic { verilog_cid });
+
+            Ok(())
         }
 
+        /// Same as `submit_config_generic_signed`; the result is sent back with `callback_new_display_circuits_package_unsigned`
         #[pallet::call_index(1)]
         #[pallet::weight(10000)]
         pub fn submit_config_display_circuits_package_signed(
             origin: OriginFor<T>,
         ) -> DispatchResult {
-            Self::submit_config_display_circuits_package_signed_impl(origin)
+            let who = ensure_signed(origin)?;
+            log::info!(
+                "[ocw-circuits] submit_config_display_circuits_package_signed: ({:?})",
+                who
+            );
+            ensure!(
+                T::CircuitGeneratorAvailable::get(),
+                <Error<T>>::GeneratorUnavailable
+            );
+            let profile = T::DisplayProfile::get();
+            ensure!(profile.is_valid(), <Error<T>>::InvalidDisplayProfile);
+
+            Self::add_job_to_queue(who, GrpcCallKind::Display { profile });
+
+            Ok(())
         }
 
         /// Called at the end of offchain_worker to publish the result
-        /// Not meant to be called by a user
-        // TODO use "with signed payload" and check if expected key?
+        /// Not meant to be called by a user: the payload MUST be signed by one of the `OcwAuthorities`
+        /// cf `validate_unsigned`
         #[pallet::call_index(2)]
         #[pallet::weight(10000)]
-        pub fn callback_new_skcd_signed(origin: OriginFor<T>, skcd_cid: Vec<u8>) -> DispatchResult {
-            let who = ensure_signed(origin)?;
+        pub fn callback_new_skcd_unsigned(
+            origin: OriginFor<T>,
+            payload: NewSkcdPayload<T::Public>,
+            _signature: T::Signature,
+        ) -> DispatchResult {
+            ensure_none(origin)?;
+            let NewSkcdPayload {
+                skcd_cid,
+                request_id,
+                public,
+            } = payload;
 
             log::info!(
-                "[ocw-circuits] callback_new_skcd_signed: {:?} for {:?})",
-                sp_std::str::from_utf8(&skcd_cid).expect("skcd_cid utf8"),
-                who
+                "[ocw-circuits] callback_new_skcd_unsigned: {:?} from {:?})",
+                sp_std::str::from_utf8(&skcd_cid),
+                public
             );
 
-            Self::deposit_event(Event::NewSkcdIpfsCid(skcd_cid));
+            Self::deposit_event(Event::NewSkcdIpfsCid {
+                skcd_cid,
+                created_at: <frame_system::Pallet<T>>::block_number(),
+                request_id,
+            });
             Ok(())
         }
 
+        /// Same as `callback_new_skcd_unsigned` for the display circuits
         #[pallet::call_index(3)]
         #[pallet::weight(10000)]
-        pub fn callback_new_display_circuits_package_signed(
+        pub fn callback_new_display_circuits_package_unsigned(
             origin: OriginFor<T>,
-            message_skcd_cid: Vec<u8>,
-            message_nb_digits: u32,
-            pinpad_skcd_cid: Vec<u8>,
-            pinpad_nb_digits: u32,
+            payload: NewDisplayCircuitsPackagePayload<T::Public>,
+            _signature: T::Signature,
         ) -> DispatchResult {
-            let who = ensure_signed(origin)?;
-
+            ensure_none(origin)?;
+            let NewDisplayCircuitsPackagePayload {
+                message_skcd_cid,
+                message_nb_digits,
+                pinpad_skcd_cid,
+                pinpad_nb_digits,
+                alphabet,
+                pinpad_layout,
+                message_capacity,
+                request_id,
+                public,
+            } = payload;
+
+            // CHECK: the cids are given to `ipfs_cat` as str; cf ocw-garble
+            let message_skcd_cid_str = sp_std::str::from_utf8(&message_skcd_cid)
+                .map_err(|_err| <Error<T>>::DeserializeError)?;
+            let pinpad_skcd_cid_str = sp_std::str::from_utf8(&pinpad_skcd_cid)
+                .map_err(|_err| <Error<T>>::DeserializeError)?;
             log::info!(
-                "[ocw-circuits] callback_new_display_circuits_package_signed: ({:?},{:?}),({:?},{:?}) for {:?}",
-                sp_std::str::from_utf8(&message_skcd_cid).expect("message_skcd_cid utf8"),
+                "[ocw-circuits] callback_new_display_circuits_package_unsigned: ({:?},{:?}),({:?},{:?}) from {:?}",
+                message_skcd_cid_str,
                 message_nb_digits,
-                sp_std::str::from_utf8(&pinpad_skcd_cid).expect("pinpad_skcd_cid utf8"),
+                pinpad_skcd_cid_str,
                 pinpad_nb_digits,
-                who
+                public
             );
 
-            Self::deposit_event(Event::NewDisplaySkcdPackageIpfsCid(
-                message_skcd_cid.clone(),
-                pinpad_skcd_cid.clone(),
-            ));
+            // CHECK: the pinpad shows each symbol exactly once, one per cell; cf `DisplayProfile`
+            let alphabet: circuits_storage_common::Alphabet = alphabet
+                .try_into()
+                .map_err(|_err| <Error<T>>::InvalidDisplayProfile)?;
+            ensure!(
+                alphabet.len() as u32 == pinpad_nb_digits
+                    && pinpad_layout.is_valid(pinpad_nb_digits),
+                <Error<T>>::InvalidDisplayProfile
+            );
+            // CHECK: else ocw-garble would reject every `tx_msg`
+            ensure!(
+                message_capacity.is_valid(),
+                <Error<T>>::InvalidMessageCapacity
+            );
+            let message_skcd_cid: BoundedVec<u8, ConstU32<64>> = message_skcd_cid
+                .try_into()
+                .map_err(|_err| <Error<T>>::IpfsCallError)?;
+            let pinpad_skcd_cid: BoundedVec<u8, ConstU32<64>> = pinpad_skcd_cid
+                .try_into()
+                .map_err(|_err| <Error<T>>::IpfsCallError)?;
+
+            T::OnNewDisplaySkcdPackage::on_new_display_skcd_package(
+                &message_skcd_cid,
+                &pinpad_skcd_cid,
+            );
 
             // and update the current "reference" circuits package
-            <DisplaySkcdPackageValue<T>>::set(DisplaySkcdPackage {
-                message_skcd_cid: TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(
-                    message_skcd_cid,
-                )
-                .unwrap(),
+            let package = DisplaySkcdPackage {
+                message_skcd_cid,
                 message_skcd_server_metadata_nb_digits: message_nb_digits,
-                pinpad_skcd_cid: TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(pinpad_skcd_cid)
-                    .unwrap(),
+                pinpad_skcd_cid,
                 pinpad_skcd_server_metadata_nb_digits: pinpad_nb_digits,
+                alphabet,
+                pinpad_layout,
+                message_capacity,
+            };
+            <DisplaySkcdPackageValue<T>>::set(package.clone());
+
+            Self::deposit_event(Event::NewDisplaySkcdPackage {
+                package,
+                created_at: <frame_system::Pallet<T>>::block_number(),
+                request_id,
             });
 
             Ok(())
         }
+
+        /// Replace `OcwAuthorities`
+        /// Root ONLY: else anyone could publish its own circuits
+        #[pallet::call_index(4)]
+        #[pallet::weight(10000)]
+        pub fn set_ocw_authorities(
+            origin: OriginFor<T>,
+            ocw_authorities: Vec<T::AccountId>,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+
+            let ocw_authorities: BoundedVec<T::AccountId, ConstU32<MAX_OCW_AUTHORITIES>> =
+                ocw_authorities
+                    .try_into()
+                    .map_err(|_err| <Error<T>>::TooManyOcwAuthorities)?;
+            <OcwAuthorities<T>>::put(ocw_authorities);
+
+            Ok(())
+        }
     }
 
-    #[derive(Debug, Encode, Decode, Default)]
+    #[derive(Debug, Encode, Decode)]
     pub(crate) enum GrpcCallKind {
         Generic {
             verilog_cid: Vec<u8>,
         },
-        #[default]
-        Display,
+        /// `Config::DisplayProfile` when the job was queued
+        Display {
+            profile: DisplayProfile,
+        },
     }
 
-    /// Results wrappers for the calls to "lib_circuits_rs::"
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-    enum LibCircuitsRsResultKind {
+    /// Results wrappers for the calls to the `CircuitGenerator` or to the generation service
+    pub(crate) enum LibCircuitsRsResultKind {
         Generic {
             ipfs_hash: String,
         },
@@ -354,16 +751,21 @@ pub mod pallet {
             message_nb_digits: u32,
             pinpad_ipfs_hash: String,
             pinpad_nb_digits: u32,
+            alphabet: Vec<u8>,
+            pinpad_layout: PinpadLayout,
+            message_capacity: MessageCapacity,
         },
     }
 
-    #[derive(Debug, Encode, Decode, Default)]
+    #[derive(Debug, Encode, Decode)]
     pub(crate) struct IndexingData {
         // // verilog_ipfs_hash only if GrpcCallKind::Generic
         // // (For now) when it is GrpcCallKind::Display the corresponding Verilog are packaged in the repo api_circuits
         // // = in "display mode" the Verilog are hardcoded, NOT passed dynamically via IPFS; contrary to "generic mode"
         // verilog_ipfs_hash: Option<Vec<u8>>,
         pub(crate) grpc_kind: GrpcCallKind,
+        /// passed back to the callback; cf `Event::CircuitsGenerationRequested`
+        pub(crate) request_id: u64,
     }
 
     impl<T: Config> Pallet<T> {
@@ -382,11 +784,21 @@ pub mod pallet {
 
         /// Append a new number to the tail of the list, removing an element from the head if reaching
         ///   the bounded length.
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-        fn add_job_to_queue(grpc_kind: GrpcCallKind) {
+        fn add_job_to_queue(who: T::AccountId, grpc_kind: GrpcCallKind) {
+            let request_id = <NextRequestId<T>>::get();
+            <NextRequestId<T>>::put(request_id.wrapping_add(1));
+
             let key = Self::derived_key();
-            let data = IndexingData { grpc_kind };
+            let data = IndexingData {
+                grpc_kind,
+                request_id,
+            };
             sp_io::offchain_index::set(&key, &data.encode());
+
+            Self::deposit_event(Event::CircuitsGenerationRequested {
+                account_id: who,
+                request_id,
+            });
         }
 
         /// Check if we have fetched the data before. If yes, we can use the cached version
@@ -395,7 +807,6 @@ pub mod pallet {
         ///
         /// https://github.com/JoshOrndorff/recipes/blob/master/text/off-chain-workers/storage.md
         /// https://gist.github.com/spencerbh/1a150e076f4cef0ff4558642c4837050
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
         fn process_if_needed(_block_number: T::BlockNumber) -> Result<(), Error<T>> {
             // Reading back the off-chain indexing value. It is exactly the same as reading from
             // ocw local storage.
@@ -417,6 +828,16 @@ pub mod pallet {
                     <Error<T>>::OffchainNothingToDoWarning
                 })?;
 
+            // NOT an on-chain error: the job stays queued and `GeneratorUnavailable` is only logged
+            // A node without a generator SHOULD NOT drop the jobs
+            if !local_generator_available::<T>() {
+                log::warn!(
+                    "[ocw-circuits] request_id {} pending but this node can not generate circuits",
+                    indexing_data.request_id
+                );
+                return Err(<Error<T>>::GeneratorUnavailable);
+            }
+
             // Since off-chain storage can be accessed by off-chain workers from multiple runs, it is important to lock
             //   it before doing heavy computations or write operations.
             //
@@ -435,120 +856,57 @@ pub mod pallet {
             // We try to acquire the lock here. If failed, we know the `fetch_n_parse` part inside is being
             //   executed by previous run of ocw, so the function just returns.
             if let Ok(_guard) = lock.try_lock() {
-                // NOTE: remove the task from the "job queue" wether it worked or not
+                // NOTE: the task stays in the "job queue" until its callback is sent; so a failed
+                // `generate`(eg IPFS or the generation service down) is retried by a next run
                 // TODO better? But in this case we should only retry in case of "remote error"
                 // and NOT retry if eg the given hash is not a valid IPFS hash
-                //
-                // DO NOT use "sp_io::offchain_index::set"!
-                // We MUST use "StorageValueRef::persistent" else the value is not updated??
-                oci_mem.clear();
-
-                let result_grpc_call = match indexing_data.grpc_kind {
-                    GrpcCallKind::Generic { verilog_cid } => Self::call_grpc_generic(&verilog_cid)?,
-                    GrpcCallKind::Display => Self::call_grpc_display()?,
-                };
-
-                Self::finalize_grpc_call(result_grpc_call);
+                let result_grpc_call = generate::<T>(indexing_data.grpc_kind)?;
+
+                if Self::finalize_grpc_call(result_grpc_call, indexing_data.request_id) {
+                    // NOT if a new job was queued in the meantime(cf `add_job_to_queue`)
+                    let current_request_id = oci_mem
+                        .get::<IndexingData>()
+                        .ok()
+                        .flatten()
+                        .map(|current| current.request_id);
+                    if current_request_id == Some(indexing_data.request_id) {
+                        // DO NOT use "sp_io::offchain_index::set"!
+                        // We MUST use "StorageValueRef::persistent" else the value is not updated??
+                        oci_mem.clear();
+                    }
+                }
             }
 
             Ok(())
         }
 
-        #[cfg(not(all(feature = "circuit-gen-rs", not(target_family = "wasm"))))]
-        fn submit_config_generic_signed_impl(
-            _origin: OriginFor<T>,
-            _verilog_cid: Vec<u8>,
-        ) -> DispatchResult {
-            unimplemented!("submit_config_generic_signed_impl: require feature circuit-gen-rs")
-        }
-
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-        fn submit_config_generic_signed_impl(
-            origin: OriginFor<T>,
-            verilog_cid: Vec<u8>,
-        ) -> DispatchResult {
-            let who = ensure_signed(origin)?;
-            log::info!(
-                "[ocw-circuits] submit_config_generic_signed: ({:?}, {:?})",
-                sp_std::str::from_utf8(&verilog_cid).expect("verilog_cid utf8"),
-                who
-            );
-
-            Self::add_job_to_queue(GrpcCallKind::Generic { verilog_cid });
-
-            Ok(())
-        }
-
-        #[cfg(not(all(feature = "circuit-gen-rs", not(target_family = "wasm"))))]
-        fn submit_config_display_circuits_package_signed_impl(
-            _origin: OriginFor<T>,
-        ) -> DispatchResult {
-            unimplemented!("submit_config_display_circuits_package_signed_impl: require feature circuit-gen-rs")
-        }
-
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-        fn submit_config_display_circuits_package_signed_impl(
-            origin: OriginFor<T>,
-        ) -> DispatchResult {
-            let who = ensure_signed(origin)?;
-            log::info!(
-                "[ocw-circuits] submit_config_display_circuits_package_signed: ({:?})",
-                who
-            );
-
-            Self::add_job_to_queue(GrpcCallKind::Display);
-
-            Ok(())
-        }
-
         /// Call the GRPC endpoint API_ENDPOINT_GENERIC_URL, encoding the request as grpc-web, and decoding the response
         ///
         /// return: a IPFS hash
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
         fn call_grpc_generic(verilog_cid: &[u8]) -> Result<LibCircuitsRsResultKind, Error<T>> {
-            let ipfs_client = interstellar_ipfs_client::IpfsClientSpOffchain::new(&get_ipfs_uri())
-                .map_err(|err| {
-                    log::error!("[ocw-circuits] ipfs client new error: {:?}", err);
-                    <Error<T>>::IpfsClientCreationError
-                })?;
-
             let verilog_cid_str = sp_std::str::from_utf8(verilog_cid)
-                .expect("call_grpc_generic from_utf8")
+                .map_err(|_err| <Error<T>>::DeserializeError)?
                 .to_owned();
 
             // TODO(lib_circuits) get .v from IPFS (eg use ipfs_cat), and use new eg `lib_circuits_rs::new_from_verilog`
-            let verilog_buf = ipfs_client.ipfs_cat(&verilog_cid_str).map_err(|err| {
-                log::error!("[ocw-circuits] ipfs call ipfs_add error: {:?}", err);
-                <Error<T>>::IpfsCallError
-            })?;
-
-            let circuit = lib_circuits_rs::new_from_verilog(&verilog_buf).map_err(|err| {
-                log::error!(
-                    "[ocw-circuits] lib_circuits_rs::new_from_verilog error: {:?} {:?}",
-                    err.to_string(),
-                    err
-                );
-                <Error<T>>::CircuitGenericGenerateError
+            let verilog_buf = ipfs_call_with_failover::<T, _>(|ipfs_client| {
+                ipfs_client.ipfs_cat(&verilog_cid_str).map_err(|err| {
+                    log::error!("[ocw-circuits] ipfs call ipfs_cat error: {:?}", err);
+                    <Error<T>>::IpfsCallError
+                })
             })?;
 
-            // then serialize "garb" and "packmsg"
-            let serialized_circuit = lib_circuits_rs::serialize(&circuit).map_err(|err| {
-                log::error!(
-                    "[ocw-circuits] lib_circuits_rs::serialize error: {:?} {:?}",
-                    err.to_string(),
-                    err
-                );
-                <Error<T>>::CircuitSerializeError
-            })?;
-
-            // the tests need the full body bytes to mock correctly...
-            #[cfg(test)]
             let serialized_circuit =
-                T::HookCallPostSerializedPackage::my_test_hook(serialized_circuit);
+                T::CircuitGenerator::generate_generic(&verilog_buf).map_err(|err| {
+                    log::error!("[ocw-circuits] CircuitGenerator::generate_generic error: {}", err);
+                    <Error<T>>::CircuitGenericGenerateError
+                })?;
 
-            let ipfs_add_response = ipfs_client.ipfs_add(&serialized_circuit).map_err(|err| {
-                log::error!("[ocw-circuits] ipfs call ipfs_add error: {:?}", err);
-                <Error<T>>::IpfsCallError
+            let ipfs_add_response = ipfs_call_with_failover::<T, _>(|ipfs_client| {
+                ipfs_client.ipfs_add(&serialized_circuit).map_err(|err| {
+                    log::error!("[ocw-circuits] ipfs call ipfs_add error: {:?}", err);
+                    <Error<T>>::IpfsCallError
+                })
             })?;
 
             Ok(LibCircuitsRsResultKind::Generic {
@@ -563,66 +921,133 @@ pub mod pallet {
         /// - the number of digits(that was sent to `api_circuits` in the Request, and SHOULD be "burned in" the Garbled Circuit)
         ///   NOTE: it is CRITICAL to expose this number of digits(eg via the Storage, or RPC) b/c `pallet-ocw-garble`
         ///         MUST know it when attempting to garble the circuit to generate the correct number of random digits.
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-        fn call_grpc_display() -> Result<LibCircuitsRsResultKind, Error<T>> {
-            let (message_ipfs_hash, message_nb_digits) = call_grpc_display_one::<T>(true)?;
-            let (pinpad_ipfs_hash, pinpad_nb_digits) = call_grpc_display_one::<T>(false)?;
+        fn call_grpc_display(
+            profile: &DisplayProfile,
+        ) -> Result<LibCircuitsRsResultKind, Error<T>> {
+            // first: else the circuits would be generated(and uploaded) for nothing
+            let (message_width, message_height, _) = display_layout_for_profile(profile, true);
+            let message_capacity =
+                T::CircuitGenerator::message_capacity(message_width, message_height).map_err(
+                    |err| {
+                        log::error!(
+                            "[ocw-circuits] CircuitGenerator::message_capacity error: {}",
+                            err
+                        );
+                        <Error<T>>::InvalidMessageCapacity
+                    },
+                )?;
+            ensure!(
+                message_capacity.is_valid(),
+                <Error<T>>::InvalidMessageCapacity
+            );
+
+            let (message_ipfs_hash, message_nb_digits) = call_grpc_display_one::<T>(profile, true)?;
+            let (pinpad_ipfs_hash, pinpad_nb_digits) = call_grpc_display_one::<T>(profile, false)?;
 
-            // TODO pass correct params for pinpad and message
             Ok(LibCircuitsRsResultKind::Display {
                 message_ipfs_hash,
                 message_nb_digits,
                 pinpad_ipfs_hash,
                 pinpad_nb_digits,
+                alphabet: profile.alphabet.to_vec(),
+                pinpad_layout: profile.pinpad_layout.clone(),
+                message_capacity,
             })
         }
 
         /// Called at the end of process_if_needed/offchain_worker
-        /// Publish the result back via send_signed_transaction(and Event)
+        /// Publish the result back via send_unsigned_transaction(and Event)
         ///
         /// param: result_grpc_call: returned by call_grpc_display/call_grpc_generic
-        #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-        fn finalize_grpc_call(lib_circuits_rs_result: LibCircuitsRsResultKind) {
-            // Using `send_signed_transaction` associated type we create and submit a transaction
-            // representing the call we've just created.
-            // `send_signed_transaction()` return type is `Option<(Account<T>, Result<(), ()>)>`. It is:
-            //   - `None`: no account is available for sending transaction
-            //   - `Some((account, Ok(())))`: transaction is successfully sent
-            //   - `Some((account, Err(())))`: error occurred when sending the transaction
-            let signer = Signer::<T, T::AuthorityId>::all_accounts();
+        /// param: request_id: cf `IndexingData`
+        ///
+        /// return: true if at least one callback was sent
+        fn finalize_grpc_call(
+            lib_circuits_rs_result: LibCircuitsRsResultKind,
+            request_id: u64,
+        ) -> bool {
+            // Unsigned with a signed payload: the offchain worker account does NOT need any funds.
+            // Only the local keys that are `OcwAuthorities`: the others would be rejected by `validate_unsigned`.
+            // The same result sent by several keys/offchain workers is deduplicated cf `validate_unsigned`.
+            // `send_unsigned_transaction()` return type is `Vec<(Account<T>, Result<(), ()>)>`;
+            // one entry per key.
+            let signer = Signer::<T, T::AuthorityId>::all_accounts()
+                .with_filter(local_authority_keys::<T, T::AuthorityId>());
             if !signer.can_sign() {
                 log::error!(
-                    "[ocw-circuits] No local accounts available. Consider adding one via `author_insertKey` RPC[ALTERNATIVE DEV ONLY check 'if config.offchain_worker.enabled' in service.rs]"
+                    "[ocw-circuits] No local authority account available. Consider adding one via `author_insertKey` RPC, and `set_ocw_authorities`[ALTERNATIVE DEV ONLY check 'if config.offchain_worker.enabled' in service.rs]"
                 );
+                return false;
             }
 
-            let tx_results =
-                signer.send_signed_transaction(|_account| match &lib_circuits_rs_result {
-                    LibCircuitsRsResultKind::Generic { ipfs_hash } => {
-                        Call::callback_new_skcd_signed {
-                            skcd_cid: ipfs_hash.bytes().collect(),
-                        }
-                    }
-                    LibCircuitsRsResultKind::Display {
-                        message_ipfs_hash,
-                        message_nb_digits,
-                        pinpad_ipfs_hash,
-                        pinpad_nb_digits,
-                    } => Call::callback_new_display_circuits_package_signed {
+            let tx_result = match &lib_circuits_rs_result {
+                LibCircuitsRsResultKind::Generic { ipfs_hash } => signer.send_unsigned_transaction(
+                    |account| NewSkcdPayload {
+                        skcd_cid: ipfs_hash.bytes().collect(),
+                        request_id,
+                        public: account.public.clone(),
+                    },
+                    |payload, signature| Call::callback_new_skcd_unsigned { payload, signature },
+                ),
+                LibCircuitsRsResultKind::Display {
+                    message_ipfs_hash,
+                    message_nb_digits,
+                    pinpad_ipfs_hash,
+                    pinpad_nb_digits,
+                    alphabet,
+                    pinpad_layout,
+                    message_capacity,
+                } => signer.send_unsigned_transaction(
+                    |account| NewDisplayCircuitsPackagePayload {
                         message_skcd_cid: message_ipfs_hash.bytes().collect(),
                         message_nb_digits: *message_nb_digits,
                         pinpad_skcd_cid: pinpad_ipfs_hash.bytes().collect(),
                         pinpad_nb_digits: *pinpad_nb_digits,
+                        alphabet: alphabet.clone(),
+                        pinpad_layout: pinpad_layout.clone(),
+                        message_capacity: message_capacity.clone(),
+                        request_id,
+                        public: account.public.clone(),
                     },
-                });
-
-            log::info!(
-                "[ocw-circuits] callback_new_skcd_signed sent number : {:#?}",
-                tx_results.len()
-            );
+                    |payload, signature| Call::callback_new_display_circuits_package_unsigned {
+                        payload,
+                        signature,
+                    },
+                ),
+            };
+
+            let mut is_sent = false;
+            for (account, result) in tx_result {
+                match result {
+                    Ok(()) => {
+                        log::info!("[ocw-circuits] callback sent by {:?}", account.id);
+                        is_sent = true;
+                    }
+                    Err(()) => log::error!("[ocw-circuits] callback failed for {:?}", account.id),
+                }
+            }
+            is_sent
         }
     }
 
+    /// The local `AuthorityId` keys that are in `OcwAuthorities`; cf `finalize_grpc_call`
+    /// Also used by pallet-ocw-garble(with its own key type) for its callbacks.
+    pub fn local_authority_keys<T: Config, AuthorityId: AppCrypto<T::Public, T::Signature>>(
+    ) -> Vec<T::Public> {
+        let ocw_authorities = <OcwAuthorities<T>>::get();
+
+        <AuthorityId as AppCrypto<T::Public, T::Signature>>::RuntimeAppPublic::all()
+            .into_iter()
+            .map(|key| {
+                let generic_public =
+                    <AuthorityId as AppCrypto<T::Public, T::Signature>>::GenericPublic::from(key);
+                let public: T::Public = generic_public.into();
+                public
+            })
+            .filter(|public| ocw_authorities.contains(&public.clone().into_account()))
+            .collect()
+    }
+
     // needed for with_block_and_time_deadline()
     impl<T: Config> BlockNumberProvider for Pallet<T> {
         type BlockNumber = T::BlockNumber;
@@ -632,99 +1057,213 @@ pub mod pallet {
         }
     }
 
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-    fn get_ipfs_uri() -> String {
+    /// Can THIS node process the jobs? cf `Config::CircuitGeneratorAvailable` for the on-chain side
+    pub(crate) fn local_generator_available<T: Config>() -> bool {
+        T::CircuitGenerator::is_available()
+            || generation_service::get_generation_service_url::<T>().is_some()
+    }
+
+    /// Dispatch the job to the available generator:
+    /// the generation service if configured(it can be switched at runtime cf `GENERATION_SERVICE_URL_LOCAL_STORAGE_KEY`),
+    /// else `Config::CircuitGenerator`
+    fn generate<T: Config>(grpc_kind: GrpcCallKind) -> Result<LibCircuitsRsResultKind, Error<T>> {
+        if let Some(url) = generation_service::get_generation_service_url::<T>() {
+            return generation_service::call_generation_service::<
+                T,
+                interstellar_http_client::ClientSpOffchain,
+            >(&url, grpc_kind);
+        }
+
+        if !T::CircuitGenerator::is_available() {
+            return Err(<Error<T>>::GeneratorUnavailable);
+        }
+
+        match grpc_kind {
+            GrpcCallKind::Generic { verilog_cid } => Pallet::<T>::call_grpc_generic(&verilog_cid),
+            GrpcCallKind::Display { profile } => Pallet::<T>::call_grpc_display(&profile),
+        }
+    }
+
+    /// Resolve the IPFS endpoint, in priority order:
+    /// - offchain local storage(PERSISTENT) at IPFS_ROOT_URL_LOCAL_STORAGE_KEY; raw utf8 url
+    /// - `Config::IpfsRootUrl`
+    /// - the env var IPFS_ROOT_URL(legacy)
+    ///
+    /// An empty value is considered "not set" and we move on to the next source.
+    pub(crate) fn get_ipfs_uri<T: Config>() -> Result<String, Error<T>> {
+        let from_local_storage = sp_io::offchain::local_storage_get(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            IPFS_ROOT_URL_LOCAL_STORAGE_KEY,
+        );
+
+        match from_local_storage
+            .filter(|value| !value.is_empty())
+            .or_else(|| T::IpfsRootUrl::get().filter(|value| !value.is_empty()))
+        {
+            Some(value) => String::from_utf8(value).map_err(|_err| {
+                log::error!("[ocw-circuits] IPFS endpoint is not valid utf8!");
+                <Error<T>>::EndpointNotConfigured
+            }),
+            None => get_env_var("IPFS_ROOT_URL").ok_or_else(|| {
+                log::error!("[ocw-circuits] IPFS endpoint not configured!");
+                <Error<T>>::EndpointNotConfigured
+            }),
+        }
+    }
+
+    /// NOTE: can be a comma separated list of endpoints, in priority order; cf `ipfs_call_with_failover`
+    fn get_ipfs_uris<T: Config>() -> Result<Vec<String>, Error<T>> {
+        let endpoints = get_ipfs_uri::<T>()?;
+        let endpoints = circuits_storage_common::ipfs_failover::parse_endpoints(&endpoints);
+        if endpoints.is_empty() {
+            return Err(<Error<T>>::EndpointNotConfigured);
+        }
+
+        Ok(endpoints)
+    }
+
+    /// Call `f` with a client for each of the configured IPFS endpoints, until one succeeds
+    /// The health of the endpoints is tracked in the offchain local storage.
+    /// NOTE: `IpfsClientSpOffchain` has no deadline; so a late answer is only used as a last resort(cf `with_failover`)
+    fn ipfs_call_with_failover<T: Config, R>(
+        mut f: impl FnMut(&interstellar_ipfs_client::IpfsClientSpOffchain) -> Result<R, Error<T>>,
+    ) -> Result<R, Error<T>> {
+        let config = circuits_storage_common::ipfs_failover::FailoverConfig {
+            request_timeout_ms: T::IpfsRequestTimeoutMs::get(),
+            ..Default::default()
+        };
+
+        circuits_storage_common::ipfs_failover::with_failover::<
+            circuits_storage_common::ipfs_failover::OffchainFailoverEnv,
+            _,
+            _,
+            _,
+        >(&get_ipfs_uris::<T>()?, &config, |endpoint, _timeout| {
+            let ipfs_client = interstellar_ipfs_client::IpfsClientSpOffchain::new(endpoint)
+                .map_err(|err| {
+                    log::error!("[ocw-circuits] ipfs client new error: {:?}", err);
+                    <Error<T>>::IpfsClientCreationError
+                })?;
+            f(&ipfs_client).map_err(|err| {
+                log::warn!("[ocw-circuits] IPFS endpoint {} failed: {:?}", endpoint, err);
+                err
+            })
+        })
+        .map_err(|err| err.unwrap_or(<Error<T>>::EndpointNotConfigured))
+    }
+
+    fn get_env_var(env_var_name: &str) -> Option<String> {
         #[cfg(all(not(feature = "sgx"), feature = "std"))]
-        return std::env::var("IPFS_ROOT_URL").unwrap();
+        return std::env::var(env_var_name).ok().filter(|value| !value.is_empty());
 
         #[cfg(all(not(feature = "std"), feature = "sgx"))]
-        return sgx_tstd::env::var("IPFS_ROOT_URL").unwrap();
+        return sgx_tstd::env::var(env_var_name)
+            .ok()
+            .filter(|value| !value.is_empty());
+
+        // eg WASM: no env
+        #[cfg(all(not(feature = "std"), not(feature = "sgx")))]
+        return None;
     }
 
-    /// aux function: call API_ENDPOINT_DISPLAY_URL for either is_message or not
+    /// The display circuits layout: width, height, and one bbox(4 floats) per digit
+    /// Shared by the `CircuitGenerator` and the generation service; cf `call_grpc_display_one`
+    /// Also used by pallet-ocw-garble to read back the digits; cf `SelfCheckGarbledPackages`
     ///
-    /// return:
-    /// - IPFS hash
-    /// - number of digits
-    #[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
-    fn call_grpc_display_one<T: Config>(is_message: bool) -> Result<(String, u32), Error<T>> {
-        let ipfs_client = interstellar_ipfs_client::IpfsClientSpOffchain::new(&get_ipfs_uri())
-            .map_err(|err| {
-                log::error!("[ocw-circuits] ipfs client new error: {:?}", err);
-                <Error<T>>::IpfsClientCreationError
-            })?;
-
-        let (width, height, digits_bboxes) = if is_message {
-            (
-                DEFAULT_MESSAGE_WIDTH,
-                DEFAULT_MESSAGE_HEIGHT,
-                vec![
-                    // first digit bbox --------------------------------------------
-                    0.25_f32, 0.1_f32, 0.45_f32, 0.9_f32,
-                    // second digit bbox -------------------------------------------
-                    0.55_f32, 0.1_f32, 0.75_f32, 0.9_f32,
-                ],
-            )
+    /// param: nb_digits: `DisplayProfile::message_nb_digits` or `DisplayProfile::pinpad_nb_digits`
+    pub fn display_layout(is_message: bool, nb_digits: u32) -> (u32, u32, Vec<f32>) {
+        let nb_digits = nb_digits.max(1);
+        if is_message {
+            // centered, with a gap of half a digit b/w digits
+            // eg 2 digits: [0.25;0.45] and [0.55;0.75]
+            let digit_width = (0.8_f32 / (1.5 * nb_digits as f32 - 0.5)).min(0.2);
+            let start = (1.0 - digit_width * (1.5 * nb_digits as f32 - 0.5)) / 2.0;
+            let digits_bboxes = (0..nb_digits)
+                .flat_map(|i| {
+                    let x_min = start + 1.5 * digit_width * i as f32;
+                    [x_min, 0.1_f32, x_min + digit_width, 0.9_f32]
+                })
+                .collect();
+
+            (DEFAULT_MESSAGE_WIDTH, DEFAULT_MESSAGE_HEIGHT, digits_bboxes)
         } else {
-            // IMPORTANT: by convention the "pinpad" is 10 digits, placed horizontally(side by side)
-            // DO NOT change the layout, else wallet-app will NOT display the pinpad correctly!
-            // That is b/c this layout in treated as a "texture atlas" so the positions MUST be known.
-            // Ideally the positions SHOULD be passed from here all the way into the serialized .pgarbled/.packmsg
-            // but this NOT YET the case.
-
-            // 10 digits, 4 corners(vertices) per digit
-            let mut digits_bboxes: Vec<f32> = Vec::with_capacity(10 * 4);
-            /*
-            for (int i = 0; i < 10; i++) {
-                digits_bboxes.emplace_back(0.1f * i, 0.0f, 0.1f * (i + 1), 1.0f);
-            }
-            */
-            for i in 0..10 {
-                digits_bboxes.append(
-                    vec![
-                        0.1_f32 * i as f32,
-                        0.0_f32,
-                        0.1_f32 * (i + 1) as f32,
-                        1.0_f32,
-                    ]
-                    .as_mut(),
-                );
-            }
+            pinpad_display_layout(&PinpadLayout::row(nb_digits))
+        }
+    }
 
-            (DEFAULT_PINPAD_WIDTH, DEFAULT_PINPAD_HEIGHT, digits_bboxes)
-        };
+    /// The pinpad circuit layout: one bbox per digit cell, in pinpad order; cf `PinpadLayout`
+    /// The blank and "cancel" cells have no bbox: the circuit does NOT draw anything there.
+    ///
+    /// IMPORTANT: wallet-app treats the pinpad as a "texture atlas" so the positions MUST be known.
+    /// The layout is passed to it via `DisplayStrippedCircuitsPackage::pinpad_layout`; and it MUST
+    /// compute the same cells, else the taps would NOT match the digits shown!
+    ///
+    /// Each cell is DEFAULT_PINPAD_WIDTH/10 x DEFAULT_PINPAD_HEIGHT; eg 590x50 for the default 1x10 row
+    pub fn pinpad_display_layout(layout: &PinpadLayout) -> (u32, u32, Vec<f32>) {
+        let nb_rows = layout.nb_rows.max(1);
+        let nb_cols = layout.nb_cols.max(1);
+        let half_gutter = layout.gutter_permille as f32 / 1000.0 / 2.0;
+        // 4 corners(vertices) per digit
+        let digits_bboxes = layout
+            .digit_cells()
+            .flat_map(|(row, col)| {
+                [
+                    (col as f32 + half_gutter) / nb_cols as f32,
+                    (row as f32 + half_gutter) / nb_rows as f32,
+                    (col as f32 + 1.0 - half_gutter) / nb_cols as f32,
+                    (row as f32 + 1.0 - half_gutter) / nb_rows as f32,
+                ]
+            })
+            .collect();
 
-        let circuit = lib_circuits_rs::generate_display_circuit(width, height, &digits_bboxes)
-            .map_err(|err| {
-                log::error!(
-                    "[ocw-circuits] lib_circuits_rs::generate_display_circuit error: {:?} {:?}",
-                    err.to_string(),
-                    err
-                );
-                <Error<T>>::CircuitDisplayGenerateError
-            })?;
+        (
+            DEFAULT_PINPAD_WIDTH / 10 * nb_cols,
+            DEFAULT_PINPAD_HEIGHT * nb_rows,
+            digits_bboxes,
+        )
+    }
 
-        // then serialize "garb" and "packmsg"
-        let serialized_circuit = lib_circuits_rs::serialize(&circuit).map_err(|err| {
-            log::error!(
-                "[ocw-circuits] lib_circuits_rs::serialize error: {:?} {:?}",
-                err.to_string(),
-                err
-            );
-            <Error<T>>::CircuitSerializeError
-        })?;
+    pub(crate) fn display_layout_for_profile(
+        profile: &DisplayProfile,
+        is_message: bool,
+    ) -> (u32, u32, Vec<f32>) {
+        match is_message {
+            true => display_layout(true, profile.message_nb_digits),
+            false => pinpad_display_layout(&profile.pinpad_layout),
+        }
+    }
 
-        // the tests need the full body bytes to mock correctly...
-        #[cfg(test)]
-        let serialized_circuit = T::HookCallPostSerializedPackage::my_test_hook(serialized_circuit);
+    /// aux function: call API_ENDPOINT_DISPLAY_URL for either is_message or not
+    ///
+    /// return:
+    /// - IPFS hash
+    /// - number of digits
+    fn call_grpc_display_one<T: Config>(
+        profile: &DisplayProfile,
+        is_message: bool,
+    ) -> Result<(String, u32), Error<T>> {
+        let (width, height, digits_bboxes) = display_layout_for_profile(profile, is_message);
+
+        let serialized_circuit = T::CircuitGenerator::generate_display(
+            width,
+            height,
+            &digits_bboxes,
+            &profile.alphabet,
+        )
+        .map_err(|err| {
+            log::error!("[ocw-circuits] CircuitGenerator::generate_display error: {}", err);
+            <Error<T>>::CircuitDisplayGenerateError
+        })?;
 
-        let ipfs_add_response = ipfs_client.ipfs_add(&serialized_circuit).map_err(|err| {
-            log::error!("[ocw-circuits] ipfs call ipfs_add error: {:?}", err);
-            <Error<T>>::IpfsCallError
+        let ipfs_add_response = ipfs_call_with_failover::<T, _>(|ipfs_client| {
+            ipfs_client.ipfs_add(&serialized_circuit).map_err(|err| {
+                log::error!("[ocw-circuits] ipfs call ipfs_add error: {:?}", err);
+                <Error<T>>::IpfsCallError
+            })
         })?;
 
         // nb_digits: we send in the Request one "BBox" per digit(ie 4 floats)
-        // NOTE: if we are here we can guarantee the C++ has checked it was indeed valid BBox so we can / 4 and this is it
+        // NOTE: if we are here we can guarantee the generator has checked it was indeed valid BBox so we can / 4 and this is it
         Ok((
             ipfs_add_response.hash,
             (digits_bboxes.len() / 4).try_into().unwrap(),
@@ -0,0 +1,204 @@
+//! Storage migrations; called from `Hooks::on_runtime_upgrade`
+//! They are chained: v2 then v3 then v4; each one is skipped if the on-chain version is already there.
+//!
+//! Only `DisplaySkcdPackageValue` changed; so each one translates it from the previous version.
+//! NOTE: a value that can not be decoded is left as-is; `get_display_circuits_package` rejects it.
+
+/// v1 -> v2: `DisplaySkcdPackage::alphabet`
+pub mod v2 {
+    use super::v3;
+    use crate::pallet::*;
+    use circuits_storage_common::DisplayProfile;
+    use frame_support::pallet_prelude::*;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    /// v1 `DisplaySkcdPackage`
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, Default, MaxEncodedLen)]
+    pub struct OldDisplaySkcdPackage {
+        pub message_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub message_skcd_server_metadata_nb_digits: u32,
+        pub pinpad_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_skcd_server_metadata_nb_digits: u32,
+    }
+
+    /// `DisplaySkcdPackageValue` with its v1 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldDisplaySkcdPackageValue<T: Config> =
+        StorageValue<Pallet<T>, OldDisplaySkcdPackage, ValueQuery>;
+
+    /// Before v2 the circuits were always generated for the decimal digits
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 2 {
+            log::info!(
+                "[ocw-circuits] migration v2: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let res =
+            <v3::OldDisplaySkcdPackageValue<T>>::translate::<OldDisplaySkcdPackage, _>(|old| {
+                old.map(|old| v3::OldDisplaySkcdPackage {
+                    alphabet: DisplayProfile::decimal(old.message_skcd_server_metadata_nb_digits)
+                        .alphabet,
+                    message_skcd_cid: old.message_skcd_cid,
+                    message_skcd_server_metadata_nb_digits: old
+                        .message_skcd_server_metadata_nb_digits,
+                    pinpad_skcd_cid: old.pinpad_skcd_cid,
+                    pinpad_skcd_server_metadata_nb_digits: old
+                        .pinpad_skcd_server_metadata_nb_digits,
+                })
+            });
+        StorageVersion::new(2).put::<Pallet<T>>();
+
+        log::info!(
+            "[ocw-circuits] migration v2: DisplaySkcdPackageValue translated: {:?}",
+            res.map(|new| new.is_some())
+        );
+
+        T::DbWeight::get().reads_writes(2, 2)
+    }
+}
+
+/// v2 -> v3: `DisplaySkcdPackage::pinpad_layout`
+pub mod v3 {
+    use super::v4;
+    use crate::pallet::*;
+    use circuits_storage_common::{Alphabet, PinpadLayout};
+    use frame_support::pallet_prelude::*;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    /// v2 `DisplaySkcdPackage`
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, Default, MaxEncodedLen)]
+    pub struct OldDisplaySkcdPackage {
+        pub message_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub message_skcd_server_metadata_nb_digits: u32,
+        pub pinpad_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_skcd_server_metadata_nb_digits: u32,
+        pub alphabet: Alphabet,
+    }
+
+    /// `DisplaySkcdPackageValue` with its v2 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldDisplaySkcdPackageValue<T: Config> =
+        StorageValue<Pallet<T>, OldDisplaySkcdPackage, ValueQuery>;
+
+    /// Before v3 the pinpad was always one row; ie `PinpadLayout::row(10)` for the package from v1
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 3 {
+            log::info!(
+                "[ocw-circuits] migration v3: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let res =
+            <v4::OldDisplaySkcdPackageValue<T>>::translate::<OldDisplaySkcdPackage, _>(|old| {
+                old.map(|old| v4::OldDisplaySkcdPackage {
+                    pinpad_layout: PinpadLayout::row(old.pinpad_skcd_server_metadata_nb_digits),
+                    message_skcd_cid: old.message_skcd_cid,
+                    message_skcd_server_metadata_nb_digits: old
+                        .message_skcd_server_metadata_nb_digits,
+                    pinpad_skcd_cid: old.pinpad_skcd_cid,
+                    pinpad_skcd_server_metadata_nb_digits: old
+                        .pinpad_skcd_server_metadata_nb_digits,
+                    alphabet: old.alphabet,
+                })
+            });
+        StorageVersion::new(3).put::<Pallet<T>>();
+
+        log::info!(
+            "[ocw-circuits] migration v3: DisplaySkcdPackageValue translated: {:?}",
+            res.map(|new| new.is_some())
+        );
+
+        T::DbWeight::get().reads_writes(2, 2)
+    }
+}
+
+/// v3 -> v4: `DisplaySkcdPackage::message_capacity`
+pub mod v4 {
+    use crate::pallet::*;
+    use circuits_storage_common::{Alphabet, DisplaySkcdPackage, MessageCapacity, PinpadLayout};
+    use frame_support::pallet_prelude::*;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+    #[cfg(feature = "try-runtime")]
+    use sp_std::vec::Vec;
+
+    /// v3 `DisplaySkcdPackage`
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, Default, MaxEncodedLen)]
+    pub struct OldDisplaySkcdPackage {
+        pub message_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub message_skcd_server_metadata_nb_digits: u32,
+        pub pinpad_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_skcd_server_metadata_nb_digits: u32,
+        pub alphabet: Alphabet,
+        pub pinpad_layout: PinpadLayout,
+    }
+
+    /// `DisplaySkcdPackageValue` with its v3 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldDisplaySkcdPackageValue<T: Config> =
+        StorageValue<Pallet<T>, OldDisplaySkcdPackage, ValueQuery>;
+
+    /// The generator did NOT report it before v4; so `MessageCapacity::default`
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 4 {
+            log::info!(
+                "[ocw-circuits] migration v4: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let res = <DisplaySkcdPackageValue<T>>::translate::<OldDisplaySkcdPackage, _>(|old| {
+            old.map(|old| DisplaySkcdPackage {
+                message_skcd_cid: old.message_skcd_cid,
+                message_skcd_server_metadata_nb_digits: old.message_skcd_server_metadata_nb_digits,
+                pinpad_skcd_cid: old.pinpad_skcd_cid,
+                pinpad_skcd_server_metadata_nb_digits: old.pinpad_skcd_server_metadata_nb_digits,
+                alphabet: old.alphabet,
+                pinpad_layout: old.pinpad_layout,
+                message_capacity: MessageCapacity::default(),
+            })
+        });
+        StorageVersion::new(4).put::<Pallet<T>>();
+
+        log::info!(
+            "[ocw-circuits] migration v4: DisplaySkcdPackageValue translated: {:?}",
+            res.map(|new| new.is_some())
+        );
+
+        T::DbWeight::get().reads_writes(2, 2)
+    }
+
+    /// For the whole chain(v2, v3, v4)
+    /// return: if `DisplaySkcdPackageValue` exists; then it MUST be decodable after the migrations
+    #[cfg(feature = "try-runtime")]
+    pub fn pre_upgrade<T: Config>() -> Result<Vec<u8>, &'static str> {
+        Ok(<DisplaySkcdPackageValue<T>>::exists().encode())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    pub fn post_upgrade<T: Config>(state: Vec<u8>) -> Result<(), &'static str> {
+        ensure!(
+            Pallet::<T>::on_chain_storage_version() >= 4,
+            "[ocw-circuits] migration v4: wrong on-chain version"
+        );
+
+        let existed: bool =
+            Decode::decode(&mut state.as_slice()).map_err(|_err| "invalid pre_upgrade state")?;
+        if existed {
+            ensure!(
+                <DisplaySkcdPackageValue<T>>::try_get().is_ok(),
+                "[ocw-circuits] migration v4: DisplaySkcdPackageValue was not translated"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate as pallet_ocw_circuits;
 use crate::*;
+use circuits_storage_common::DisplayProfile;
 use frame_support::{
     parameter_types,
     traits::{ConstU32, ConstU64},
@@ -94,15 +95,43 @@ where
 
 parameter_types! {
     pub const UnsignedPriority: u64 = 1 << 20;
+    // "static" so that the tests can flip it cf `CircuitGeneratorAvailable::set`
+    // NOTE: also used by MockCircuitGenerator ie both the on-chain and the offchain side
+    pub static CircuitGeneratorAvailable: bool = true;
+    pub static MockDisplayProfile: DisplayProfile = DisplayProfile::default();
 }
 
 const OVERWRITTEN_SERIALIZED_IPFS_ADD: &[u8] = &[40, 41, 42, 43, 44];
 
-pub struct MyTestCallbackMock;
-impl MyTestCallback for MyTestCallbackMock {
-    fn my_test_hook(_input: Vec<u8>) -> Vec<u8> {
-        // MUST match mock_ipfs_add_response
-        OVERWRITTEN_SERIALIZED_IPFS_ADD.to_vec()
+/// Fast fake: NO need for "circuit-gen-rs"
+pub struct MockCircuitGenerator;
+impl CircuitGenerator for MockCircuitGenerator {
+    fn is_available() -> bool {
+        CircuitGeneratorAvailable::get()
+    }
+
+    fn generate_generic(verilog_buf: &[u8]) -> Result<Vec<u8>, String> {
+        // cf MockType::InvalidVerilog
+        match std::str::from_utf8(verilog_buf) {
+            Ok(verilog) if verilog.contains("module") => {
+                // MUST match mock_ipfs_add_response
+                Ok(OVERWRITTEN_SERIALIZED_IPFS_ADD.to_vec())
+            }
+            _ => Err("MockCircuitGenerator: not a Verilog file".to_string()),
+        }
+    }
+
+    fn generate_display(
+        _width: u32,
+        _height: u32,
+        _digits_bboxes: &[f32],
+        _alphabet: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        Ok(OVERWRITTEN_SERIALIZED_IPFS_ADD.to_vec())
+    }
+
+    fn message_capacity(width: u32, _height: u32) -> Result<MessageCapacity, String> {
+        Ok(pallet_ocw_circuits::lib_circuits_rs_message_capacity(width))
     }
 }
 
@@ -110,7 +139,14 @@ impl pallet_ocw_circuits::Config for Test {
     type RuntimeEvent = RuntimeEvent;
     type RuntimeCall = RuntimeCall;
     type AuthorityId = crypto::TestAuthId;
-    type HookCallPostSerializedPackage = MyTestCallbackMock;
+    type IpfsRootUrl = ();
+    type IpfsRequestTimeoutMs = ConstU64<10_000>;
+    type OnNewDisplaySkcdPackage = ();
+    type GenerationServiceUrl = ();
+    type CircuitGeneratorAvailable = CircuitGeneratorAvailable;
+    type UnsignedPriority = UnsignedPriority;
+    type CircuitGenerator = MockCircuitGenerator;
+    type DisplayProfile = MockDisplayProfile;
 }
 
 pub(crate) enum MockType {
@@ -120,6 +156,8 @@ pub(crate) enum MockType {
     InvalidVerilog,
     /// error case: can not connect to IPFS,
     IpfsDown,
+    /// no HTTP request expected; eg the tests NOT running the generation
+    NoRequests,
 }
 
 // Build genesis storage according to the mock runtime.
@@ -272,6 +310,7 @@ pub(crate) async fn new_test_ext(
                 });
             }
         }
+        MockType::NoRequests => {}
     };
 
     (t,)
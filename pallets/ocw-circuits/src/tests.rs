@@ -2,7 +2,9 @@
 use frame_support::pallet_prelude::DispatchError;
 use frame_support::pallet_prelude::Hooks;
 use frame_support::{assert_err, assert_ok};
-use sp_core::Encode;
+use frame_support::unsigned::ValidateUnsigned;
+use sp_core::{Encode, Pair};
+use sp_runtime::transaction_validity::{InvalidTransaction, TransactionSource, TransactionValidity};
 use sp_runtime::ModuleError;
 
 use crate::mock::*;
@@ -15,6 +17,13 @@ fn test_pub() -> sp_core::sr25519::Public {
     sp_core::sr25519::Public::from_raw([1u8; 32])
 }
 
+/// What `submit_config_display_circuits_package_signed` queues
+fn display_job() -> GrpcCallKind {
+    GrpcCallKind::Display {
+        profile: MockDisplayProfile::get(),
+    }
+}
+
 fn prepare_ocw_storage(call_kind: GrpcCallKind) {
     // TODO? https://github.com/paritytech/substrate/blob/033d4e86cc7eff0066cd376b9375f815761d653c/frame/merkle-mountain-range/src/tests.rs#L38C1-L46C2
     //      fn new_block() {
@@ -35,11 +44,11 @@ fn prepare_ocw_storage(call_kind: GrpcCallKind) {
     assert_eq!(sp_io::offchain::local_storage_get(storage_kind, &key), None);
     let data = IndexingData {
         grpc_kind: call_kind,
+        request_id: 0,
     };
     sp_io::offchain::local_storage_set(storage_kind, &key, &data.encode());
 }
 
-#[cfg(feature = "circuit-gen-rs")]
 async fn test_submit_config_generic_signed(
     mock_type: MockType,
 ) -> (
@@ -68,7 +77,6 @@ async fn test_submit_config_generic_signed(
     (res, t)
 }
 
-#[cfg(feature = "circuit-gen-rs")]
 async fn test_submit_config_display_circuits_package_signed(
     mock_type: MockType,
 ) -> (
@@ -86,7 +94,7 @@ async fn test_submit_config_display_circuits_package_signed(
         // TODO how to CHECK "append_or_replace_verilog_hash"
         // System::assert_last_event(crate::Event::NewMobileRegistered { account_id: 1 }.into());
 
-        prepare_ocw_storage(GrpcCallKind::Display);
+        prepare_ocw_storage(display_job());
 
         res
     });
@@ -95,7 +103,6 @@ async fn test_submit_config_display_circuits_package_signed(
 }
 
 /// If IPFS is down; it MUST NOT panic/crash/etc
-#[cfg(feature = "circuit-gen-rs")]
 #[tokio::test]
 #[serial_test::serial]
 async fn test_submit_config_generic_signed_ipfs_down_does_not_panic() {
@@ -112,7 +119,6 @@ async fn test_submit_config_generic_signed_ipfs_down_does_not_panic() {
 }
 
 /// If IPFS is down; it MUST NOT panic/crash/etc
-#[cfg(feature = "circuit-gen-rs")]
 #[tokio::test]
 #[serial_test::serial]
 async fn test_submit_config_display_circuits_package_signed_ipfs_down_does_not_panic() {
@@ -123,13 +129,17 @@ async fn test_submit_config_display_circuits_package_signed_ipfs_down_does_not_p
 
     t.execute_with(|| {
         PalletOcwCircuits::offchain_worker(1);
-    });
 
-    // TODO check error in logs? other way?
+        // NOT dropped: retried by the next offchain_worker
+        assert!(sp_io::offchain::local_storage_get(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            &PalletOcwCircuits::derived_key()
+        )
+        .is_some());
+    });
 }
 
 /// [generic] If the given IPFS hash is NOT a valid Verilog file; it should fail gracefully
-#[cfg(feature = "circuit-gen-rs")]
 #[tokio::test]
 #[serial_test::serial]
 async fn test_submit_config_generic_signed_not_a_verilog_file_does_not_panic() {
@@ -143,7 +153,6 @@ async fn test_submit_config_generic_signed_not_a_verilog_file_does_not_panic() {
     });
 }
 
-#[cfg(feature = "circuit-gen-rs")]
 #[tokio::test]
 #[serial_test::serial]
 async fn test_submit_config_generic_signed_ok() {
@@ -157,9 +166,6 @@ async fn test_submit_config_generic_signed_ok() {
     });
 }
 
-/// NOTE: this test is slow!
-/// on local machine: finished in 211.52s
-#[cfg(feature = "circuit-gen-rs")]
 #[tokio::test]
 #[serial_test::serial]
 async fn test_submit_config_display_circuits_package_signed_ok() {
@@ -176,7 +182,6 @@ async fn test_submit_config_display_circuits_package_signed_ok() {
 
 /// Starting multiple offchain_worker SHOULD get "nothing to do, returning..."
 // TODO this is not ideal; we should start the jobs in parallel; BUT it is better than nothing
-#[cfg(feature = "circuit-gen-rs")]
 #[tokio::test]
 #[serial_test::serial]
 async fn test_only_one_job_can_be_running() {
@@ -190,3 +195,850 @@ async fn test_only_one_job_can_be_running() {
         PalletOcwCircuits::offchain_worker(2);
     });
 }
+
+/// The offchain local storage MUST take priority over the env var;
+/// and missing config MUST NOT panic
+#[tokio::test]
+#[serial_test::serial]
+async fn test_get_ipfs_uri_local_storage_then_env() {
+    let (mut t,) = new_test_ext(MockType::DisplayValid, false).await;
+    t.execute_with(|| {
+        // set by "new_test_ext"
+        assert_eq!(
+            crate::get_ipfs_uri::<Test>().unwrap(),
+            "http://127.0.0.1:4242"
+        );
+
+        sp_io::offchain::local_storage_set(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            crate::IPFS_ROOT_URL_LOCAL_STORAGE_KEY,
+            b"http://local-storage",
+        );
+        assert_eq!(
+            crate::get_ipfs_uri::<Test>().unwrap(),
+            "http://local-storage"
+        );
+
+        sp_io::offchain::local_storage_clear(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            crate::IPFS_ROOT_URL_LOCAL_STORAGE_KEY,
+        );
+        std::env::remove_var("IPFS_ROOT_URL");
+        assert!(matches!(
+            crate::get_ipfs_uri::<Test>(),
+            Err(crate::Error::<Test>::EndpointNotConfigured)
+        ));
+    });
+}
+
+fn test_display_payload(
+    public: sp_core::sr25519::Public,
+) -> crate::NewDisplayCircuitsPackagePayload<sp_core::sr25519::Public> {
+    crate::NewDisplayCircuitsPackagePayload {
+        message_skcd_cid: b"MESSAGE_SKCD_CID".to_vec(),
+        message_nb_digits: 2,
+        pinpad_skcd_cid: b"PINPAD_SKCD_CID".to_vec(),
+        pinpad_nb_digits: 10,
+        alphabet: (0..10).collect(),
+        pinpad_layout: circuits_storage_common::PinpadLayout::row(10),
+        message_capacity: crate::lib_circuits_rs_message_capacity(crate::display_layout(true, 2).0),
+        request_id: 42,
+        public,
+    }
+}
+
+/// What the offchain worker would send, signed with `pair`
+fn test_display_call(
+    pair: &sp_core::sr25519::Pair,
+    public: sp_core::sr25519::Public,
+) -> crate::Call<Test> {
+    let payload = test_display_payload(public);
+    let signature = payload.using_encoded(|encoded| pair.sign(encoded));
+    crate::Call::callback_new_display_circuits_package_unsigned { payload, signature }
+}
+
+fn validate(call: &crate::Call<Test>) -> TransactionValidity {
+    PalletOcwCircuits::validate_unsigned(TransactionSource::Local, call)
+}
+
+/// The event MUST contain the full package; and the `request_id` given by the offchain worker
+#[test]
+fn test_callback_new_display_circuits_package_event() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        System::set_block_number(1);
+
+        // NOTE: the signature is checked in `validate_unsigned`, NOT in the call itself
+        assert_ok!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                test_display_payload(test_pub()),
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            )
+        );
+
+        let package = crate::get_display_circuits_package::<Test>().unwrap();
+        assert_eq!(package.message_skcd_cid.to_vec(), b"MESSAGE_SKCD_CID".to_vec());
+        System::assert_last_event(
+            crate::Event::NewDisplaySkcdPackage {
+                package,
+                created_at: 1,
+                request_id: 42,
+            }
+            .into(),
+        );
+    });
+}
+
+/// v1 -> v4: the package MUST still be usable by ocw-garble(decimal, one row, default capacity)
+#[test]
+fn test_migrations_from_v1_translate_display_package() {
+    use crate::migrations::v2::{OldDisplaySkcdPackage, OldDisplaySkcdPackageValue};
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        StorageVersion::new(1).put::<PalletOcwCircuits>();
+        OldDisplaySkcdPackageValue::<Test>::put(OldDisplaySkcdPackage {
+            message_skcd_cid: b"MESSAGE_SKCD_CID".to_vec().try_into().unwrap(),
+            message_skcd_server_metadata_nb_digits: 2,
+            pinpad_skcd_cid: b"PINPAD_SKCD_CID".to_vec().try_into().unwrap(),
+            pinpad_skcd_server_metadata_nb_digits: 10,
+        });
+
+        <PalletOcwCircuits as Hooks<u64>>::on_runtime_upgrade();
+
+        assert_eq!(PalletOcwCircuits::on_chain_storage_version(), 4);
+        let package = crate::get_display_circuits_package::<Test>().unwrap();
+        assert_eq!(package.message_skcd_cid.to_vec(), b"MESSAGE_SKCD_CID".to_vec());
+        assert_eq!(
+            package.display_profile(),
+            circuits_storage_common::DisplayProfile::decimal(2)
+        );
+        assert_eq!(
+            package.pinpad_layout,
+            circuits_storage_common::PinpadLayout::row(10)
+        );
+        assert_eq!(
+            package.message_capacity,
+            circuits_storage_common::MessageCapacity::default()
+        );
+    });
+}
+
+/// Already migrated: NOTHING must be touched
+#[test]
+fn test_migrations_skipped_if_up_to_date() {
+    use frame_support::traits::StorageVersion;
+
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        StorageVersion::new(4).put::<PalletOcwCircuits>();
+        assert_ok!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                test_display_payload(test_pub()),
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            )
+        );
+        let before = crate::get_display_circuits_package::<Test>().unwrap();
+
+        <PalletOcwCircuits as Hooks<u64>>::on_runtime_upgrade();
+
+        assert_eq!(crate::get_display_circuits_package::<Test>().unwrap(), before);
+    });
+}
+
+/// The same result sent by several authorities MUST have the same "provides" tag
+#[test]
+fn test_validate_unsigned_authorities_deduplicated() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        let pair_a = sp_core::sr25519::Pair::from_seed(&[1; 32]);
+        let pair_b = sp_core::sr25519::Pair::from_seed(&[2; 32]);
+        assert_ok!(PalletOcwCircuits::set_ocw_authorities(
+            RuntimeOrigin::root(),
+            vec![pair_a.public(), pair_b.public()]
+        ));
+
+        let valid_a = validate(&test_display_call(&pair_a, pair_a.public())).unwrap();
+        let valid_b = validate(&test_display_call(&pair_b, pair_b.public())).unwrap();
+        assert_eq!(valid_a.provides, valid_b.provides);
+        assert_eq!(valid_a.priority, UnsignedPriority::get());
+    });
+}
+
+#[test]
+fn test_validate_unsigned_not_an_authority_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        let pair = sp_core::sr25519::Pair::from_seed(&[1; 32]);
+
+        assert_eq!(
+            validate(&test_display_call(&pair, pair.public())),
+            Err(InvalidTransaction::BadSigner.into())
+        );
+    });
+}
+
+#[test]
+fn test_validate_unsigned_bad_signature_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        let pair_a = sp_core::sr25519::Pair::from_seed(&[1; 32]);
+        let pair_b = sp_core::sr25519::Pair::from_seed(&[2; 32]);
+        assert_ok!(PalletOcwCircuits::set_ocw_authorities(
+            RuntimeOrigin::root(),
+            vec![pair_a.public()]
+        ));
+
+        // signed by b, claiming to be a
+        assert_eq!(
+            validate(&test_display_call(&pair_b, pair_a.public())),
+            Err(InvalidTransaction::BadProof.into())
+        );
+    });
+}
+
+/// Else anyone could publish its own circuits
+#[test]
+fn test_set_ocw_authorities_root_only() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        assert_err!(
+            PalletOcwCircuits::set_ocw_authorities(
+                RuntimeOrigin::signed(test_pub()),
+                vec![test_pub()]
+            ),
+            DispatchError::BadOrigin
+        );
+    });
+}
+
+/// The callbacks MUST only be signed by the local keys that are `OcwAuthorities`
+#[test]
+fn test_local_authority_keys() {
+    use sp_keystore::SyncCryptoStore;
+
+    let keystore = sp_keystore::testing::KeyStore::new();
+    let authority = keystore
+        .sr25519_generate_new(crate::KEY_TYPE, Some("//Alice"))
+        .unwrap();
+    keystore
+        .sr25519_generate_new(crate::KEY_TYPE, Some("//Bob"))
+        .unwrap();
+
+    let mut t = sp_io::TestExternalities::default();
+    t.register_extension(sp_keystore::KeystoreExt(std::sync::Arc::new(keystore)));
+    t.execute_with(|| {
+        assert_eq!(crate::local_authority_keys::<Test, crate::crypto::TestAuthId>(), vec![]);
+
+        assert_ok!(PalletOcwCircuits::set_ocw_authorities(
+            RuntimeOrigin::root(),
+            vec![authority]
+        ));
+        assert_eq!(crate::local_authority_keys::<Test, crate::crypto::TestAuthId>(), vec![authority]);
+    });
+}
+
+/// Runtimes without any generator MUST fail cleanly; NOT panic
+#[test]
+fn test_submit_config_generator_unavailable_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        CircuitGeneratorAvailable::set(false);
+
+        assert_err!(
+            PalletOcwCircuits::submit_config_display_circuits_package_signed(
+                RuntimeOrigin::signed(test_pub()),
+            ),
+            crate::Error::<Test>::GeneratorUnavailable
+        );
+        assert_err!(
+            PalletOcwCircuits::submit_config_generic_signed(
+                RuntimeOrigin::signed(test_pub()),
+                "PLACEHOLDER_HASH".into(),
+            ),
+            crate::Error::<Test>::GeneratorUnavailable
+        );
+
+        CircuitGeneratorAvailable::set(true);
+    });
+}
+
+/// eg a typo in the runtime config MUST be caught before queuing the job
+#[test]
+fn test_submit_config_display_invalid_profile_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        MockDisplayProfile::set(circuits_storage_common::DisplayProfile::decimal(0));
+
+        assert_err!(
+            PalletOcwCircuits::submit_config_display_circuits_package_signed(
+                RuntimeOrigin::signed(test_pub()),
+            ),
+            crate::Error::<Test>::InvalidDisplayProfile
+        );
+
+        MockDisplayProfile::set(Default::default());
+    });
+}
+
+/// The pinpad shows each symbol once: its size MUST match the alphabet
+#[test]
+fn test_callback_new_display_circuits_package_alphabet_mismatch_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        let payload = crate::NewDisplayCircuitsPackagePayload {
+            alphabet: (0..16).collect(),
+            ..test_display_payload(test_pub())
+        };
+
+        assert_err!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                payload,
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            ),
+            crate::Error::<Test>::InvalidDisplayProfile
+        );
+        assert!(crate::get_display_circuits_package::<Test>().is_err());
+    });
+}
+
+#[test]
+fn test_display_layout_follows_profile() {
+    let profile = circuits_storage_common::DisplayProfile::hexadecimal(6);
+
+    let (_width, _height, message_bboxes) = crate::display_layout_for_profile(&profile, true);
+    assert_eq!(message_bboxes.len(), 6 * 4);
+    // all the digits MUST fit; and NOT overlap
+    assert!(message_bboxes.chunks_exact(4).all(|bbox| bbox[0] >= 0.0 && bbox[2] <= 1.0));
+    assert!(message_bboxes
+        .chunks_exact(4)
+        .zip(message_bboxes.chunks_exact(4).skip(1))
+        .all(|(bbox, next_bbox)| bbox[2] < next_bbox[0]));
+
+    let (width, height, pinpad_bboxes) = crate::display_layout_for_profile(&profile, false);
+    assert_eq!(pinpad_bboxes.len(), 16 * 4);
+    assert_eq!((width, height), (944, 50));
+}
+
+/// eg a phone keypad: 10 digits in a 4x3 grid; the blank and "cancel" cells are NOT drawn
+#[test]
+fn test_pinpad_display_layout_grid() {
+    use circuits_storage_common::{PinpadCell, PinpadLayout};
+
+    let layout = PinpadLayout::phone();
+    let (width, height, pinpad_bboxes) = crate::pinpad_display_layout(&layout);
+    assert_eq!((width, height), (177, 200));
+    assert_eq!(pinpad_bboxes.len(), 10 * 4);
+    // inside the grid cells, with the gutter
+    assert!(pinpad_bboxes
+        .chunks_exact(4)
+        .all(|bbox| bbox[0] > 0.0 && bbox[1] > 0.0 && bbox[2] < 1.0 && bbox[3] < 1.0));
+    // the last digit is in the middle of the bottom row
+    assert!(pinpad_bboxes[9 * 4..]
+        .iter()
+        .zip([1.05 / 3.0, 3.05 / 4.0, 1.95 / 3.0, 3.95 / 4.0])
+        .all(|(coord, expected)| (coord - expected).abs() < 1e-6));
+
+    // a tap on a digit cell MUST give the position of its bbox ie what `check_input` expects
+    for (digit_index, (row, col)) in layout.digit_cells().enumerate() {
+        assert_eq!(
+            layout.cell(row, col),
+            Some(PinpadCell::Digit(digit_index as u32))
+        );
+    }
+    assert_eq!(layout.cell(3, 0), Some(PinpadCell::Blank));
+    assert_eq!(layout.cell(3, 2), Some(PinpadCell::Cancel));
+    assert_eq!(layout.cell(4, 0), None);
+
+    // the default 1x10 row is unchanged
+    assert_eq!(
+        crate::pinpad_display_layout(&PinpadLayout::row(10)),
+        crate::display_layout(false, 10)
+    );
+    assert_eq!(crate::display_layout(false, 10).0, 590);
+}
+
+/// Each digit MUST have its own cell, with none left over
+#[test]
+fn test_pinpad_layout_is_valid() {
+    use circuits_storage_common::{DisplayProfile, PinpadLayout};
+
+    assert!(DisplayProfile::decimal(6)
+        .with_pinpad_layout(PinpadLayout::phone())
+        .is_valid());
+    assert!(DisplayProfile::hexadecimal(6)
+        .with_pinpad_layout(PinpadLayout::grid(4, 4))
+        .is_valid());
+    // 12 cells for 10 digits
+    assert!(!DisplayProfile::decimal(6)
+        .with_pinpad_layout(PinpadLayout::grid(3, 4))
+        .is_valid());
+    assert!(!DisplayProfile::hexadecimal(6)
+        .with_pinpad_layout(PinpadLayout::phone())
+        .is_valid());
+    // the same cell twice
+    assert!(!PinpadLayout {
+        cancel_cell: Some(9),
+        ..PinpadLayout::phone()
+    }
+    .is_valid(10));
+    // outside the grid
+    assert!(!PinpadLayout {
+        cancel_cell: Some(12),
+        ..PinpadLayout::phone()
+    }
+    .is_valid(10));
+    assert!(!PinpadLayout {
+        gutter_permille: 600,
+        ..PinpadLayout::phone()
+    }
+    .is_valid(10));
+}
+
+/// The layout MUST match the pinpad; else the wallet would map the taps to the wrong digits
+#[test]
+fn test_callback_new_display_circuits_package_layout_mismatch_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        let payload = crate::NewDisplayCircuitsPackagePayload {
+            pinpad_layout: circuits_storage_common::PinpadLayout::grid(4, 4),
+            ..test_display_payload(test_pub())
+        };
+
+        assert_err!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                payload,
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            ),
+            crate::Error::<Test>::InvalidDisplayProfile
+        );
+        assert!(crate::get_display_circuits_package::<Test>().is_err());
+    });
+}
+
+/// A message circuit that can NOT show anything is a generator bug; ocw-garble would reject every `tx_msg`
+#[test]
+fn test_callback_new_display_circuits_package_invalid_message_capacity_err() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        let payload = crate::NewDisplayCircuitsPackagePayload {
+            message_capacity: circuits_storage_common::MessageCapacity {
+                max_chars: 0,
+                ..Default::default()
+            },
+            ..test_display_payload(test_pub())
+        };
+
+        assert_err!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                payload,
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            ),
+            crate::Error::<Test>::InvalidMessageCapacity
+        );
+        assert!(crate::get_display_circuits_package::<Test>().is_err());
+    });
+}
+
+/// The cids come from the user(or the offchain worker): they MUST NOT panic
+#[test]
+fn test_invalid_cids_do_not_panic() {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        assert_err!(
+            PalletOcwCircuits::submit_config_generic_signed(
+                RuntimeOrigin::signed(test_pub()),
+                vec![0xff, 0xfe],
+            ),
+            crate::Error::<Test>::DeserializeError
+        );
+
+        assert_err!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                crate::NewDisplayCircuitsPackagePayload {
+                    pinpad_skcd_cid: vec![0xff, 0xfe],
+                    ..test_display_payload(test_pub())
+                },
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            ),
+            crate::Error::<Test>::DeserializeError
+        );
+        assert_err!(
+            PalletOcwCircuits::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                crate::NewDisplayCircuitsPackagePayload {
+                    message_skcd_cid: vec![b'Q'; 65],
+                    ..test_display_payload(test_pub())
+                },
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            ),
+            crate::Error::<Test>::IpfsCallError
+        );
+        assert!(crate::get_display_circuits_package::<Test>().is_err());
+    });
+}
+
+/// The job is always queued on-chain; whatever the generator
+#[tokio::test]
+#[serial_test::serial]
+async fn test_submit_config_display_circuits_package_signed_queues_job() {
+    let (mut t,) = new_test_ext(MockType::NoRequests, false).await;
+    t.execute_with(|| {
+        System::set_block_number(1);
+
+        assert_ok!(
+            PalletOcwCircuits::submit_config_display_circuits_package_signed(
+                RuntimeOrigin::signed(test_pub()),
+            )
+        );
+
+        System::assert_last_event(
+            crate::Event::CircuitsGenerationRequested {
+                account_id: test_pub(),
+                request_id: 0,
+            }
+            .into(),
+        );
+    });
+}
+
+/// A node without a generator MUST NOT drop the job
+#[tokio::test]
+#[serial_test::serial]
+async fn test_offchain_worker_without_generator_keeps_job() {
+    let (mut t,) = new_test_ext(MockType::NoRequests, false).await;
+    t.execute_with(|| {
+        CircuitGeneratorAvailable::set(false);
+        prepare_ocw_storage(display_job());
+
+        PalletOcwCircuits::offchain_worker(1);
+
+        assert!(sp_io::offchain::local_storage_get(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            &PalletOcwCircuits::derived_key()
+        )
+        .is_some());
+
+        CircuitGeneratorAvailable::set(true);
+    });
+}
+
+fn call_generation_service(
+    url: &str,
+    grpc_kind: GrpcCallKind,
+) -> Result<crate::LibCircuitsRsResultKind, crate::Error<Test>> {
+    crate::generation_service::call_generation_service::<
+        Test,
+        interstellar_http_client::ClientHttpReq,
+    >(url, grpc_kind)
+}
+
+#[test]
+fn test_generation_service_generic_ok() {
+    let mock_server = httpmock::MockServer::start();
+    let generic_mock = mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/generic")
+            .json_body(serde_json::json!({ "verilog_cid": "QmVERILOG" }));
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmSKCD"}"#);
+    });
+
+    match call_generation_service(
+        &mock_server.base_url(),
+        GrpcCallKind::Generic {
+            verilog_cid: "QmVERILOG".into(),
+        },
+    ) {
+        Ok(crate::LibCircuitsRsResultKind::Generic { ipfs_hash }) => {
+            assert_eq!(ipfs_hash, "QmSKCD")
+        }
+        _ => panic!("unexpected result"),
+    }
+    generic_mock.assert();
+}
+
+#[test]
+fn test_generation_service_display_ok() {
+    let mock_server = httpmock::MockServer::start();
+    let message_mock = mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":640,"height":360}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"skcd_cid":"QmMESSAGE","nb_digits":2,"max_chars":32,"glyphs":"0123456789 #x"}"#,
+            );
+    });
+    let pinpad_mock = mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":590,"height":50}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmPINPAD","nb_digits":10}"#);
+    });
+
+    match call_generation_service(&mock_server.base_url(), display_job()) {
+        Ok(crate::LibCircuitsRsResultKind::Display {
+            message_ipfs_hash,
+            message_nb_digits,
+            pinpad_ipfs_hash,
+            pinpad_nb_digits,
+            alphabet,
+            pinpad_layout,
+            message_capacity,
+        }) => {
+            assert_eq!(message_ipfs_hash, "QmMESSAGE");
+            assert_eq!(message_nb_digits, 2);
+            assert_eq!(pinpad_ipfs_hash, "QmPINPAD");
+            assert_eq!(pinpad_nb_digits, 10);
+            assert_eq!(alphabet, (0..10).collect::<Vec<u8>>());
+            assert_eq!(
+                pinpad_layout,
+                circuits_storage_common::PinpadLayout::row(10)
+            );
+            assert_eq!(message_capacity.max_chars, 32);
+        }
+        _ => panic!("unexpected result"),
+    }
+    message_mock.assert();
+    pinpad_mock.assert();
+}
+
+/// The service draws the symbols of the profile; eg a 16 cells pinpad for hexadecimal
+#[test]
+fn test_generation_service_display_hexadecimal_ok() {
+    let mock_server = httpmock::MockServer::start();
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":640,"height":360}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"skcd_cid":"QmMESSAGE","nb_digits":6,"max_chars":32,"glyphs":"0123456789 #x"}"#,
+            );
+    });
+    let pinpad_mock = mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(
+                r#"{"width":944,"alphabet":[0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15]}"#,
+            );
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmPINPAD","nb_digits":16}"#);
+    });
+
+    let grpc_kind = GrpcCallKind::Display {
+        profile: circuits_storage_common::DisplayProfile::hexadecimal(6),
+    };
+    match call_generation_service(&mock_server.base_url(), grpc_kind) {
+        Ok(crate::LibCircuitsRsResultKind::Display {
+            message_nb_digits,
+            pinpad_nb_digits,
+            alphabet,
+            ..
+        }) => {
+            assert_eq!(message_nb_digits, 6);
+            assert_eq!(pinpad_nb_digits, 16);
+            assert_eq!(alphabet.len(), 16);
+        }
+        _ => panic!("unexpected result"),
+    }
+    pinpad_mock.assert();
+}
+
+/// The grid is sent to the service as bboxes; and recorded in the result
+#[test]
+fn test_generation_service_display_phone_layout_ok() {
+    let mock_server = httpmock::MockServer::start();
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":640,"height":360}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"skcd_cid":"QmMESSAGE","nb_digits":6,"max_chars":32,"glyphs":"0123456789 #x"}"#,
+            );
+    });
+    let pinpad_mock = mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":177,"height":200}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmPINPAD","nb_digits":10}"#);
+    });
+
+    let grpc_kind = GrpcCallKind::Display {
+        profile: circuits_storage_common::DisplayProfile::decimal(6)
+            .with_pinpad_layout(circuits_storage_common::PinpadLayout::phone()),
+    };
+    match call_generation_service(&mock_server.base_url(), grpc_kind) {
+        Ok(crate::LibCircuitsRsResultKind::Display {
+            pinpad_nb_digits,
+            pinpad_layout,
+            ..
+        }) => {
+            assert_eq!(pinpad_nb_digits, 10);
+            assert_eq!(
+                pinpad_layout,
+                circuits_storage_common::PinpadLayout::phone()
+            );
+        }
+        _ => panic!("unexpected result"),
+    }
+    pinpad_mock.assert();
+}
+
+/// The capacity reported by the service is recorded; ocw-garble checks the `tx_msg` against it
+#[test]
+fn test_generation_service_display_message_capacity_ok() {
+    let mock_server = httpmock::MockServer::start();
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":640,"height":360}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"skcd_cid":"QmMESSAGE","nb_digits":2,"max_chars":32,"glyphs":"0123456789 #x"}"#,
+            );
+    });
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/display")
+            .json_body_partial(r#"{"width":590,"height":50}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmPINPAD","nb_digits":10}"#);
+    });
+
+    match call_generation_service(&mock_server.base_url(), display_job()) {
+        Ok(crate::LibCircuitsRsResultKind::Display {
+            message_capacity, ..
+        }) => {
+            assert_eq!(message_capacity.max_chars, 32);
+            assert_eq!(message_capacity.glyphs.to_vec(), b"0123456789 #x".to_vec());
+            assert_eq!(message_capacity.check("42 #7 to 0x01"), Ok(()));
+            assert_eq!(
+                message_capacity.check("42 to 0xd43593c7"),
+                Err(circuits_storage_common::MessageCapacityError::UnsupportedCharacter)
+            );
+        }
+        _ => panic!("unexpected result"),
+    }
+}
+
+/// The glyphs are ASCII; anything else is an invalid response
+#[test]
+fn test_generation_service_display_non_ascii_glyphs_err() {
+    let mock_server = httpmock::MockServer::start();
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST).path("/display");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"skcd_cid":"QmMESSAGE","nb_digits":2,"max_chars":32,"glyphs":"0123456789é"}"#,
+            );
+    });
+
+    assert!(matches!(
+        call_generation_service(&mock_server.base_url(), display_job()),
+        Err(crate::Error::<Test>::InvalidMessageCapacity)
+    ));
+}
+
+/// There is no sensible default for the capacity; a service that does NOT report it is rejected
+#[test]
+fn test_generation_service_display_no_message_capacity_err() {
+    let mock_server = httpmock::MockServer::start();
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST).path("/display");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmMESSAGE","nb_digits":2}"#);
+    });
+
+    assert!(matches!(
+        call_generation_service(&mock_server.base_url(), display_job()),
+        Err(crate::Error::<Test>::InvalidMessageCapacity)
+    ));
+}
+
+/// The service MUST use our layout; else pallet-ocw-garble would generate the wrong number of digits
+#[test]
+fn test_generation_service_nb_digits_mismatch_err() {
+    let mock_server = httpmock::MockServer::start();
+    mock_server.mock(|when, then| {
+        when.method(httpmock::Method::POST).path("/display");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"skcd_cid":"QmMESSAGE","nb_digits":4}"#);
+    });
+
+    assert!(matches!(
+        call_generation_service(&mock_server.base_url(), display_job()),
+        Err(crate::Error::<Test>::DeserializeError)
+    ));
+}
+
+#[test]
+fn test_generation_service_down_err() {
+    assert!(matches!(
+        call_generation_service("http://127.0.0.1:1", display_job()),
+        Err(crate::Error::<Test>::HttpFetchingError)
+    ));
+}
+
+/// The service can be switched on/off at runtime via the offchain local storage
+#[tokio::test]
+#[serial_test::serial]
+async fn test_generation_service_url_local_storage() {
+    let (mut t,) = new_test_ext(MockType::NoRequests, false).await;
+    t.execute_with(|| {
+        assert_eq!(
+            crate::generation_service::get_generation_service_url::<Test>(),
+            None
+        );
+        CircuitGeneratorAvailable::set(false);
+        assert!(!crate::local_generator_available::<Test>());
+
+        sp_io::offchain::local_storage_set(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            crate::GENERATION_SERVICE_URL_LOCAL_STORAGE_KEY,
+            b"http://generation-service",
+        );
+        assert_eq!(
+            crate::generation_service::get_generation_service_url::<Test>(),
+            Some("http://generation-service".to_string())
+        );
+        assert!(crate::local_generator_available::<Test>());
+
+        CircuitGeneratorAvailable::set(true);
+    });
+}
+
+/// The real generator; NOTE: slow
+#[cfg(all(feature = "circuit-gen-rs", not(target_family = "wasm")))]
+#[test]
+fn test_lib_circuits_rs_generate_generic() {
+    use crate::CircuitGenerator;
+
+    assert!(
+        crate::LibCircuitsRs::generate_generic(include_bytes!("../tests/data/adder.v")).is_ok()
+    );
+    assert!(crate::LibCircuitsRs::generate_generic(&[52]).is_err());
+}
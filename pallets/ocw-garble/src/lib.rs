@@ -6,6 +6,10 @@ mod mock;
 #[cfg(test)]
 mod tests;
 
+pub mod migrations;
+mod skcd_cache;
+mod verified_package_cache;
+
 extern crate alloc;
 
 use alloc::string::String;
@@ -14,6 +18,7 @@ use codec::{Decode, Encode};
 use frame_system::ensure_signed;
 use frame_system::offchain::AppCrypto;
 use frame_system::offchain::CreateSignedTransaction;
+use frame_system::offchain::{SendUnsignedTransaction, SignedPayload, Signer, SigningTypes};
 use rand::seq::SliceRandom;
 use rand::Rng;
 use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
@@ -22,6 +27,9 @@ use serde::Deserialize;
 use serde_json::json;
 use sp_core::crypto::KeyTypeId;
 use sp_runtime::traits::BlockNumberProvider;
+use sp_runtime::traits::IdentifyAccount;
+use sp_runtime::traits::One;
+use sp_runtime::traits::Zero;
 use sp_runtime::transaction_validity::InvalidTransaction;
 use sp_std::borrow::ToOwned;
 use sp_std::str;
@@ -29,6 +37,9 @@ use sp_std::vec::Vec;
 
 use interstellar_http_client::SendRequest;
 use interstellar_ipfs_client::IpfsClient;
+use circuits_storage_common::seven_segments::{self, Frame};
+use circuits_storage_common::{MessageCapacityError, PinpadLayout};
+use pallet_tx_validation::ValidationMetadataInjector;
 
 pub use pallet::*;
 
@@ -37,21 +48,159 @@ struct GarbleAndStripIpfsReply {
     pgarbled_cid: String,
 }
 
-/// TEST ONLY "hook"
-/// Because the tests need the full body bytes to mock correctly...
+/// Source of the seed used to generate the pinpad permutation and the OTP(ie "message_digits")
 ///
-/// cf https://github.com/paritytech/substrate/pull/12307/files
+/// NOTE: it is used from a dispatchable; so depending on where the pallet runs(node vs enclave)
+/// not every implementation is valid/secure. Pick the one matching your runtime:
+/// - `EnclaveEntropy`: SGX(or std) entropy; this is the one to use in `integritee-worker`
+/// - `OffchainRandomSeed`: `sp_io::offchain::random_seed`; only when the offchain API is available
+/// - `DeterministicRandomness`: TEST ONLY; fully reproducible
+pub trait GarbleRandomness {
+    /// Return a 32 bytes seed, suitable for `ChaChaRng::from_seed`
+    ///
+    /// param: subject: typically a nonce; it MUST be different for each call
+    fn random_seed(subject: &[u8]) -> [u8; 32];
+}
+
+/// Entropy from the platform(ie `getrandom`)
+/// In SGX it requires the "rand/getrandom" patch; cf Cargo.toml
+#[cfg(any(feature = "std", feature = "sgx"))]
+pub struct EnclaveEntropy;
+
+#[cfg(any(feature = "std", feature = "sgx"))]
+impl GarbleRandomness for EnclaveEntropy {
+    fn random_seed(_subject: &[u8]) -> [u8; 32] {
+        // https://github.com/paritytech/substrate/blob/master/frame/society/src/lib.rs#L1420
+        // TODO is ChaChaRng secure? (or at least good enough)
+        let mut rng = ChaChaRng::from_entropy();
+        rng.gen()
+    }
+}
+
+/// Randomness from the offchain API
+/// NOTE: NOT deterministic b/w nodes so it MUST NOT be used from a "node" runtime dispatch!
+#[cfg(feature = "sp-io")]
+pub struct OffchainRandomSeed;
+
+#[cfg(feature = "sp-io")]
+impl GarbleRandomness for OffchainRandomSeed {
+    fn random_seed(subject: &[u8]) -> [u8; 32] {
+        let random_seed = sp_io::offchain::random_seed();
+        sp_core::hashing::blake2_256(&[&random_seed[..], subject].concat())
+    }
+}
+
+/// TEST ONLY: seeded and deterministic
+/// The seed is mixed with `subject` so that successive calls still give different results.
+pub struct DeterministicRandomness<Seed>(sp_std::marker::PhantomData<Seed>);
+
+impl<Seed: frame_support::traits::Get<[u8; 32]>> GarbleRandomness
+    for DeterministicRandomness<Seed>
+{
+    fn random_seed(subject: &[u8]) -> [u8; 32] {
+        sp_core::hashing::blake2_256(&[&Seed::get()[..], subject].concat())
+    }
+}
+
+/// Source of the latest trusted parentchain header, eg the light-client in `integritee-worker`
 ///
-#[cfg(test)]
-pub trait MyTestCallback {
-    fn my_test_hook(input: Vec<u8>) -> Vec<u8> {
-        input
+/// It is used to verify the storage proof returned by `state_getReadProof` when the
+/// ocw-circuits Storage can not be read directly; cf `get_ocw_circuits_storage_value_rpc`
+pub trait TrustedParentchainHeader {
+    /// return: (block_hash, state_root); None if no header is trusted(yet)
+    fn trusted_header() -> Option<(sp_core::H256, sp_core::H256)>;
+}
+
+/// No trusted header: the RPC fallback is disabled
+impl TrustedParentchainHeader for () {
+    fn trusted_header() -> Option<(sp_core::H256, sp_core::H256)> {
+        None
     }
 }
 
-/// Empty implementation in case no callbacks are required.
-#[cfg(test)]
-impl MyTestCallback for () {}
+/// cf https://github.com/paritytech/substrate/blob/master/client/rpc-api/src/state/mod.rs "state_getReadProof"
+#[derive(Deserialize)]
+struct RpcReadProofResponse {
+    result: RpcReadProof,
+}
+
+#[derive(Deserialize)]
+struct RpcReadProof {
+    /// hex encoded
+    proof: Vec<String>,
+}
+
+/// Garble a .skcd and serialize the result for the evaluator(ie the bytes uploaded to IPFS)
+/// cf `Config::Garbler`; the errors are only logged, they all end up as `Error::GarblerError`
+pub trait Garbler {
+    /// "generic" circuit; cf `garble_standard_signed`
+    fn garble(skcd_buf: &[u8], garbler_inputs: &[u8]) -> Result<Vec<u8>, String>;
+    /// display circuit: the digits and the message are "burned in"(ie "packmsg")
+    fn garble_and_strip(skcd_buf: &[u8], digits: &[u8], tx_msg: &str) -> Result<Vec<u8>, String>;
+    /// Evaluate a `garble_and_strip` output like the phone does: `nb_frames` frames with random
+    /// evaluator inputs, merged; cf `Config::SelfCheckGarbledPackages`
+    ///
+    /// param: layout: (width, height, digits_bboxes) the circuit was generated with; cf
+    ///   `pallet_ocw_circuits::display_layout`. A real evaluator gets them from the circuit,
+    ///   and MUST fail if they do not match(ie the bboxes would be read on the wrong pixels).
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        nb_frames: u32,
+        layout: &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String>;
+}
+
+/// cf `Config::SelfCheckGarbledPackages`
+pub type SelfCheckInDebugBuilds = frame_support::traits::ConstBool<{ cfg!(debug_assertions) }>;
+
+/// The default `Garbler`; requires the feature "lib-garble-rs"
+#[cfg(all(feature = "lib-garble-rs", not(target_family = "wasm")))]
+pub struct LibGarbleRs;
+
+#[cfg(all(feature = "lib-garble-rs", not(target_family = "wasm")))]
+impl Garbler for LibGarbleRs {
+    fn garble(skcd_buf: &[u8], garbler_inputs: &[u8]) -> Result<Vec<u8>, String> {
+        let garb = lib_garble_rs::garble_skcd(skcd_buf)
+            .map_err(|err| alloc::format!("garble_skcd: {}", err.to_string()))?;
+        let encoded_garbler_inputs = lib_garble_rs::prepare_garbler_inputs(&garb, garbler_inputs)
+            .map_err(|err| alloc::format!("prepare_garbler_inputs: {}", err.to_string()))?;
+
+        lib_garble_rs::serialize_for_evaluator(garb, encoded_garbler_inputs)
+            .map_err(|err| alloc::format!("serialize_for_evaluator: {}", err.to_string()))
+    }
+
+    fn garble_and_strip(skcd_buf: &[u8], digits: &[u8], tx_msg: &str) -> Result<Vec<u8>, String> {
+        let garb = lib_garble_rs::garble_skcd(skcd_buf)
+            .map_err(|err| alloc::format!("garble_skcd: {}", err.to_string()))?;
+        // "packsmg"
+        let encoded_garbler_inputs =
+            lib_garble_rs::garbled_display_circuit_prepare_garbler_inputs(&garb, digits, tx_msg)
+                .map_err(|err| {
+                    alloc::format!(
+                        "garbled_display_circuit_prepare_garbler_inputs: {}",
+                        err.to_string()
+                    )
+                })?;
+
+        lib_garble_rs::serialize_for_evaluator(garb, encoded_garbler_inputs)
+            .map_err(|err| alloc::format!("serialize_for_evaluator: {}", err.to_string()))
+    }
+
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        nb_frames: u32,
+        (width, height, _digits_bboxes): &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String> {
+        // NOT security relevant: the phone uses a random "rndswitch" per frame, we only need "some" of them
+        // NOTE: fails if the circuit does NOT match the layout; eg generated with another profile
+        circuits_storage_common::evaluator::evaluate_display(
+            serialized_package_for_eval,
+            nb_frames,
+            (*width, *height),
+            &mut ChaChaRng::seed_from_u64(0),
+        )
+    }
+}
 
 #[frame_support::pallet]
 pub mod pallet {
@@ -70,6 +219,11 @@ pub mod pallet {
     /// The keys can be inserted manually via RPC (see `author_insertKey`).
     pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"garb");
 
+    /// Offchain local storage(PERSISTENT) key for the IPFS endpoints; cf `offchain_local_storage_get`
+    /// eg: `offchain_localStorageSet("PERSISTENT", "0x" + hex(key), "0x" + hex("http://127.0.0.1:5001"))`
+    /// NOTE: only used by the offchain worker(ie to unpin); the dispatchables use `Config::IpfsRootUrl`
+    pub const IPFS_ROOT_URL_LOCAL_STORAGE_KEY: &[u8] = b"ocw-garble::config::ipfs_root_url";
+
     /// Based on the above `KeyTypeId` we need to generate a pallet-specific crypto type wrapper.
     /// We can utilize the supported crypto kinds (`sr25519`, `ed25519` and `ecdsa`) and augment
     /// them with the pallet-specific identifier.
@@ -123,8 +277,39 @@ pub mod pallet {
         type RuntimeCall: From<Call<Self>>;
         /// The identifier type for an offchain worker.
         type AuthorityId: AppCrypto<Self::Public, Self::Signature>;
-        #[cfg(test)]
-        type HookCallGrpGarbleAndStripSerializedPackageForEval: MyTestCallback;
+        /// Seed for the pinpad permutation and the OTP; cf `GarbleRandomness`
+        type GarbleRandomness: GarbleRandomness;
+        /// Where to store the expected digits; typically `pallet_tx_validation::Pallet`
+        type ValidationMetadata: pallet_tx_validation::ValidationMetadataInjector<Self::AccountId>;
+        /// Number of blocks after which a pending circuit(ie not yet validated) is removed
+        #[pallet::constant]
+        type PendingCircuitLifetime: Get<Self::BlockNumber>;
+        /// Used to verify the ocw-circuits Storage when read via RPC; cf `TrustedParentchainHeader`
+        type TrustedParentchainHeader: TrustedParentchainHeader;
+        /// IPFS API root url eg "http://127.0.0.1:5001"; utf8
+        /// `()` to use the env var IPFS_ROOT_URL
+        /// NOTE: the offchain worker first looks in the local storage; cf `IPFS_ROOT_URL_LOCAL_STORAGE_KEY`
+        type IpfsRootUrl: Get<Option<Vec<u8>>>;
+        /// Node RPC url, needed for the fallback in `get_ocw_circuits_storage_value_rpc`; utf8
+        /// `()` to use the env var INTERSTELLAR_URI_NODE
+        type NodeUri: Get<Option<Vec<u8>>>;
+        /// A IPFS request taking longer than this(in ms) marks the endpoint as unhealthy
+        /// cf `circuits_storage_common::ipfs_failover::FailoverConfig`
+        #[pallet::constant]
+        type IpfsRequestTimeoutMs: Get<u64>;
+        /// Every `PinSweepInterval` blocks the offchain worker checks ALL of `PinnedCidsMap`
+        /// instead of only `UnpinQueue`; cf `unpin_released_circuits`
+        #[pallet::constant]
+        type PinSweepInterval: Get<Self::BlockNumber>;
+        /// Max number of `TxIntent` in one `garble_and_strip_batch_signed`
+        #[pallet::constant]
+        type MaxBatchSize: Get<u32>;
+        /// Typically `LibGarbleRs`; cf `Garbler`
+        type Garbler: Garbler;
+        /// Evaluate each garbled display circuit before publishing it, and check the digits shown;
+        /// cf `Error::GarbledPackageSelfCheckFailed`. Typically `SelfCheckInDebugBuilds`
+        #[pallet::constant]
+        type SelfCheckGarbledPackages: Get<bool>;
     }
 
     pub type PendingCircuitsType = BoundedVec<
@@ -134,7 +319,7 @@ pub mod pallet {
 
     /// Store account_id -> list(ipfs_cids);
     /// That represents the "list of pending txs" for a given Account
-    const MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT: u32 = 16;
+    pub(crate) const MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT: u32 = 16;
     #[pallet::storage]
     #[pallet::getter(fn get_pending_circuits_for_account)]
     pub(super) type AccountToPendingCircuitsMap<T: Config> = StorageMap<
@@ -146,9 +331,103 @@ pub mod pallet {
         ValueQuery,
     >;
 
+    /// Max number of pending circuits expiring at the same block; across all accounts
+    pub const MAX_NUMBER_EXPIRING_CIRCUITS_PER_BLOCK: u32 = 256;
+
+    /// If the expiry block is full, the circuit expires at one of the next `MAX_EXPIRY_SPILL_BLOCKS`
+    /// blocks instead; else a few accounts could block everyone else from garbling.
+    pub const MAX_EXPIRY_SPILL_BLOCKS: u32 = 16;
+
+    pub type ExpiringCircuitsType<T> = BoundedVec<
+        (<T as frame_system::Config>::AccountId, BoundedVec<u8, ConstU32<64>>),
+        ConstU32<MAX_NUMBER_EXPIRING_CIRCUITS_PER_BLOCK>,
+    >;
+
+    /// Store expiry block -> list(account_id, message_pgarbled_cid);
+    /// or the pgarbled_cid of a "generic" garbled circuit(cf `AccountToGarbledCircuitsMap`)
+    /// cf `Config::PendingCircuitLifetime` and `on_initialize`
+    #[pallet::storage]
+    pub(super) type ExpiringCircuitsMap<T: Config> =
+        StorageMap<_, Twox64Concat, T::BlockNumber, ExpiringCircuitsType<T>, ValueQuery>;
+
+    /// Max number of entries in `ExpiringCircuitsMap` for ONE account; ie its pending and "generic"
+    /// circuits. NOTE: a cancelled/validated circuit still counts until its expiry block; else an
+    /// account could fill the whole `ExpiringCircuitsMap` by looping "garble then cancel".
+    pub const MAX_NUMBER_EXPIRING_CIRCUITS_PER_ACCOUNT: u32 =
+        2 * MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT;
+
+    /// Store account_id -> number of its entries in `ExpiringCircuitsMap`
+    /// cf `MAX_NUMBER_EXPIRING_CIRCUITS_PER_ACCOUNT`
+    #[pallet::storage]
+    pub(super) type AccountToNbExpiringCircuitsMap<T: Config> =
+        StorageMap<_, Twox64Concat, T::AccountId, u32, ValueQuery>;
+
+    pub type GarbledCircuitsType =
+        BoundedVec<BoundedVec<u8, ConstU32<64>>, ConstU32<MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT>>;
+
+    /// Store account_id -> list(pgarbled_cid);
+    /// That represents the "generic" garbled circuits(ie `garble_standard_signed`) for a given Account
+    /// They are removed(and unpinned) after `Config::PendingCircuitLifetime`, like the pending circuits.
+    #[pallet::storage]
+    #[pallet::getter(fn get_garbled_circuits_for_account)]
+    pub(super) type AccountToGarbledCircuitsMap<T: Config> = StorageMap<
+        _,
+        Twox128,
+        // key: AccountId
+        T::AccountId,
+        GarbledCircuitsType,
+        ValueQuery,
+    >;
+
     #[pallet::storage]
     pub(super) type Nonce<T: Config> = StorageValue<_, u64, ValueQuery>;
 
+    /// The next `request_id`; cf `Event::NewGarbleAndStrippedIpfsCid`
+    /// NOT a secret(contrary to `Nonce`), it is only there to correlate the events.
+    #[pallet::storage]
+    pub(super) type NextRequestId<T: Config> = StorageValue<_, u64, ValueQuery>;
+
+    /// The "pin set": pgarbled_cid -> (account_id, message_pgarbled_cid)
+    /// for both the message and the pinpad of each pending circuit;
+    /// and pgarbled_cid -> (account_id, pgarbled_cid) for the "generic" garbled circuits.
+    /// NOTE: `ipfs_add` pins by default so there is no explicit "pin" call.
+    #[pallet::storage]
+    pub(super) type PinnedCidsMap<T: Config> = StorageMap<
+        _,
+        Twox64Concat,
+        BoundedVec<u8, ConstU32<64>>,
+        (T::AccountId, BoundedVec<u8, ConstU32<64>>),
+        OptionQuery,
+    >;
+
+    /// Max number of cids waiting to be unpinned; if full they are left to the periodic sweep
+    const MAX_UNPIN_QUEUE_LEN: u32 = 512;
+
+    /// The pgarbled cids of the circuits removed from `AccountToPendingCircuitsMap`(validated, expired, cancelled)
+    /// They are unpinned by the offchain worker, then removed via `confirm_unpinned_unsigned`
+    #[pallet::storage]
+    #[pallet::getter(fn get_unpin_queue)]
+    pub(super) type UnpinQueue<T: Config> = StorageValue<
+        _,
+        BoundedVec<BoundedVec<u8, ConstU32<64>>, ConstU32<MAX_UNPIN_QUEUE_LEN>>,
+        ValueQuery,
+    >;
+
+    /// Signed by one of the `pallet_ocw_circuits::OcwAuthorities`; cf `confirm_unpinned_unsigned`
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, scale_info::TypeInfo)]
+    pub struct ConfirmUnpinnedPayload<Public, BlockNumber> {
+        pub pgarbled_cids: Vec<Vec<u8>>,
+        /// when the offchain worker unpinned them; NOT checked, only for the logs
+        pub block_number: BlockNumber,
+        pub public: Public,
+    }
+
+    impl<T: SigningTypes> SignedPayload<T> for ConfirmUnpinnedPayload<T::Public, T::BlockNumber> {
+        fn public(&self) -> T::Public {
+            self.public.clone()
+        }
+    }
+
     // // TODO decouple cf https://substrate.stackexchange.com/questions/3354/access-storage-map-from-another-pallet-without-trait-pallet-config
     // pub struct DisplaySkcdPackageValueCopyPrefix;
     // impl frame_support::traits::StorageInstance for DisplaySkcdPackageValueCopyPrefix {
@@ -164,8 +443,11 @@ pub mod pallet {
     //     StorageValue<_, pallet_ocw_circuits::DisplaySkcdPackage, ValueQuery>;
 
     /// The current storage version.
+    /// v2: `DisplayStrippedCircuitsPackage::pinpad_nb_digits`; cf `migrations::v2`
+    /// v3: `DisplayStrippedCircuitsPackage::pinpad_layout`; cf `migrations::v3`
+    /// v4: `PinpadPool` removed(the pre-garbled pinpads); cf `migrations::v4`
     const STORAGE_VERSION: frame_support::traits::StorageVersion =
-        frame_support::traits::StorageVersion::new(1);
+        frame_support::traits::StorageVersion::new(4);
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
@@ -175,15 +457,51 @@ pub mod pallet {
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
-        // Sent at the end of the offchain_worker(ie it is an OUTPUT)
-        NewGarbledIpfsCid(Vec<u8>),
-        // Strip version: (one IPFS cid for the circuit), for both mesage and pinpad
+        /// `garble_standard_signed`: the package is in `AccountToGarbledCircuitsMap`
+        NewGarbledIpfsCid {
+            account_id: T::AccountId,
+            pgarbled_cid: Vec<u8>,
+            created_at: T::BlockNumber,
+            request_id: u64,
+        },
+        /// Strip version: a new pending circuit(message + pinpad) for `account_id`
+        /// It contains everything the wallet app needs; no need to query `AccountToPendingCircuitsMap`.
+        /// `request_id` is unique per pallet; it is also used by `NewGarbleAndStrippedBatch`.
         NewGarbleAndStrippedIpfsCid {
+            account_id: T::AccountId,
+            package: DisplayStrippedCircuitsPackage,
+            created_at: T::BlockNumber,
+            request_id: u64,
+        },
+        /// A circuit was removed from `AccountToPendingCircuitsMap`
+        /// The wallet app SHOULD use it to keep its list in sync
+        PendingCircuitRemoved {
+            account_id: T::AccountId,
             message_pgarbled_cid: Vec<u8>,
-            pinpad_pgarbled_cid: Vec<u8>,
+            reason: PendingCircuitRemovalReason,
+        },
+        /// `garble_and_strip_batch_signed`: ONE event for the whole batch
+        /// `packages`: (request_id, package) in the same order as the successful `TxIntent`;
+        /// `failed` is the index(in the batch) of each failed `TxIntent`, with the reason.
+        NewGarbleAndStrippedBatch {
+            account_id: T::AccountId,
+            packages: Vec<(u64, DisplayStrippedCircuitsPackage)>,
+            failed: Vec<(u32, DispatchError)>,
+            created_at: T::BlockNumber,
         },
     }
 
+    /// cf `Event::PendingCircuitRemoved`
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, scale_info::TypeInfo)]
+    pub enum PendingCircuitRemovalReason {
+        /// `cancel_pending_circuit`
+        Cancelled,
+        /// `pallet_tx_validation::check_input` was called; TxPass or TxFail
+        Validated,
+        /// `Config::PendingCircuitLifetime` blocks have passed
+        Expired,
+    }
+
     // Errors inform users that something went wrong.
     #[pallet::error]
     pub enum Error<T> {
@@ -208,31 +526,105 @@ pub mod pallet {
         IpfsCallError,
         GarblerError,
         Utf8Error,
+        // garble_standard_signed: AccountToGarbledCircuitsMap is full for this account
+        TooManyGarbledCircuits,
+        // AccountToPendingCircuitsMap is full for this account; cancel or validate some first
+        TooManyPendingCircuits,
+        // cancel_pending_circuit: the given cid is not pending for this account
+        PendingCircuitNotFound,
+        // get_ocw_circuits_storage_value_rpc: no trusted header, or the proof does not match it
+        StorageProofError,
+        // no endpoint(IPFS, node RPC) in the offchain local storage, nor in the Config, nor in the env
+        EndpointNotConfigured,
+        // NOT used anymore(the pinpad pool was removed); kept b/c the indexes of the next ones
+        PinpadPoolFull,
+        // garble_and_strip_batch_signed: empty batch, or more than `Config::MaxBatchSize`
+        InvalidBatchSize,
+        /// the garbled package does NOT show the expected digits; cf `Config::SelfCheckGarbledPackages`
+        GarbledPackageSelfCheckFailed,
+        /// the `tx_msg` has more characters than the message circuit can show; cf `MessageCapacity`
+        MessageTooLong,
+        /// the `tx_msg` has a character the message circuit can NOT draw; cf `MessageCapacity`
+        UnsupportedCharacter,
+        /// `ExpiringCircuitsMap` is full for the next `MAX_EXPIRY_SPILL_BLOCKS` blocks; retry later
+        TooManyExpiringCircuits,
+        /// the cid is already in `PinnedCidsMap`(or is one of ocw-circuits'): it has ONE owner only
+        CidAlreadyPinned,
+        /// the account has `MAX_NUMBER_EXPIRING_CIRCUITS_PER_ACCOUNT` circuits not yet expired;
+        /// including the cancelled ones. Retry after `Config::PendingCircuitLifetime` blocks.
+        TooManyExpiringCircuitsForAccount,
     }
 
     #[pallet::hooks]
     impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
-        // TODO TOREMOVE "fn offchain_worker" not used in TEE
-        /*
-        /// Offchain Worker entry point.
+        /// IMPORTANT: in order; v4 puts version 4 whatever the on-chain version was
+        fn on_runtime_upgrade() -> Weight {
+            crate::migrations::v2::migrate::<T>()
+                .saturating_add(crate::migrations::v3::migrate::<T>())
+                .saturating_add(crate::migrations::v4::migrate::<T>())
+        }
+
+        /// NOTE: every step is checked against the state BEFORE the whole chain
+        #[cfg(feature = "try-runtime")]
+        fn pre_upgrade() -> Result<Vec<u8>, &'static str> {
+            Ok((
+                crate::migrations::v2::pre_upgrade::<T>()?,
+                crate::migrations::v3::pre_upgrade::<T>()?,
+                crate::migrations::v4::pre_upgrade::<T>()?,
+            )
+                .encode())
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn post_upgrade(state: Vec<u8>) -> Result<(), &'static str> {
+            let (v2_state, v3_state, v4_state): (Vec<u8>, Vec<u8>, Vec<u8>) =
+                Decode::decode(&mut state.as_slice())
+                    .map_err(|_err| "invalid pre_upgrade state")?;
+            crate::migrations::v2::post_upgrade::<T>(v2_state)?;
+            crate::migrations::v3::post_upgrade::<T>(v3_state)?;
+            crate::migrations::v4::post_upgrade::<T>(v4_state)
+        }
+
+        /// Remove the pending circuits expiring at this block
+        fn on_initialize(block_number: T::BlockNumber) -> Weight {
+            let expiring_circuits = <ExpiringCircuitsMap<T>>::take(block_number);
+            let nb_expiring_circuits = expiring_circuits.len() as u64;
+
+            for (who, pgarbled_cid) in expiring_circuits {
+                // NOTE: saturating b/c the entries scheduled before the counter existed
+                <AccountToNbExpiringCircuitsMap<T>>::mutate_exists(&who, |nb_expiring_circuits| {
+                    *nb_expiring_circuits = nb_expiring_circuits
+                        .map(|nb| nb.saturating_sub(1))
+                        .filter(|nb| *nb > 0);
+                });
+                // NOT an error: the circuit was already validated or cancelled
+                if Self::remove_pending_circuit(
+                    &who,
+                    &pgarbled_cid,
+                    PendingCircuitRemovalReason::Expired,
+                )
+                .is_err()
+                {
+                    Self::remove_garbled_circuit(&who, &pgarbled_cid);
+                }
+            }
+
+            T::DbWeight::get()
+                .reads_writes(1 + 3 * nb_expiring_circuits, 1 + 4 * nb_expiring_circuits)
+        }
+
+        /// Offchain Worker entry point: unpin the circuits that are not pending anymore
         ///
-        /// By implementing `fn offchain_worker` you declare a new offchain worker.
-        /// This function will be called when the node is fully synced and a new best block is
-        /// succesfuly imported.
-        /// Note that it's not guaranteed for offchain workers to run on EVERY block, there might
-        /// be cases where some blocks are skipped, or for some the worker runs twice (re-orgs),
-        /// so the code should be able to handle that.
-        /// You can use `Local Storage` API to coordinate runs of the worker.
+        /// NOTE: NOT used in TEE; the pin set is then only cleaned by the node(if it runs this pallet)
         fn offchain_worker(block_number: T::BlockNumber) {
-            log::info!("[ocw-garble] Hello from pallet-ocw-garble.");
-
-            let result = Self::process_if_needed(block_number);
+            let pin_sweep_interval = T::PinSweepInterval::get();
+            let is_full_sweep =
+                !pin_sweep_interval.is_zero() && (block_number % pin_sweep_interval).is_zero();
 
-            if let Err(e) = result {
+            if let Err(e) = Self::unpin_released_circuits(is_full_sweep) {
                 log::error!("[ocw-garble] offchain_worker error: {:?}", e);
             }
         }
-        */
     }
 
     #[pallet::validate_unsigned]
@@ -244,12 +636,47 @@ pub mod pallet {
         /// By default unsigned transactions are disallowed, but implementing the validator
         /// here we make sure that some particular calls (the ones produced by offchain worker)
         /// are being whitelisted and marked as valid.
-        fn validate_unsigned(
-            _source: TransactionSource,
-            _call: &Self::Call,
+        ///
+        /// Only the calls signed by one of the `pallet_ocw_circuits::OcwAuthorities` are valid.
+        /// NOTE: the "provides" tag does NOT contain the signer: the same call sent by several
+        /// offchain workers(or several keys) is deduplicated by the pool.
+        fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+            match call {
+                Call::confirm_unpinned_unsigned { payload, signature } => Self::validate_ocw_call(
+                    payload,
+                    signature,
+                    (
+                        b"confirm_unpinned",
+                        sp_io::hashing::blake2_256(&payload.pgarbled_cids.encode()),
+                    ),
+                ),
+                _ => InvalidTransaction::Call.into(),
+            }
+        }
+    }
+
+    impl<T: Config> Pallet<T> {
+        fn validate_ocw_call<P: SignedPayload<T>>(
+            payload: &P,
+            signature: &T::Signature,
+            provides: impl Encode,
         ) -> TransactionValidity {
-            // TODO?
-            InvalidTransaction::Call.into()
+            if !SignedPayload::<T>::verify::<<T as Config>::AuthorityId>(payload, signature.clone())
+            {
+                return InvalidTransaction::BadProof.into();
+            }
+            let signer = payload.public().into_account();
+            if !pallet_ocw_circuits::Pallet::<T>::ocw_authorities().contains(&signer) {
+                return InvalidTransaction::BadSigner.into();
+            }
+
+            ValidTransaction::with_tag_prefix("OcwGarble")
+                .priority(<T as pallet_ocw_circuits::Config>::UnsignedPriority::get())
+                .and_provides(provides)
+                // the offchain worker will send it again if needed
+                .longevity(5)
+                .propagate(true)
+                .build()
         }
     }
 
@@ -259,24 +686,52 @@ pub mod pallet {
         /// [FAIL cf core/rpc-client/src/direct_client.rs and cli/src/trusted_operation.rs
         ///     -> issue with sgx_tstd + std]
         /// cf https://github.com/scs/substrate-api-client/blob/master/examples/example_get_storage.rs
+        ///
+        /// IMPORTANT: the RPC answer is NOT trusted: we query a storage proof(`state_getReadProof`)
+        /// at the block given by `T::TrustedParentchainHeader` and verify it against its state root.
+        /// The verified value is cached(in memory, NOT in the Storage) for this block hash.
         fn get_ocw_circuits_storage_value_rpc(
         ) -> Result<circuits_storage_common::DisplaySkcdPackage, Error<T>> {
+            let (block_hash, state_root) =
+                T::TrustedParentchainHeader::trusted_header().ok_or_else(|| {
+                    log::error!("[ocw-garble] get_ocw_circuits_storage_value_rpc: no header!");
+                    <Error<T>>::StorageProofError
+                })?;
+
+            if let Some(cached_value) = crate::verified_package_cache::get(&block_hash) {
+                return Ok(cached_value);
+            }
+
+            let storage_key = frame_support::storage::storage_prefix(
+                "OcwCircuits".as_bytes(),
+                "DisplaySkcdPackageValue".as_bytes(),
+            );
+
             // TODO? use proper struct to encode the request
             let body_json = json!({
                 "jsonrpc": "2.0",
                 "id": "1",
-                "method":"state_getStorage",
+                "method":"state_getReadProof",
                 // TODO compute this dynamically
                 // You can get it using eg https://polkadot.js.org/apps/#/chainstate;
                 // then select "ocwCircuits" and then the correct storage entry.
-                "params": [compute_storage_hash_hex_for_rpc("OcwCircuits", "DisplaySkcdPackageValue")]
+                "params": [
+                    [compute_storage_hash_hex_for_rpc("OcwCircuits", "DisplaySkcdPackageValue")],
+                    "0x".to_string() + &hex::encode(block_hash.as_bytes()),
+                ]
             });
 
-            let endpoint = get_node_uri();
+            let endpoint = get_node_uri::<T>()?;
 
-            let (resp_bytes, resp_content_type) =
+            // NOTE: the request can NOT be sent; so same as a failed request
+            let body_bytes = serde_json::to_vec(&body_json).map_err(|e| {
+                log::error!("[ocw-garble] state_getReadProof encode error: {:?}", e);
+                <Error<T>>::HttpFetchingError
+            })?;
+
+            let (resp_bytes, _resp_content_type) =
                 interstellar_http_client::ClientHttpReq::send_request(
-                    Some(bytes::Bytes::from(serde_json::to_vec(&body_json).unwrap())),
+                    Some(bytes::Bytes::from(body_bytes)),
                     &endpoint,
                     &interstellar_http_client::MyRequestMethod::Post,
                     Some(&interstellar_http_client::MyContentType::Json),
@@ -291,17 +746,44 @@ pub mod pallet {
                     <Error<T>>::HttpFetchingError
                 })?;
 
-            let response: circuits_storage_common::DisplaySkcdPackage =
-                interstellar_http_client::decode_rpc_json(&resp_bytes, &resp_content_type)
-                    .map_err(|e| {
-                        log::error!("[ocw-circuits] call_grpc_generic error: {:?}", e);
-                        <Error<T>>::DeserializeError
-                    })?;
+            let response: RpcReadProofResponse =
+                serde_json::from_slice(&resp_bytes).map_err(|e| {
+                    log::error!("[ocw-garble] state_getReadProof decode error: {:?}", e);
+                    <Error<T>>::DeserializeError
+                })?;
+            let proof_nodes = response
+                .result
+                .proof
+                .iter()
+                .map(|node_hex| hex::decode(node_hex.trim_start_matches("0x")))
+                .collect::<Result<Vec<Vec<u8>>, _>>()
+                .map_err(|e| {
+                    log::error!("[ocw-garble] state_getReadProof hex error: {:?}", e);
+                    <Error<T>>::DeserializeError
+                })?;
+
+            let value_encoded = verify_storage_proof(state_root, proof_nodes, &storage_key)
+                .ok_or(<Error<T>>::StorageProofError)?;
+            let response = circuits_storage_common::DisplaySkcdPackage::decode(
+                &mut value_encoded.as_slice(),
+            )
+            .map_err(|e| {
+                log::error!("[ocw-garble] DisplaySkcdPackage decode error: {:?}", e);
+                <Error<T>>::DeserializeError
+            })?;
+            // same CHECK as `pallet_ocw_circuits::get_display_circuits_package`
+            // NOTE: an empty alphabet would panic in `generate_random_digits`
+            if !response.display_profile().is_valid() {
+                log::error!("[ocw-garble] DisplaySkcdPackage: invalid profile!");
+                return Err(<Error<T>>::DeserializeError);
+            }
             log::info!(
                 "[ocw-garble] get_ocw_circuits_storage_value response : {:?}",
                 response
             );
 
+            crate::verified_package_cache::put(block_hash, response.clone());
+
             Ok(response)
         }
 
@@ -317,19 +799,27 @@ pub mod pallet {
                 Ok(circuit) => Ok(circuit),
                 Err(_) => {
                     log::warn!("[ocw-garble] get_ocw_circuits_storage_value: storage COULD NOT be read! Fallback to RPC...");
-                    Self::get_ocw_circuits_storage_value_rpc()
-                        .map_err(|_err| <Error<T>>::MissingSkcdCircuitsError)
+                    // NOTE: a proof that does not match the trusted header is NOT a missing circuit
+                    Self::get_ocw_circuits_storage_value_rpc().map_err(|err| match err {
+                        <Error<T>>::EndpointNotConfigured | <Error<T>>::StorageProofError => err,
+                        _ => <Error<T>>::MissingSkcdCircuitsError,
+                    })
                 }
             }
         }
 
         // TODO TOREMOVE #[pallet::weight(10000)]
+        ///
+        /// param: request_id: allocated by the caller at dispatch entry; cf `next_request_id`
         pub fn callback_new_garbled_and_strip_signed(
             who: T::AccountId,
+            request_id: u64,
             message_pgarbled_cid: Vec<u8>,
             message_digits: Vec<u8>,
             pinpad_pgarbled_cid: Vec<u8>,
             pinpad_digits: Vec<u8>,
+            pinpad_layout: PinpadLayout,
+            tx_intent: pallet_tx_validation::TxIntent<T::AccountId>,
         ) -> DispatchResult {
             // TODO TOREMOVE
             // let who = ensure_signed(origin.clone())?;
@@ -343,25 +833,108 @@ pub mod pallet {
                 who
             );
 
+            let package = Self::register_pending_circuit(
+                who.clone(),
+                message_pgarbled_cid,
+                message_digits,
+                pinpad_pgarbled_cid,
+                pinpad_digits,
+                pinpad_layout,
+                tx_intent,
+            )?;
+
             Self::deposit_event(Event::NewGarbleAndStrippedIpfsCid {
-                message_pgarbled_cid: message_pgarbled_cid.clone(),
-                pinpad_pgarbled_cid: pinpad_pgarbled_cid.clone(),
+                account_id: who,
+                package,
+                created_at: <frame_system::Pallet<T>>::block_number(),
+                request_id,
             });
 
+            log::info!("[ocw-garble] callback_new_garbled_and_strip_signed: done!");
+
+            Ok(())
+        }
+
+        /// `garble_standard_signed`: store the new package and publish it
+        /// MUST only be called with a cid uploaded by this pallet; it will be unpinned at expiry.
+        pub(crate) fn register_garbled_circuit(
+            who: T::AccountId,
+            request_id: u64,
+            pgarbled_cid: Vec<u8>,
+        ) -> DispatchResult {
+            log::info!(
+                "[ocw-garble] register_garbled_circuit: #{} ({:?},{:?})",
+                request_id,
+                sp_std::str::from_utf8(&pgarbled_cid).map_err(|_err| <Error<T>>::Utf8Error)?,
+                who
+            );
+
+            let bounded_pgarbled_cid: BoundedVec<u8, ConstU32<64>> = pgarbled_cid
+                .clone()
+                .try_into()
+                .map_err(|_err| <Error<T>>::IpfsCallError)?;
+            Self::ensure_cid_not_pinned(&bounded_pgarbled_cid)?;
+
+            // and update our internal map of garbled circuits for the given account
+            // "append if exists, create if not"
+            let mut current_garbled_circuits: GarbledCircuitsType =
+                <AccountToGarbledCircuitsMap<T>>::try_get(&who).unwrap_or_default();
+            current_garbled_circuits
+                .try_push(bounded_pgarbled_cid.clone())
+                .map_err(|_err| <Error<T>>::TooManyGarbledCircuits)?;
+            <AccountToGarbledCircuitsMap<T>>::insert(&who, current_garbled_circuits);
+
+            // pinned by `ipfs_add`; unpinned once removed from `AccountToGarbledCircuitsMap`
+            <PinnedCidsMap<T>>::insert(
+                &bounded_pgarbled_cid,
+                (who.clone(), bounded_pgarbled_cid.clone()),
+            );
+            Self::schedule_expiry(who.clone(), bounded_pgarbled_cid)?;
+
+            Self::deposit_event(Event::NewGarbledIpfsCid {
+                account_id: who,
+                pgarbled_cid,
+                created_at: <frame_system::Pallet<T>>::block_number(),
+                request_id,
+            });
+            Ok(())
+        }
+
+        /// Store everything needed to later validate a "garble+strip" circuit; and make it pending.
+        /// Shared by the single and the batch version; the caller is responsible for the events
+        /// (and for the `request_id`).
+        fn register_pending_circuit(
+            who: T::AccountId,
+            message_pgarbled_cid: Vec<u8>,
+            message_digits: Vec<u8>,
+            pinpad_pgarbled_cid: Vec<u8>,
+            pinpad_digits: Vec<u8>,
+            pinpad_layout: PinpadLayout,
+            tx_intent: pallet_tx_validation::TxIntent<T::AccountId>,
+        ) -> Result<DisplayStrippedCircuitsPackage, DispatchError> {
+            let pinpad_nb_digits: u32 = pinpad_digits
+                .len()
+                .try_into()
+                .map_err(|_err| <Error<T>>::GarblerError)?;
+            // CHECK before storing anything; and the message and the pinpad MUST NOT share a cid
+            ensure!(
+                message_pgarbled_cid != pinpad_pgarbled_cid,
+                <Error<T>>::CidAlreadyPinned
+            );
+            Self::ensure_cid_not_pinned(&message_pgarbled_cid)?;
+            Self::ensure_cid_not_pinned(&pinpad_pgarbled_cid)?;
+
             // store the metadata using the pallet-tx-validation
             // (only in "garble+strip" mode b/c else it makes no sense)
-            // TODO? Call?
-            // pallet_tx_validation::Call::<T>::store_metadata {
-            //     ipfs_cid: pgarbled_cid,
-            //     circuit_digits: circuit_digits,
-            // };
-            pallet_tx_validation::store_metadata_aux::<T>(
+            T::ValidationMetadata::inject_metadata(
                 &who,
                 message_pgarbled_cid.clone(),
                 message_digits.clone(),
                 pinpad_digits,
-            )
-            .expect("store_metadata_aux failed!");
+                Self::generate_commitment_nonce(),
+            )?;
+            // and bind the TxIntent to the circuit; it will be executed on TxPass
+            T::ValidationMetadata::inject_tx_intent(&who, message_pgarbled_cid.clone(), tx_intent)?;
 
             // and update our internal map of pending circuits for the given account
             // this is USED via RPC by the app, not directly!
@@ -369,67 +942,431 @@ pub mod pallet {
             // TODO done in two steps, is there a way to do it atomically?
             let mut current_pending_circuits: PendingCircuitsType =
                 <AccountToPendingCircuitsMap<T>>::try_get(&who).unwrap_or_default();
+            let message_pgarbled_cid: BoundedVec<u8, ConstU32<64>> = message_pgarbled_cid
+                .try_into()
+                .map_err(|_err| <Error<T>>::IpfsCallError)?;
+            let pinpad_pgarbled_cid: BoundedVec<u8, ConstU32<64>> = pinpad_pgarbled_cid
+                .try_into()
+                .map_err(|_err| <Error<T>>::IpfsCallError)?;
+            let package = DisplayStrippedCircuitsPackage {
+                message_pgarbled_cid: message_pgarbled_cid.clone(),
+                pinpad_pgarbled_cid: pinpad_pgarbled_cid.clone(),
+                message_nb_digits: message_digits.len().try_into().unwrap(),
+                pinpad_nb_digits,
+                pinpad_layout,
+            };
             current_pending_circuits
-                .try_push(DisplayStrippedCircuitsPackage {
-                    message_pgarbled_cid: TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(
-                        message_pgarbled_cid,
-                    )
-                    .unwrap(),
-                    pinpad_pgarbled_cid: TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(
-                        pinpad_pgarbled_cid,
-                    )
-                    .unwrap(),
-                    message_nb_digits: message_digits.len().try_into().unwrap(),
+                .try_push(package.clone())
+                .map_err(|_err| <Error<T>>::TooManyPendingCircuits)?;
+            <AccountToPendingCircuitsMap<T>>::insert(&who, current_pending_circuits);
+
+            // both were pinned by `ipfs_add`; they will be unpinned when no longer pending
+            <PinnedCidsMap<T>>::insert(
+                &message_pgarbled_cid,
+                (who.clone(), message_pgarbled_cid.clone()),
+            );
+            <PinnedCidsMap<T>>::insert(
+                &pinpad_pgarbled_cid,
+                (who.clone(), message_pgarbled_cid.clone()),
+            );
+
+            // and schedule its removal if it is never validated
+            Self::schedule_expiry(who, message_pgarbled_cid)?;
+
+            Ok(package)
+        }
+
+        /// CHECK before garbling: the account MUST be able to schedule `nb_circuits` more expiries
+        fn ensure_can_schedule_expiries(
+            who: &T::AccountId,
+            nb_circuits: usize,
+        ) -> Result<(), Error<T>> {
+            ensure!(
+                <AccountToNbExpiringCircuitsMap<T>>::get(who) as usize + nb_circuits
+                    <= MAX_NUMBER_EXPIRING_CIRCUITS_PER_ACCOUNT as usize,
+                <Error<T>>::TooManyExpiringCircuitsForAccount
+            );
+            Ok(())
+        }
+
+        /// Add the circuit to `ExpiringCircuitsMap` at `Config::PendingCircuitLifetime` blocks from now;
+        /// or at the first of the `MAX_EXPIRY_SPILL_BLOCKS` next blocks that is not full.
+        /// At most `MAX_NUMBER_EXPIRING_CIRCUITS_PER_ACCOUNT` per account; cf `AccountToNbExpiringCircuitsMap`
+        fn schedule_expiry(
+            who: T::AccountId,
+            message_pgarbled_cid: BoundedVec<u8, ConstU32<64>>,
+        ) -> Result<(), Error<T>> {
+            Self::ensure_can_schedule_expiries(&who, 1)?;
+
+            let mut expiry_block_number =
+                <frame_system::Pallet<T>>::block_number() + T::PendingCircuitLifetime::get();
+            for _ in 0..=MAX_EXPIRY_SPILL_BLOCKS {
+                if <ExpiringCircuitsMap<T>>::try_append(
+                    expiry_block_number,
+                    (who.clone(), message_pgarbled_cid.clone()),
+                )
+                .is_ok()
+                {
+                    <AccountToNbExpiringCircuitsMap<T>>::mutate(&who, |nb_expiring_circuits| {
+                        *nb_expiring_circuits += 1
+                    });
+                    return Ok(());
+                }
+                expiry_block_number += One::one();
+            }
+
+            log::warn!("[ocw-garble] schedule_expiry: all the expiry blocks are full!");
+            Err(<Error<T>>::TooManyExpiringCircuits)
+        }
+
+        /// The uploads of the failed items of `garble_and_strip_batch_signed` are NOT tracked
+        /// anywhere(ie NOT in `PinnedCidsMap`): unpin them.
+        ///
+        /// param: is_call_failing: the Storage will be rolled back so `UnpinQueue` can NOT be used;
+        ///   they are unpinned right away instead(best effort: NOT retried)
+        fn release_failed_uploads(pgarbled_cids: Vec<Vec<u8>>, is_call_failing: bool) {
+            if pgarbled_cids.is_empty() {
+                return;
+            }
+
+            if !is_call_failing {
+                for pgarbled_cid in pgarbled_cids {
+                    match pgarbled_cid.try_into() {
+                        Ok(pgarbled_cid) => Self::enqueue_unpin(pgarbled_cid),
+                        Err(pgarbled_cid) => log::warn!(
+                            "[ocw-garble] release_failed_uploads: invalid cid {:?}",
+                            sp_std::str::from_utf8(&pgarbled_cid)
+                        ),
+                    }
+                }
+                return;
+            }
+
+            let ipfs_uris = match get_ipfs_uris::<T>(None) {
+                Ok(ipfs_uris) => ipfs_uris,
+                Err(err) => {
+                    log::warn!("[ocw-garble] release_failed_uploads: {:?}", err);
+                    return;
+                }
+            };
+            for pgarbled_cid in pgarbled_cids {
+                if let Err(err) = unpin_cid::<T>(&ipfs_uris, &pgarbled_cid) {
+                    log::warn!(
+                        "[ocw-garble] release_failed_uploads: {:?} is left pinned: {:?}",
+                        sp_std::str::from_utf8(&pgarbled_cid),
+                        err
+                    );
+                }
+            }
+        }
+
+        /// Remove a "generic" circuit from `AccountToGarbledCircuitsMap`; and queue it for unpinning
+        /// NOT an error if not found
+        fn remove_garbled_circuit(who: &T::AccountId, pgarbled_cid: &[u8]) {
+            let mut garbled_circuits: GarbledCircuitsType =
+                <AccountToGarbledCircuitsMap<T>>::get(who);
+            let position = match garbled_circuits
+                .iter()
+                .position(|garbled_cid| garbled_cid.as_slice() == pgarbled_cid)
+            {
+                Some(position) => position,
+                None => return,
+            };
+            let removed_cid = garbled_circuits.remove(position);
+
+            if garbled_circuits.is_empty() {
+                <AccountToGarbledCircuitsMap<T>>::remove(who);
+            } else {
+                <AccountToGarbledCircuitsMap<T>>::insert(who, garbled_circuits);
+            }
+            Self::enqueue_unpin(removed_cid);
+        }
+
+        /// Remove a circuit from `AccountToPendingCircuitsMap`, and its metadata from pallet-tx-validation
+        ///
+        /// NOTE: `ExpiringCircuitsMap` is NOT updated; at expiry the circuit will simply not be found
+        pub fn remove_pending_circuit(
+            who: &T::AccountId,
+            message_pgarbled_cid: &[u8],
+            reason: PendingCircuitRemovalReason,
+        ) -> Result<(), Error<T>> {
+            let mut current_pending_circuits: PendingCircuitsType =
+                <AccountToPendingCircuitsMap<T>>::get(who);
+            let position = current_pending_circuits
+                .iter()
+                .position(|pending_circuit| {
+                    pending_circuit.message_pgarbled_cid.as_slice() == message_pgarbled_cid
                 })
-                .unwrap();
-            <AccountToPendingCircuitsMap<T>>::insert(who, current_pending_circuits);
+                .ok_or(<Error<T>>::PendingCircuitNotFound)?;
+            let removed_circuit = current_pending_circuits.remove(position);
 
-            log::info!("[ocw-garble] callback_new_garbled_and_strip_signed: done!");
+            if current_pending_circuits.is_empty() {
+                <AccountToPendingCircuitsMap<T>>::remove(who);
+            } else {
+                <AccountToPendingCircuitsMap<T>>::insert(who, current_pending_circuits);
+            }
+            T::ValidationMetadata::remove_metadata(who, message_pgarbled_cid);
+            Self::enqueue_unpin(removed_circuit.message_pgarbled_cid);
+            Self::enqueue_unpin(removed_circuit.pinpad_pgarbled_cid);
+
+            log::info!(
+                "[ocw-garble] remove_pending_circuit: {:?} for {:?}: {:?}",
+                sp_std::str::from_utf8(message_pgarbled_cid),
+                who,
+                reason
+            );
+            Self::deposit_event(Event::PendingCircuitRemoved {
+                account_id: who.clone(),
+                message_pgarbled_cid: message_pgarbled_cid.to_vec(),
+                reason,
+            });
 
             Ok(())
         }
     }
 
-    #[pallet::call]
     impl<T: Config> Pallet<T> {
-        // TODO TOREMOVE
-        /*
-        #[pallet::weight(10000)]
-        pub fn garble_standard_signed(origin: OriginFor<T>, skcd_cid: Vec<u8>) -> DispatchResult {
-            let who = ensure_signed(origin)?;
-            log::info!(
-                "[ocw-garble] garble_standard_signed: ({}, {:?})",
-                sp_std::str::from_utf8(&skcd_cid).map_err(|err| <Error<T>>::Utf8Error)?,
-                who
+        /// One item of `garble_and_strip_batch_signed`
+        /// MUST be called inside a storage transaction: it is NOT rolled back on error.
+        ///
+        /// param: uploaded_cids: the cids uploaded to IPFS; the uploads are NOT rolled back, so
+        ///   on error the caller MUST unpin them.
+        #[allow(clippy::too_many_arguments)]
+        fn garble_and_strip_batch_item(
+            who: &T::AccountId,
+            display_circuits_package: &circuits_storage_common::DisplaySkcdPackage,
+            message_skcd_buf: &[u8],
+            pinpad_skcd_buf: &[u8],
+            tx_intent: pallet_tx_validation::TxIntent<T::AccountId>,
+            message_digits: Vec<u8>,
+            pinpad_digits: Vec<u8>,
+            uploaded_cids: &mut Vec<Vec<u8>>,
+        ) -> Result<DisplayStrippedCircuitsPackage, DispatchError> {
+            let tx_msg: Vec<u8> = tx_intent.render_display_text().into_bytes();
+            Self::check_tx_msg(display_circuits_package, &tx_msg)?;
+            let message_reply = garble_and_strip_skcd_buf::<T>(
+                message_skcd_buf,
+                tx_msg,
+                message_digits.clone(),
+                None,
+            )?;
+            uploaded_cids.push(message_reply.pgarbled_cid.as_bytes().to_vec());
+
+            let pinpad_reply = garble_and_strip_skcd_buf::<T>(
+                pinpad_skcd_buf,
+                vec![],
+                pinpad_digits.clone(),
+                Some(&display_circuits_package.pinpad_layout),
+            )?;
+            uploaded_cids.push(pinpad_reply.pgarbled_cid.as_bytes().to_vec());
+
+            Self::register_pending_circuit(
+                who.clone(),
+                message_reply.pgarbled_cid.into_bytes(),
+                message_digits,
+                pinpad_reply.pgarbled_cid.into_bytes(),
+                pinpad_digits,
+                display_circuits_package.pinpad_layout.clone(),
+                tx_intent,
+            )
+        }
+
+        /// The `tx_msg` MUST fit on the message circuit; cf `DisplaySkcdPackage::message_capacity`
+        fn check_tx_msg(
+            display_circuits_package: &circuits_storage_common::DisplaySkcdPackage,
+            tx_msg: &[u8],
+        ) -> Result<(), Error<T>> {
+            let tx_msg_str =
+                sp_std::str::from_utf8(tx_msg).map_err(|_err| <Error<T>>::Utf8Error)?;
+
+            display_circuits_package
+                .message_capacity
+                .check(tx_msg_str)
+                .map_err(|err| match err {
+                    MessageCapacityError::TooLong => <Error<T>>::MessageTooLong,
+                    MessageCapacityError::UnsupportedCharacter => <Error<T>>::UnsupportedCharacter,
+                })
+        }
+
+        fn enqueue_unpin(pgarbled_cid: BoundedVec<u8, ConstU32<64>>) {
+            if <UnpinQueue<T>>::try_append(&pgarbled_cid).is_err() {
+                log::warn!(
+                    "[ocw-garble] UnpinQueue is full: {:?} is left to the sweep",
+                    sp_std::str::from_utf8(&pgarbled_cid)
+                );
+            }
+        }
+
+        /// A cid has at most ONE entry in `PinnedCidsMap`: overwriting it would let its owner
+        /// get it unpinned while still used by the previous one.
+        /// NOTE: untracked cids(eg too long) are NOT rejected here; the callers bound them anyway.
+        fn ensure_cid_not_pinned(pgarbled_cid: &[u8]) -> Result<(), Error<T>> {
+            ensure!(
+                !pallet_ocw_circuits::is_display_skcd_cid::<T>(pgarbled_cid),
+                <Error<T>>::CidAlreadyPinned
+            );
+            let pgarbled_cid: BoundedVec<u8, ConstU32<64>> = match pgarbled_cid.to_vec().try_into()
+            {
+                Ok(pgarbled_cid) => pgarbled_cid,
+                Err(_) => return Ok(()),
+            };
+            ensure!(
+                !<PinnedCidsMap<T>>::contains_key(pgarbled_cid),
+                <Error<T>>::CidAlreadyPinned
             );
+            Ok(())
+        }
 
-            Self::append_or_replace_skcd_hash(
-                GrpcCallKind::GarbleStandard,
-                Some(skcd_cid),
-                None,
-                None,
-                None,
-                None,
-                None,
+        /// If the cid MUST stay pinned: it is one of ocw-circuits' .skcd, or it is tracked in
+        /// `PinnedCidsMap` and not releasable. Checked before unpinning ANY cid(including the
+        /// ones in `UnpinQueue`).
+        pub(crate) fn is_cid_in_use(pgarbled_cid: &[u8]) -> bool {
+            if pallet_ocw_circuits::is_display_skcd_cid::<T>(pgarbled_cid) {
+                return true;
+            }
+            let is_tracked =
+                TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(pgarbled_cid.to_vec())
+                    .map(<PinnedCidsMap<T>>::contains_key)
+                    .unwrap_or(false);
+            is_tracked && !Self::is_pinned_cid_releasable(pgarbled_cid)
+        }
+
+        /// A cid in `PinnedCidsMap` MUST stay pinned as long as its circuit is pending
+        /// (or for a "generic" one: as long as it is in `AccountToGarbledCircuitsMap`)
+        /// and NEVER if it is one of ocw-circuits' .skcd
+        pub(crate) fn is_pinned_cid_releasable(pgarbled_cid: &[u8]) -> bool {
+            if pallet_ocw_circuits::is_display_skcd_cid::<T>(pgarbled_cid) {
+                return false;
+            }
+            let pgarbled_cid: BoundedVec<u8, ConstU32<64>> =
+                match pgarbled_cid.to_vec().try_into() {
+                    Ok(pgarbled_cid) => pgarbled_cid,
+                    Err(_) => return false,
+                };
+            match <PinnedCidsMap<T>>::get(pgarbled_cid) {
+                Some((who, message_pgarbled_cid)) => {
+                    !<AccountToPendingCircuitsMap<T>>::get(&who)
+                        .iter()
+                        .any(|pending_circuit| {
+                            pending_circuit.message_pgarbled_cid == message_pgarbled_cid
+                        })
+                        && !<AccountToGarbledCircuitsMap<T>>::get(&who)
+                            .contains(&message_pgarbled_cid)
+                }
+                None => false,
+            }
+        }
+
+        /// Unpin the cids in `UnpinQueue`; or if `is_full_sweep` all the ones in `PinnedCidsMap`
+        /// that are not pending anymore.
+        /// Then `confirm_unpinned_unsigned` to remove them from the Storage.
+        fn unpin_released_circuits(is_full_sweep: bool) -> Result<(), Error<T>> {
+            let mut pgarbled_cids: Vec<BoundedVec<u8, ConstU32<64>>> = <UnpinQueue<T>>::get()
+                .into_iter()
+                .filter(|pgarbled_cid| !Self::is_cid_in_use(pgarbled_cid))
+                .collect();
+            if is_full_sweep {
+                pgarbled_cids.extend(
+                    <PinnedCidsMap<T>>::iter_keys()
+                        .filter(|pgarbled_cid| Self::is_pinned_cid_releasable(pgarbled_cid)),
+                );
+            }
+            pgarbled_cids.sort();
+            pgarbled_cids.dedup();
+
+            // offchain context: the local storage CAN be used
+            let ipfs_uris =
+                get_ipfs_uris::<T>(offchain_local_storage_get(IPFS_ROOT_URL_LOCAL_STORAGE_KEY))?;
+            let unpinned_cids: Vec<Vec<u8>> = pgarbled_cids
+                .into_iter()
+                .take(MAX_UNPIN_QUEUE_LEN as usize)
+                .filter(|pgarbled_cid| unpin_cid::<T>(&ipfs_uris, pgarbled_cid).is_ok())
+                .map(|pgarbled_cid| pgarbled_cid.into_inner())
+                .collect();
+            if unpinned_cids.is_empty() {
+                return Ok(());
+            }
+
+            // Unsigned with a signed payload: only the local keys that are `OcwAuthorities`,
+            // the others would be rejected by `validate_unsigned`.
+            let signer = Signer::<T, <T as Config>::AuthorityId>::all_accounts().with_filter(
+                pallet_ocw_circuits::local_authority_keys::<T, <T as Config>::AuthorityId>(),
+            );
+            if !signer.can_sign() {
+                log::error!("[ocw-garble] No local authority account available. Consider adding one via `author_insertKey` RPC, and `pallet_ocw_circuits::set_ocw_authorities`");
+                return Err(<Error<T>>::NoLocalAcctForSigning);
+            }
+            let block_number = <frame_system::Pallet<T>>::block_number();
+            let tx_results = signer.send_unsigned_transaction(
+                |account| ConfirmUnpinnedPayload {
+                    pgarbled_cids: unpinned_cids.clone(),
+                    block_number,
+                    public: account.public.clone(),
+                },
+                |payload, signature| Call::confirm_unpinned_unsigned { payload, signature },
             );
+            for (account, result) in &tx_results {
+                if result.is_err() {
+                    log::error!(
+                        "[ocw-garble] confirm_unpinned_unsigned error: {:?}",
+                        account.id
+                    );
+                    return Err(<Error<T>>::OffchainUnsignedTxSignedPayloadError);
+                }
+            }
 
             Ok(())
         }
-        */
+    }
 
+    /// The .skcd were regenerated: the cached ones will not be used anymore
+    impl<T: Config> pallet_ocw_circuits::OnNewDisplaySkcdPackage for Pallet<T> {
+        fn on_new_display_skcd_package(_message_skcd_cid: &[u8], _pinpad_skcd_cid: &[u8]) {
+            crate::skcd_cache::invalidate();
+            crate::verified_package_cache::invalidate();
+        }
+    }
+
+    /// Cleanup the pending circuit once the user has entered the code(wether it is correct or not)
+    impl<T: Config> pallet_tx_validation::OnCircuitValidated<T::AccountId> for Pallet<T> {
+        fn on_circuit_validated(who: &T::AccountId, message_pgarbled_cid: &[u8], _is_tx_pass: bool) {
+            // NOT an error: the circuit MAY not come from this pallet(eg `store_metadata`)
+            let _ = Self::remove_pending_circuit(
+                who,
+                message_pgarbled_cid,
+                PendingCircuitRemovalReason::Validated,
+            );
+        }
+    }
+
+    #[pallet::call]
+    impl<T: Config> Pallet<T> {
         #[pallet::call_index(0)]
         #[pallet::weight(10000)]
         pub fn garble_and_strip_display_circuits_package_signed(
             origin: OriginFor<T>,
-            tx_msg: Vec<u8>,
+            tx_intent: pallet_tx_validation::TxIntent<T::AccountId>,
         ) -> DispatchResult {
             let who = ensure_signed(origin)?;
+            let request_id = Self::next_request_id();
+            // what is displayed on the "message" circuit is derived from the TxIntent
+            // that way the user confirms what will be executed, cf pallet_tx_validation::TxIntentExecutor
+            let tx_msg: Vec<u8> = tx_intent.render_display_text().into_bytes();
             log::info!(
-                "[ocw-garble] garble_and_strip_display_circuits_package_signed: ({:?} for {:?})",
+                "[ocw-garble] garble_and_strip_display_circuits_package_signed: #{} ({:?} for {:?})",
+                request_id,
                 sp_std::str::from_utf8(&tx_msg).map_err(|_err| <Error<T>>::Utf8Error)?,
                 who
             );
 
+            // CHECK before garbling: no point in doing all the IPFS work if it can not be stored
+            ensure!(
+                <AccountToPendingCircuitsMap<T>>::decode_len(&who).unwrap_or(0)
+                    < MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT as usize,
+                <Error<T>>::TooManyPendingCircuits
+            );
+            Self::ensure_can_schedule_expiries(&who, 1)?;
+
             // read DisplayCircuitsPackageValue directly from ocw-circuits
             //
             // FAIL, even with "key_hashes.push(storage_value_key("OcwCircuits", "DisplaySkcdPackageValue"))"
@@ -450,6 +1387,8 @@ pub mod pallet {
             // let display_circuits_package = <DisplaySkcdPackageValueCopy<T>>::get();
             //
             let display_circuits_package = Self::get_ocw_circuits_storage_value()?;
+            // CHECK before garbling: else it would fail deep in the garbler, or be truncated on screen
+            Self::check_tx_msg(&display_circuits_package, &tx_msg)?;
 
             log::info!(
                 "[ocw-garble] display_circuits_package: ({:?},{:?}) ({:?},{:?})",
@@ -461,38 +1400,6 @@ pub mod pallet {
                 display_circuits_package.pinpad_skcd_server_metadata_nb_digits,
             );
 
-            // Generate random digits
-            // FAIL does not seem to be random in enclave?
-            // cf https://github.com/paritytech/substrate/blob/master/frame/lottery/src/lib.rs#L506
-            // let nonce = Self::get_and_increment_nonce();
-            // let (random_seed, _) = T::MyRandomness::random(&nonce);
-            // random_seed is a Hash so 256 -> 32 u8 is fine
-            // so we have more than enough
-            // TODO we could(SHOULD) split "random_seed" 4 bits by 4 bits b/c that is enough for [0-10] range
-            // let random_seed = <[u8; 32]>::decode(&mut random_seed.as_ref())
-            //     .expect("secure hashes should always be bigger than u32; qed");
-
-            // https://github.com/paritytech/substrate/blob/master/frame/society/src/lib.rs#L1420
-            // TODO is ChaChaRng secure? (or at least good enough)
-            let mut rng = ChaChaRng::from_entropy();
-
-            // typically we need (2-4) digits for the message
-            // and 10 digits(NOT u8) for the pinpad
-            // MUST SHUFFLE the pinpad digits, NOT randomize them
-            // each digit from 0 to 10 (included!) MUST be in the final "digits"
-            let mut pinpad_digits: Vec<u8> =
-                (0..display_circuits_package.pinpad_skcd_server_metadata_nb_digits as u8).collect();
-            pinpad_digits.shuffle(&mut rng);
-            let message_digits: Vec<u8> =
-                (0..display_circuits_package.message_skcd_server_metadata_nb_digits as u8)
-                    .map(|_| rng.gen_range(0..10))
-                    .collect();
-            log::info!(
-                "[ocw-garble] pinpad_digits: {:?}, message_digits: {:?}",
-                pinpad_digits,
-                message_digits,
-            );
-
             // Self::append_or_replace_skcd_hash(
             //     GrpcCallKind::GarbleAndStrip,
             //     // optional: only if GrpcCallKind::GarbleStandard
@@ -506,12 +1413,17 @@ pub mod pallet {
             // );
             //
             // FAIL: apparently "fn offchain_worker" is NOT called?
+            let (message_digits, pinpad_digits) = Self::generate_random_digits(
+                &display_circuits_package.alphabet,
+                display_circuits_package.message_skcd_server_metadata_nb_digits,
+            );
             let result_grpc_call = Self::call_grpc_garble_and_strip(
                 display_circuits_package.message_skcd_cid.to_vec(),
                 display_circuits_package.pinpad_skcd_cid.to_vec(),
                 tx_msg,
                 message_digits,
                 pinpad_digits,
+                &display_circuits_package.pinpad_layout,
             )?;
 
             let (message_reply, message_digits, pinpad_reply, pinpad_digits) =
@@ -522,6 +1434,9 @@ pub mod pallet {
                         pinpad_reply,
                         pinpad_digits,
                     ) => (message_reply, message_digits, pinpad_reply, pinpad_digits),
+                    GrpcCallReplyKind::GarbleStandard(_) => {
+                        return Err(<Error<T>>::UnknownOffchainMux.into())
+                    }
                 };
 
             // TODO TOREMOVE
@@ -529,35 +1444,226 @@ pub mod pallet {
 
             Self::callback_new_garbled_and_strip_signed(
                 who,
+                request_id,
                 message_reply.pgarbled_cid.bytes().collect(),
                 message_digits.to_vec(),
                 pinpad_reply.pgarbled_cid.bytes().collect(),
                 pinpad_digits.to_vec(),
+                display_circuits_package.pinpad_layout,
+                tx_intent,
             )?;
 
             Ok(())
         }
 
-        /// Called at the end of offchain_worker to publish the result
-        /// Not meant to be called by a user
-        // TODO use "with signed payload" and check if expected key?
-        // TODO TOREMOVE
-        #[pallet::call_index(1)]
+        // NOTE: call_index(1) was `callback_new_garbled_signed`; removed b/c anyone could register
+        // any cid(and get it unpinned at expiry). Do NOT reuse the index.
+
+        /// Remove a pending circuit; eg the user does not want to confirm the tx anymore
+        ///
+        /// param: message_pgarbled_cid: cf `DisplayStrippedCircuitsPackage`
+        #[pallet::call_index(3)]
         #[pallet::weight(10000)]
-        pub fn callback_new_garbled_signed(
+        pub fn cancel_pending_circuit(
             origin: OriginFor<T>,
-            pgarbled_cid: Vec<u8>,
+            message_pgarbled_cid: Vec<u8>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            Self::remove_pending_circuit(
+                &who,
+                &message_pgarbled_cid,
+                PendingCircuitRemovalReason::Cancelled,
+            )?;
+
+            Ok(())
+        }
+
+        // NOTE: call_index(5) was `refill_pinpad_pool_unsigned`; removed b/c the digits of the
+        // pre-garbled pinpads can NOT be kept outside of the Storage(and so of the consensus).
+        // Do NOT reuse the index.
+
+        /// Batch version of `garble_and_strip_display_circuits_package_signed`:
+        /// the .skcd are downloaded only once, and there is only ONE event for the whole batch.
+        ///
+        /// Partial failures:
+        /// - the whole call fails(and nothing is stored) if the batch is empty or bigger than
+        ///   `Config::MaxBatchSize`; or if there is not enough room in `AccountToPendingCircuitsMap`
+        ///   for ALL of them; or if the .skcd can not be downloaded
+        /// - else each `TxIntent` is independent: a failed one is rolled back and reported in
+        ///   `Event::NewGarbleAndStrippedBatch::failed`; the others are pending as usual
+        /// - if ALL of them failed: the call fails with the first error(and there is no event)
+        ///
+        /// The packages already uploaded by a failed `TxIntent` are unpinned; cf `release_failed_uploads`
+        #[pallet::call_index(6)]
+        #[pallet::weight(10_000u64.saturating_mul(tx_intents.len().max(1) as u64))]
+        pub fn garble_and_strip_batch_signed(
+            origin: OriginFor<T>,
+            tx_intents: Vec<pallet_tx_validation::TxIntent<T::AccountId>>,
         ) -> DispatchResult {
             let who = ensure_signed(origin)?;
             log::info!(
-                "[ocw-garble] callback_new_garbled_signed: ({:?},{:?})",
-                sp_std::str::from_utf8(&pgarbled_cid).map_err(|_err| <Error<T>>::Utf8Error)?,
+                "[ocw-garble] garble_and_strip_batch_signed: ({:?} for {:?})",
+                tx_intents.len(),
                 who
             );
 
-            Self::deposit_event(Event::NewGarbledIpfsCid(pgarbled_cid));
+            ensure!(
+                !tx_intents.is_empty() && tx_intents.len() as u32 <= T::MaxBatchSize::get(),
+                <Error<T>>::InvalidBatchSize
+            );
+            // CHECK before garbling; cf garble_and_strip_display_circuits_package_signed
+            ensure!(
+                <AccountToPendingCircuitsMap<T>>::decode_len(&who).unwrap_or(0) + tx_intents.len()
+                    <= MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT as usize,
+                <Error<T>>::TooManyPendingCircuits
+            );
+            Self::ensure_can_schedule_expiries(&who, tx_intents.len())?;
+
+            let display_circuits_package = Self::get_ocw_circuits_storage_value()?;
+            let message_skcd_buf = fetch_skcd::<T>(&display_circuits_package.message_skcd_cid)?;
+            let pinpad_skcd_buf = fetch_skcd::<T>(&display_circuits_package.pinpad_skcd_cid)?;
+
+            let mut packages = Vec::with_capacity(tx_intents.len());
+            let mut failed = Vec::new();
+            let mut failed_uploaded_cids = Vec::new();
+            for (index, tx_intent) in tx_intents.into_iter().enumerate() {
+                // allocated before the work, like the single version; a failed item keeps its id
+                let request_id = Self::next_request_id();
+                // IMPORTANT: generated OUTSIDE of the transaction, so that the nonce is NOT
+                // rolled back on failure; a failed item MUST NOT "give" its digits to the next one.
+                let (message_digits, pinpad_digits) = Self::generate_random_digits(
+                    &display_circuits_package.alphabet,
+                    display_circuits_package.message_skcd_server_metadata_nb_digits,
+                );
+
+                let mut uploaded_cids = Vec::new();
+                let result = frame_support::storage::with_transaction(|| {
+                    match Self::garble_and_strip_batch_item(
+                        &who,
+                        &display_circuits_package,
+                        &message_skcd_buf,
+                        &pinpad_skcd_buf,
+                        tx_intent,
+                        message_digits,
+                        pinpad_digits,
+                        &mut uploaded_cids,
+                    ) {
+                        Ok(package) => sp_runtime::TransactionOutcome::Commit(Ok(package)),
+                        Err(err) => sp_runtime::TransactionOutcome::Rollback(Err(err)),
+                    }
+                });
+
+                match result {
+                    Ok(package) => packages.push((request_id, package)),
+                    Err(err) => {
+                        log::warn!(
+                            "[ocw-garble] garble_and_strip_batch_signed: #{} failed: {:?}",
+                            index,
+                            err
+                        );
+                        failed.push((index as u32, err));
+                        failed_uploaded_cids.extend(uploaded_cids);
+                    }
+                }
+            }
+
+            let is_call_failing = packages.is_empty();
+            Self::release_failed_uploads(failed_uploaded_cids, is_call_failing);
+            if is_call_failing {
+                if let Some((_index, err)) = failed.into_iter().next() {
+                    return Err(err);
+                }
+            }
+
+            Self::deposit_event(Event::NewGarbleAndStrippedBatch {
+                account_id: who,
+                packages,
+                failed,
+                created_at: <frame_system::Pallet<T>>::block_number(),
+            });
+
+            Ok(())
+        }
+
+        /// Called by the offchain worker once the given cids are unpinned from IPFS
+        /// cf `unpin_released_circuits`
+        /// Not meant to be called by a user: the payload MUST be signed by one of the
+        /// `pallet_ocw_circuits::OcwAuthorities`; cf `validate_unsigned`
+        ///
+        /// NOTE: only the cids whose circuit is NOT pending anymore are removed;
+        /// but a cid removed from `PinnedCidsMap` is never unpinned again, so the caller MUST be trusted.
+        #[pallet::call_index(4)]
+        #[pallet::weight(10000)]
+        pub fn confirm_unpinned_unsigned(
+            origin: OriginFor<T>,
+            payload: ConfirmUnpinnedPayload<T::Public, T::BlockNumber>,
+            _signature: T::Signature,
+        ) -> DispatchResult {
+            ensure_none(origin)?;
+            log::info!(
+                "[ocw-garble] confirm_unpinned_unsigned: {} cids unpinned at {:?} by {:?}",
+                payload.pgarbled_cids.len(),
+                payload.block_number,
+                payload.public
+            );
+
+            let released_cids: Vec<Vec<u8>> = payload
+                .pgarbled_cids
+                .into_iter()
+                .filter(|pgarbled_cid| Self::is_pinned_cid_releasable(pgarbled_cid))
+                .collect();
+            for pgarbled_cid in &released_cids {
+                if let Ok(pgarbled_cid) =
+                    TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(pgarbled_cid.clone())
+                {
+                    <PinnedCidsMap<T>>::remove(pgarbled_cid);
+                }
+            }
+            <UnpinQueue<T>>::mutate(|unpin_queue| {
+                unpin_queue.retain(|pgarbled_cid| {
+                    !released_cids.contains(&pgarbled_cid.to_vec())
+                        && <PinnedCidsMap<T>>::contains_key(pgarbled_cid)
+                })
+            });
+
             Ok(())
         }
+
+        /// Garble a "generic" circuit(ie NOT a display one), typically generated by
+        /// `pallet-ocw-circuits::submit_config_generic_signed`
+        ///
+        /// param: skcd_cid: IPFS cid of the .skcd; cf Event `NewSkcdIpfsCid` in `pallet-ocw-circuits`
+        /// param: garbler_inputs: the garbler's inputs, "burned in" the package sent to the evaluator
+        #[pallet::call_index(2)]
+        #[pallet::weight(10000)]
+        pub fn garble_standard_signed(
+            origin: OriginFor<T>,
+            skcd_cid: Vec<u8>,
+            garbler_inputs: Vec<u8>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            let request_id = Self::next_request_id();
+            log::info!(
+                "[ocw-garble] garble_standard_signed: #{} ({:?}, {:?})",
+                request_id,
+                sp_std::str::from_utf8(&skcd_cid).map_err(|_err| <Error<T>>::Utf8Error)?,
+                who
+            );
+            // CHECK before garbling; cf garble_and_strip_display_circuits_package_signed
+            Self::ensure_can_schedule_expiries(&who, 1)?;
+
+            let result_grpc_call = Self::call_grpc_garble(skcd_cid, garbler_inputs)?;
+
+            let reply = match result_grpc_call {
+                GrpcCallReplyKind::GarbleStandard(reply) => reply,
+                GrpcCallReplyKind::GarbleAndStrip(..) => {
+                    return Err(<Error<T>>::UnknownOffchainMux.into())
+                }
+            };
+
+            Self::register_garbled_circuit(who, request_id, reply.pgarbled_cid.bytes().collect())
+        }
     }
 
     #[derive(Debug, Deserialize, Encode, Decode, Default)]
@@ -569,6 +1675,8 @@ pub mod pallet {
 
     // reply type for each GrpcCallKind
     enum GrpcCallReplyKind {
+        /// one reply: the evaluator package for the given "generic" circuit
+        GarbleStandard(crate::GarbleAndStripIpfsReply),
         /// two reply b/c we call the same endpoint twice: one for message, then one for pinpad
         /// param Vec<u8> = "digits"; generated randomly in "garble_and_strip_display_circuits_package_signed"
         ///   and passed all the way around
@@ -581,6 +1689,61 @@ pub mod pallet {
     }
 
     impl<T: Config> Pallet<T> {
+        /// MUST be called at dispatch entry, BEFORE the garbling; the id is then passed around
+        pub(crate) fn next_request_id() -> u64 {
+            let request_id = <NextRequestId<T>>::get();
+            <NextRequestId<T>>::put(request_id.wrapping_add(1));
+            request_id
+        }
+
+        /// cf https://github.com/paritytech/substrate/blob/master/frame/lottery/src/lib.rs
+        fn get_and_increment_nonce() -> Vec<u8> {
+            let nonce = <Nonce<T>>::get();
+            <Nonce<T>>::put(nonce.wrapping_add(1));
+            nonce.encode()
+        }
+
+        /// Nonce of the commitment stored by `pallet_tx_validation::store_metadata_aux`
+        /// NOTE: it is public(in the Storage); the commitment is keyed by `pallet_tx_validation::Config::CommitmentSecret`
+        fn generate_commitment_nonce() -> [u8; 32] {
+            let nonce = Self::get_and_increment_nonce();
+            T::GarbleRandomness::random_seed(&nonce)
+        }
+
+        /// Generate the random digits for both circuits, using `T::GarbleRandomness`
+        ///
+        /// param: alphabet: cf `circuits_storage_common::DisplayProfile`; MUST NOT be empty
+        /// return: (message_digits, pinpad_digits)
+        pub(crate) fn generate_random_digits(
+            alphabet: &[u8],
+            message_nb_digits: u32,
+        ) -> (Vec<u8>, Vec<u8>) {
+            let nonce = Self::get_and_increment_nonce();
+            let mut rng = ChaChaRng::from_seed(T::GarbleRandomness::random_seed(&nonce));
+
+            // typically we need (2-4) digits for the message
+            // and the whole alphabet(eg 10 digits) for the pinpad
+            // MUST SHUFFLE the pinpad digits, NOT randomize them
+            // each symbol of the alphabet MUST be in the final "digits"
+            let mut pinpad_digits: Vec<u8> = alphabet.to_vec();
+            pinpad_digits.shuffle(&mut rng);
+            let message_digits: Vec<u8> = (0..message_nb_digits)
+                .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                .collect();
+
+            (message_digits, pinpad_digits)
+        }
+
+        /// Garble a "generic" circuit and upload the package for the evaluator
+        fn call_grpc_garble(
+            skcd_cid: Vec<u8>,
+            garbler_inputs: Vec<u8>,
+        ) -> Result<GrpcCallReplyKind, Error<T>> {
+            let reply = call_grpc_garble_one::<T>(skcd_cid, garbler_inputs)?;
+
+            Ok(GrpcCallReplyKind::GarbleStandard(reply))
+        }
+
         /// Regroup the 2 calls to API_ENDPOINT_GARBLE_STRIP_URL in one
         fn call_grpc_garble_and_strip(
             message_skcd_ipfs_cid: Vec<u8>,
@@ -588,17 +1751,20 @@ pub mod pallet {
             tx_msg: Vec<u8>,
             message_digits: Vec<u8>,
             pinpad_digits: Vec<u8>,
+            pinpad_layout: &PinpadLayout,
         ) -> Result<GrpcCallReplyKind, Error<T>> {
             // TODO pass correct params for pinpad and message
             let message_reply = call_grpc_garble_and_strip_one::<T>(
                 message_skcd_ipfs_cid,
                 tx_msg,
                 message_digits.clone(),
+                None,
             )?;
             let pinpad_reply = call_grpc_garble_and_strip_one::<T>(
                 pinpad_skcd_ipfs_cid,
                 vec![],
                 pinpad_digits.clone(),
+                Some(pinpad_layout),
             )?;
 
             // TODO pass correct params for pinpad and message
@@ -680,20 +1846,185 @@ pub mod pallet {
         }
     }
 
-    fn get_ipfs_uri() -> alloc::string::String {
-        #[cfg(all(not(feature = "sgx"), feature = "std"))]
-        return std::env::var("IPFS_ROOT_URL").unwrap();
+    /// Resolve an endpoint, in priority order:
+    /// - `from_local_storage` cf `offchain_local_storage_get`; ONLY in offchain context, the
+    ///   dispatchables MUST give None
+    /// - the `Config` getter ie `config_value`
+    /// - the env var `env_var_name`(legacy)
+    ///
+    /// An empty value is considered "not set" and we move on to the next source.
+    pub(crate) fn get_endpoint<T: Config>(
+        from_local_storage: Option<Vec<u8>>,
+        config_value: Option<Vec<u8>>,
+        env_var_name: &str,
+    ) -> Result<String, Error<T>> {
+        let endpoint = match from_local_storage
+            .filter(|value| !value.is_empty())
+            .or_else(|| config_value.filter(|value| !value.is_empty()))
+        {
+            Some(value) => String::from_utf8(value).map_err(|_err| {
+                log::error!("[ocw-garble] endpoint for {} is not valid utf8!", env_var_name);
+                <Error<T>>::EndpointNotConfigured
+            })?,
+            None => get_env_var(env_var_name).ok_or_else(|| {
+                log::error!("[ocw-garble] endpoint not configured: {}", env_var_name);
+                <Error<T>>::EndpointNotConfigured
+            })?,
+        };
 
-        #[cfg(all(not(feature = "std"), feature = "sgx"))]
-        return sgx_tstd::env::var("IPFS_ROOT_URL").unwrap();
+        Ok(endpoint)
+    }
+
+    /// Read an endpoint from the offchain local storage(PERSISTENT); the value is the raw utf8 url
+    /// It can be set at runtime with eg the `offchain_localStorageSet` RPC
+    ///
+    /// IMPORTANT: offchain context ONLY(ie `offchain_worker`): there is no `OffchainDbExt` during
+    /// block import(it would panic), and the value is per node so it MUST NOT change a dispatch.
+    #[cfg_attr(not(feature = "sp-io"), allow(unused_variables))]
+    pub(crate) fn offchain_local_storage_get(local_storage_key: &[u8]) -> Option<Vec<u8>> {
+        #[cfg(feature = "sp-io")]
+        return sp_io::offchain::local_storage_get(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            local_storage_key,
+        );
+
+        #[cfg(not(feature = "sp-io"))]
+        return None;
     }
 
-    fn get_node_uri() -> alloc::string::String {
+    fn get_env_var(env_var_name: &str) -> Option<String> {
         #[cfg(all(not(feature = "sgx"), feature = "std"))]
-        return std::env::var("INTERSTELLAR_URI_NODE").unwrap();
+        return std::env::var(env_var_name).ok().filter(|value| !value.is_empty());
 
         #[cfg(all(not(feature = "std"), feature = "sgx"))]
-        return sgx_tstd::env::var("INTERSTELLAR_URI_NODE").unwrap();
+        return sgx_tstd::env::var(env_var_name)
+            .ok()
+            .filter(|value| !value.is_empty());
+
+        #[cfg(not(any(feature = "std", feature = "sgx")))]
+        return None;
+    }
+
+    /// NOTE: can be a comma separated list of endpoints, in priority order; cf `ipfs_call_with_failover`
+    ///
+    /// param: from_local_storage: cf `get_endpoint`
+    pub(crate) fn get_ipfs_uris<T: Config>(
+        from_local_storage: Option<Vec<u8>>,
+    ) -> Result<Vec<String>, Error<T>> {
+        let endpoints =
+            get_endpoint::<T>(from_local_storage, T::IpfsRootUrl::get(), "IPFS_ROOT_URL")?;
+        let endpoints = circuits_storage_common::ipfs_failover::parse_endpoints(&endpoints);
+        if endpoints.is_empty() {
+            return Err(<Error<T>>::EndpointNotConfigured);
+        }
+
+        Ok(endpoints)
+    }
+
+    /// Health tracking of the IPFS endpoints; in memory b/c `ipfs_call_with_failover` is called
+    /// from the dispatchables, where the offchain local storage is NOT available
+    #[cfg(feature = "std")]
+    type IpfsFailoverEnv = circuits_storage_common::ipfs_failover::InMemoryFailoverEnv;
+    #[cfg(not(feature = "std"))]
+    type IpfsFailoverEnv = ();
+
+    /// Unpin `pgarbled_cid` from ALL the IPFS endpoints(pins are per node)
+    ///
+    /// param: ipfs_uris: cf `get_ipfs_uris`; resolved once by the caller
+    /// return: Ok if at least one endpoint unpinned it
+    pub(crate) fn unpin_cid<T: Config>(
+        ipfs_uris: &[String],
+        pgarbled_cid: &[u8],
+    ) -> Result<(), Error<T>> {
+        let pgarbled_cid_str =
+            sp_std::str::from_utf8(pgarbled_cid).map_err(|_err| <Error<T>>::Utf8Error)?;
+        let timeout = core::time::Duration::from_millis(T::IpfsRequestTimeoutMs::get());
+
+        let mut is_unpinned = false;
+        for endpoint in ipfs_uris {
+            match interstellar_http_client::ClientHttpReq::send_request(
+                None,
+                &alloc::format!("{}/api/v0/pin/rm?arg={}", endpoint, pgarbled_cid_str),
+                &interstellar_http_client::MyRequestMethod::Post,
+                None,
+                timeout,
+            ) {
+                Ok(_) => is_unpinned = true,
+                Err(err) => log::warn!(
+                    "[ocw-garble] unpin {} from {} error: {:?}",
+                    pgarbled_cid_str,
+                    endpoint,
+                    err
+                ),
+            }
+        }
+
+        match is_unpinned {
+            true => Ok(()),
+            false => Err(<Error<T>>::IpfsCallError),
+        }
+    }
+
+    fn new_ipfs_client<T: Config>(
+        endpoint: &str,
+    ) -> Result<interstellar_ipfs_client::IpfsClientHttpReq, Error<T>> {
+        interstellar_ipfs_client::IpfsClientHttpReq::new(endpoint).map_err(|err| {
+            log::error!("[ocw-garble] ipfs client new error: {:?}", err);
+            <Error<T>>::IpfsClientCreationError
+        })
+    }
+
+    /// Call `f` with each of the configured IPFS endpoints, and the request timeout, until one
+    /// succeeds in time; cf `with_failover`
+    fn ipfs_call_with_failover<T: Config, R>(
+        mut f: impl FnMut(&str, core::time::Duration) -> Result<R, Error<T>>,
+    ) -> Result<R, Error<T>> {
+        let config = circuits_storage_common::ipfs_failover::FailoverConfig {
+            request_timeout_ms: T::IpfsRequestTimeoutMs::get(),
+            ..Default::default()
+        };
+
+        circuits_storage_common::ipfs_failover::with_failover::<IpfsFailoverEnv, _, _, _>(
+            &get_ipfs_uris::<T>(None)?,
+            &config,
+            |endpoint, timeout| {
+                f(endpoint, timeout).map_err(|err| {
+                    log::warn!("[ocw-garble] IPFS endpoint {} failed: {:?}", endpoint, err);
+                    err
+                })
+            },
+        )
+        .map_err(|err| err.unwrap_or(<Error<T>>::EndpointNotConfigured))
+    }
+
+    /// NOTE: only used from the dispatchables; so NOT from the offchain local storage
+    fn get_node_uri<T: Config>() -> Result<String, Error<T>> {
+        get_endpoint::<T>(None, T::NodeUri::get(), "INTERSTELLAR_URI_NODE")
+    }
+
+    /// Verify a storage proof(ie the trie nodes returned by `state_getReadProof`) against `state_root`
+    ///
+    /// return: the value at `storage_key`; None if the proof is invalid, or if the value is not set
+    pub(crate) fn verify_storage_proof(
+        state_root: sp_core::H256,
+        proof_nodes: Vec<Vec<u8>>,
+        storage_key: &[u8],
+    ) -> Option<Vec<u8>> {
+        let db = sp_trie::StorageProof::new(proof_nodes)
+            .into_memory_db::<sp_runtime::traits::BlakeTwo256>();
+
+        sp_trie::read_trie_value::<sp_trie::LayoutV1<sp_runtime::traits::BlakeTwo256>, _>(
+            &db,
+            &state_root,
+            storage_key,
+            None,
+            None,
+        )
+        .map_err(|err| {
+            log::error!("[ocw-garble] verify_storage_proof error: {:?}", err);
+        })
+        .ok()
+        .flatten()
     }
 
     /// Compute the Storage key; version for RPC
@@ -709,84 +2040,142 @@ pub mod pallet {
         "0x".to_string() + &hex::encode(raw_hash)
     }
 
+    /// INTERNAL: download a .skcd from IPFS(or the cache)
+    fn fetch_skcd<T: Config>(skcd_cid: &[u8]) -> Result<Vec<u8>, Error<T>> {
+        let skcd_cid_str = sp_std::str::from_utf8(skcd_cid)
+            .map_err(|_err| <Error<T>>::Utf8Error)?
+            .to_owned();
+
+        crate::skcd_cache::get_or_fetch(skcd_cid, || {
+            ipfs_call_with_failover::<T, _>(|endpoint, timeout| {
+                // NOTE: `IpfsClientHttpReq::ipfs_cat` has no deadline; so call the API directly
+                interstellar_http_client::ClientHttpReq::send_request(
+                    None,
+                    &alloc::format!("{}/api/v0/cat?arg={}", endpoint, skcd_cid_str),
+                    &interstellar_http_client::MyRequestMethod::Post,
+                    None,
+                    timeout,
+                )
+                .map(|(body, _content_type)| body.to_vec())
+                .map_err(|err| {
+                    log::error!("[ocw-garble] ipfs call cat error: {:?}", err);
+                    <Error<T>>::IpfsCallError
+                })
+            })
+        })
+    }
+
+    /// INTERNAL: upload the serialized package(ie `Garbler` output) to IPFS
+    fn upload_for_evaluator<T: Config>(
+        serialized_package_for_eval: Vec<u8>,
+    ) -> Result<crate::GarbleAndStripIpfsReply, Error<T>> {
+        // NOTE: `ipfs_add` has no deadline; a late answer is only used as a last resort
+        let ipfs_add_response = ipfs_call_with_failover::<T, _>(|endpoint, _timeout| {
+            new_ipfs_client::<T>(endpoint)?
+                .ipfs_add(&serialized_package_for_eval)
+                .map_err(|err| {
+                    log::error!("[ocw-garble] ipfs call ipfs_add error: {:?}", err);
+                    <Error<T>>::IpfsCallError
+                })
+        })?;
+
+        Ok(crate::GarbleAndStripIpfsReply {
+            pgarbled_cid: ipfs_add_response.hash,
+        })
+    }
+
+    /// INTERNAL: garble one "generic" circuit
+    fn call_grpc_garble_one<T: Config>(
+        skcd_cid: Vec<u8>,
+        garbler_inputs: Vec<u8>,
+    ) -> Result<crate::GarbleAndStripIpfsReply, Error<T>> {
+        let skcd_buf = fetch_skcd::<T>(&skcd_cid)?;
+
+        let serialized_package_for_eval = T::Garbler::garble(&skcd_buf, &garbler_inputs)
+            .map_err(|err| {
+                log::error!("[ocw-garble] Garbler::garble error: {}", err);
+                <Error<T>>::GarblerError
+            })?;
+
+        upload_for_evaluator::<T>(serialized_package_for_eval)
+    }
+
     /// INTERNAL: call API_ENDPOINT_GARBLE_STRIP_URL for one circuits
+    ///
+    /// param: pinpad_layout: None for the message; cf `pallet_ocw_circuits::pinpad_display_layout`
     fn call_grpc_garble_and_strip_one<T: Config>(
         skcd_cid: Vec<u8>,
         tx_msg: Vec<u8>,
         digits: Vec<u8>,
+        pinpad_layout: Option<&PinpadLayout>,
     ) -> Result<crate::GarbleAndStripIpfsReply, Error<T>> {
-        let skcd_cid_str = sp_std::str::from_utf8(&skcd_cid)
-            .map_err(|_err| <Error<T>>::Utf8Error)?
-            .to_owned();
-        let tx_msg_str = sp_std::str::from_utf8(&tx_msg)
-            .map_err(|_err| <Error<T>>::Utf8Error)?
-            .to_owned();
+        let skcd_buf = fetch_skcd::<T>(&skcd_cid)?;
 
-        let ipfs_client = interstellar_ipfs_client::IpfsClientHttpReq::new(&get_ipfs_uri())
-            .map_err(|err| {
-                log::error!("[ocw-garble] ipfs client new error: {:?}", err);
-                <Error<T>>::IpfsClientCreationError
-            })?;
-        let skcd_buf = ipfs_client.ipfs_cat(&skcd_cid_str).map_err(|err| {
-            log::error!("[ocw-garble] ipfs call ipfs_cat error: {:?}", err);
-            <Error<T>>::IpfsCallError
-        })?;
+        garble_and_strip_skcd_buf::<T>(&skcd_buf, tx_msg, digits, pinpad_layout)
+    }
 
-        let garb = lib_garble_rs::garble_skcd(&skcd_buf).map_err(|err| {
-            log::error!(
-                "[ocw-garble] lib_garble_rs::garble_skcd error: {:?} {:?}",
-                err.to_string(),
-                err
-            );
-            <Error<T>>::GarblerError
-        })?;
-        // "packsmg"
-        let encoded_garbler_inputs =
-            lib_garble_rs::garbled_display_circuit_prepare_garbler_inputs(
-                &garb,
-                &digits,
-                &tx_msg_str,
-            )
-            .map_err(|err| {
-                log::error!(
-                    "[ocw-garble] lib_garble_rs::garbled_display_circuit_prepare_garbler_inputs error: {:?} {:?}",
-                    err.to_string(),
-                    err
-                );
+    /// Same as `call_grpc_garble_and_strip_one` but with an already downloaded .skcd
+    /// eg `garble_and_strip_batch_signed` downloads it only once for the whole batch
+    fn garble_and_strip_skcd_buf<T: Config>(
+        skcd_buf: &[u8],
+        tx_msg: Vec<u8>,
+        digits: Vec<u8>,
+        pinpad_layout: Option<&PinpadLayout>,
+    ) -> Result<crate::GarbleAndStripIpfsReply, Error<T>> {
+        let tx_msg_str =
+            sp_std::str::from_utf8(&tx_msg).map_err(|_err| <Error<T>>::Utf8Error)?;
+
+        let serialized_package_for_eval =
+            T::Garbler::garble_and_strip(skcd_buf, &digits, tx_msg_str).map_err(|err| {
+                log::error!("[ocw-garble] Garbler::garble_and_strip error: {}", err);
                 <Error<T>>::GarblerError
             })?;
-        // then serialize "garb" and "packmsg"
-        let serialized_package_for_eval =
-            lib_garble_rs::serialize_for_evaluator(garb, encoded_garbler_inputs).map_err(
-                |err| {
-                    log::error!(
-                        "[ocw-garble] lib_garble_rs::serialize_for_evaluator error: {:?} {:?}",
-                        err.to_string(),
-                        err
-                    );
-                    <Error<T>>::GarblerError
-                },
-            )?;
 
-        // the tests need the full body bytes to mock correctly...
-        #[cfg(test)]
-        let serialized_package_for_eval =
-            T::HookCallGrpGarbleAndStripSerializedPackageForEval::my_test_hook(
-                serialized_package_for_eval,
-            );
+        if T::SelfCheckGarbledPackages::get() {
+            self_check_garbled_package::<T>(&serialized_package_for_eval, &digits, pinpad_layout)?;
+        }
 
-        let ipfs_add_response =
-            ipfs_client
-                .ipfs_add(&serialized_package_for_eval)
+        upload_for_evaluator::<T>(serialized_package_for_eval)
+    }
+
+    /// Each frame only shows some of the ON segments; 16 is enough to see all of them
+    const SELF_CHECK_NB_FRAMES: u32 = 16;
+
+    /// INTERNAL: evaluate the package like the phone does, and check that each cell shows `digits`
+    /// A bug in the layout or in the garbler inputs would otherwise silently lock the users out.
+    fn self_check_garbled_package<T: Config>(
+        serialized_package_for_eval: &[u8],
+        digits: &[u8],
+        pinpad_layout: Option<&PinpadLayout>,
+    ) -> Result<(), Error<T>> {
+        let layout = match pinpad_layout {
+            Some(pinpad_layout) => pallet_ocw_circuits::pinpad_display_layout(pinpad_layout),
+            None => pallet_ocw_circuits::display_layout(true, digits.len() as u32),
+        };
+
+        let frame =
+            T::Garbler::evaluate(serialized_package_for_eval, SELF_CHECK_NB_FRAMES, &layout)
                 .map_err(|err| {
-                    log::error!("[ocw-garble] ipfs call ipfs_add error: {:?}", err);
-                    <Error<T>>::IpfsCallError
+                    log::error!("[ocw-garble] Garbler::evaluate error: {}", err);
+                    <Error<T>>::GarbledPackageSelfCheckFailed
                 })?;
+        let (_width, _height, digits_bboxes) = layout;
+        let digits_shown = seven_segments::read_digits(&frame, &digits_bboxes);
+
+        let wrong_cells: Vec<usize> = (0..digits.len().max(digits_shown.len()))
+            .filter(|index| {
+                digits_shown.get(*index).copied().flatten() != digits.get(*index).copied()
+            })
+            .collect();
+        if !wrong_cells.is_empty() {
+            // IMPORTANT: only the cell indexes; the digits are the secrets(OTP, pinpad permutation)
+            log::error!(
+                "[ocw-garble] self-check failed: wrong cells {:?}",
+                wrong_cells
+            );
+            return Err(<Error<T>>::GarbledPackageSelfCheckFailed);
+        }
 
-        // TODO
-        // let resp: GarbleAndStripIpfsReply = ;
-        Ok(crate::GarbleAndStripIpfsReply {
-            pgarbled_cid: ipfs_add_response.hash,
-        })
+        Ok(())
     }
 }
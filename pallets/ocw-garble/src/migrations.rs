@@ -0,0 +1,345 @@
+//! Storage migrations; called from `Hooks::on_runtime_upgrade`
+//! They are chained: v2 then v3 then v4; each one is skipped if the on-chain version is already there.
+
+/// v1 -> v2: `DisplayStrippedCircuitsPackage::pinpad_nb_digits`
+pub mod v2 {
+    use super::v3;
+    use crate::pallet::*;
+    use frame_support::pallet_prelude::*;
+    use frame_support::storage::StoragePrefixedMap;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+    use sp_std::vec::Vec;
+
+    /// v1 `DisplayStrippedCircuitsPackage`
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen)]
+    pub struct OldDisplayStrippedCircuitsPackage {
+        pub message_pgarbled_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_pgarbled_cid: BoundedVec<u8, ConstU32<64>>,
+        pub message_nb_digits: u32,
+    }
+
+    pub type OldPendingCircuitsType = BoundedVec<
+        OldDisplayStrippedCircuitsPackage,
+        ConstU32<MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT>,
+    >;
+
+    /// `AccountToPendingCircuitsMap` with its v1 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldAccountToPendingCircuitsMap<T: Config> = StorageMap<
+        Pallet<T>,
+        Twox128,
+        <T as frame_system::Config>::AccountId,
+        OldPendingCircuitsType,
+        ValueQuery,
+    >;
+
+    /// Before v2 the pinpad was always the 10 decimal digits
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 2 {
+            log::info!(
+                "[ocw-garble] migration v2: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let mut nb_accounts: u64 = 0;
+        // NOTE: `translate_values` b/c Twox128 is NOT reversible
+        <v3::OldAccountToPendingCircuitsMap<T>>::translate_values::<OldPendingCircuitsType, _>(
+            |old_pending_circuits| {
+                nb_accounts += 1;
+                let pending_circuits: Vec<_> = old_pending_circuits
+                    .into_iter()
+                    .map(|old| v3::OldDisplayStrippedCircuitsPackage {
+                        message_pgarbled_cid: old.message_pgarbled_cid,
+                        pinpad_pgarbled_cid: old.pinpad_pgarbled_cid,
+                        message_nb_digits: old.message_nb_digits,
+                        pinpad_nb_digits: 10,
+                    })
+                    .collect();
+                // NOT truncated: same bound
+                Some(BoundedVec::truncate_from(pending_circuits))
+            },
+        );
+        StorageVersion::new(2).put::<Pallet<T>>();
+
+        log::info!(
+            "[ocw-garble] migration v2: pending circuits of {} accounts translated",
+            nb_accounts
+        );
+
+        T::DbWeight::get().reads_writes(1 + nb_accounts, 1 + nb_accounts)
+    }
+
+    /// return: the number of accounts with v1 pending circuits; None if skipped
+    #[cfg(feature = "try-runtime")]
+    pub fn pre_upgrade<T: Config>() -> Result<Vec<u8>, &'static str> {
+        // NOTE: `iter_values` skips the values that can not be decoded
+        let nb_accounts: Option<u64> = if Pallet::<T>::on_chain_storage_version() < 2 {
+            Some(<OldAccountToPendingCircuitsMap<T>>::iter_values().count() as u64)
+        } else {
+            None
+        };
+
+        Ok(nb_accounts.encode())
+    }
+
+    /// NOTE: called after the whole chain; ie the v1 pending circuits are now v4 ones
+    #[cfg(feature = "try-runtime")]
+    pub fn post_upgrade<T: Config>(state: Vec<u8>) -> Result<(), &'static str> {
+        ensure!(
+            Pallet::<T>::on_chain_storage_version() >= 2,
+            "[ocw-garble] migration v2: wrong on-chain version"
+        );
+
+        let nb_accounts: Option<u64> =
+            Decode::decode(&mut state.as_slice()).map_err(|_err| "invalid pre_upgrade state")?;
+        if let Some(nb_accounts) = nb_accounts {
+            ensure!(
+                <AccountToPendingCircuitsMap<T>>::iter_values().count() as u64 == nb_accounts,
+                "[ocw-garble] migration v2: some pending circuits were not translated"
+            );
+            // every pending circuit was a v1 one: ie a decimal pinpad
+            ensure!(
+                <AccountToPendingCircuitsMap<T>>::iter_values()
+                    .flatten()
+                    .all(|package| package.pinpad_nb_digits == 10),
+                "[ocw-garble] migration v2: wrong pinpad_nb_digits"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// v2 -> v3: `DisplayStrippedCircuitsPackage::pinpad_layout`
+pub mod v3 {
+    use crate::pallet::*;
+    use circuits_storage_common::PinpadLayout;
+    use frame_support::pallet_prelude::*;
+    use frame_support::storage::StoragePrefixedMap;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+    use sp_std::vec::Vec;
+
+    /// v2 `DisplayStrippedCircuitsPackage`
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen)]
+    pub struct OldDisplayStrippedCircuitsPackage {
+        pub message_pgarbled_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_pgarbled_cid: BoundedVec<u8, ConstU32<64>>,
+        pub message_nb_digits: u32,
+        pub pinpad_nb_digits: u32,
+    }
+
+    pub type OldPendingCircuitsType = BoundedVec<
+        OldDisplayStrippedCircuitsPackage,
+        ConstU32<MAX_NUMBER_PENDING_CIRCUITS_PER_ACCOUNT>,
+    >;
+
+    /// `AccountToPendingCircuitsMap` with its v2 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldAccountToPendingCircuitsMap<T: Config> = StorageMap<
+        Pallet<T>,
+        Twox128,
+        <T as frame_system::Config>::AccountId,
+        OldPendingCircuitsType,
+        ValueQuery,
+    >;
+
+    /// Before v3 the pinpad was always one row; ie `PinpadLayout::row(10)` for the packages from v1
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 3 {
+            log::info!(
+                "[ocw-garble] migration v3: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let mut nb_accounts: u64 = 0;
+        <AccountToPendingCircuitsMap<T>>::translate_values::<OldPendingCircuitsType, _>(
+            |old_pending_circuits| {
+                nb_accounts += 1;
+                let pending_circuits: Vec<_> = old_pending_circuits
+                    .into_iter()
+                    .map(|old| DisplayStrippedCircuitsPackage {
+                        message_pgarbled_cid: old.message_pgarbled_cid,
+                        pinpad_pgarbled_cid: old.pinpad_pgarbled_cid,
+                        message_nb_digits: old.message_nb_digits,
+                        pinpad_nb_digits: old.pinpad_nb_digits,
+                        pinpad_layout: PinpadLayout::row(old.pinpad_nb_digits),
+                    })
+                    .collect();
+                // NOT truncated: same bound
+                Some(BoundedVec::truncate_from(pending_circuits))
+            },
+        );
+        StorageVersion::new(3).put::<Pallet<T>>();
+
+        log::info!(
+            "[ocw-garble] migration v3: pending circuits of {} accounts translated",
+            nb_accounts
+        );
+
+        T::DbWeight::get().reads_writes(1 + nb_accounts, 1 + nb_accounts)
+    }
+
+    /// return: the number of accounts with v2 pending circuits; None if skipped
+    /// NOTE: v1 ones(cf `v2::pre_upgrade`) are also counted: v2 runs first
+    #[cfg(feature = "try-runtime")]
+    pub fn pre_upgrade<T: Config>() -> Result<Vec<u8>, &'static str> {
+        // NOTE: `iter_values` skips the values that can not be decoded
+        let nb_accounts: Option<u64> = match Pallet::<T>::on_chain_storage_version() {
+            v if v < 2 => {
+                Some(<super::v2::OldAccountToPendingCircuitsMap<T>>::iter_values().count())
+            }
+            v if v < 3 => Some(<OldAccountToPendingCircuitsMap<T>>::iter_values().count()),
+            _ => None,
+        }
+        .map(|nb_accounts| nb_accounts as u64);
+
+        Ok(nb_accounts.encode())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    pub fn post_upgrade<T: Config>(state: Vec<u8>) -> Result<(), &'static str> {
+        ensure!(
+            Pallet::<T>::on_chain_storage_version() >= 3,
+            "[ocw-garble] migration v3: wrong on-chain version"
+        );
+
+        let nb_accounts: Option<u64> =
+            Decode::decode(&mut state.as_slice()).map_err(|_err| "invalid pre_upgrade state")?;
+        if let Some(nb_accounts) = nb_accounts {
+            // NOTE: `iter_values` skips the values that can not be decoded
+            ensure!(
+                <AccountToPendingCircuitsMap<T>>::iter_values().count() as u64 == nb_accounts,
+                "[ocw-garble] migration v3: some pending circuits were not translated"
+            );
+            // every pending circuit was a v1/v2 one: ie a single row pinpad
+            ensure!(
+                <AccountToPendingCircuitsMap<T>>::iter_values()
+                    .flatten()
+                    .all(|package| package.pinpad_layout
+                        == PinpadLayout::row(package.pinpad_nb_digits)),
+                "[ocw-garble] migration v3: wrong pinpad_layout"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// v3 -> v4: `PinpadPool` removed(its digits were public, and can NOT be kept anywhere else)
+/// and `VerifiedDisplaySkcdPackageCache` removed(cf `verified_package_cache`)
+pub mod v4 {
+    use crate::pallet::*;
+    use frame_support::pallet_prelude::*;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+    use sp_std::vec::Vec;
+
+    /// v3 `MAX_PINPAD_POOL_SIZE`
+    pub const OLD_MAX_PINPAD_POOL_SIZE: u32 = 64;
+
+    /// v3 `PinpadPoolEntry`: the digits in clear, in the Storage
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen)]
+    pub struct OldPinpadPoolEntry {
+        pub pinpad_skcd_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_pgarbled_cid: BoundedVec<u8, ConstU32<64>>,
+        pub pinpad_digits: BoundedVec<u8, ConstU32<64>>,
+    }
+
+    /// `PinpadPool`, removed; with its v3 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldPinpadPool<T: Config> = StorageValue<
+        Pallet<T>,
+        BoundedVec<OldPinpadPoolEntry, ConstU32<OLD_MAX_PINPAD_POOL_SIZE>>,
+        ValueQuery,
+    >;
+
+    /// Removed; now in memory
+    /// NOTE: never read, only killed; so the value type does not matter
+    #[frame_support::storage_alias]
+    pub(crate) type OldVerifiedDisplaySkcdPackageCache<T: Config> =
+        StorageValue<Pallet<T>, Vec<u8>, OptionQuery>;
+
+    /// The old entries can NOT be kept: their digits were public.
+    /// So the pool is removed, and the pinpads queued for unpinning(cf `UnpinQueue`).
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 4 {
+            log::info!(
+                "[ocw-garble] migration v4: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let old_pinpad_pool = <OldPinpadPool<T>>::take();
+        let nb_entries = old_pinpad_pool.len() as u64;
+        for old_pinpad in old_pinpad_pool {
+            // NOT an error: it is left to the sweep; cf `enqueue_unpin`
+            let _ = <UnpinQueue<T>>::try_append(old_pinpad.pinpad_pgarbled_cid);
+        }
+        <OldVerifiedDisplaySkcdPackageCache<T>>::kill();
+        StorageVersion::new(4).put::<Pallet<T>>();
+
+        log::info!(
+            "[ocw-garble] migration v4: {} pre-garbled pinpads discarded",
+            nb_entries
+        );
+
+        T::DbWeight::get().reads_writes(3, 4)
+    }
+
+    /// return: the pgarbled cids of the pool; they MUST be queued for unpinning. None if skipped
+    #[cfg(feature = "try-runtime")]
+    pub fn pre_upgrade<T: Config>() -> Result<Vec<u8>, &'static str> {
+        let old_pinpad_pgarbled_cids: Option<Vec<BoundedVec<u8, ConstU32<64>>>> =
+            if Pallet::<T>::on_chain_storage_version() < 4 {
+                Some(
+                    <OldPinpadPool<T>>::get()
+                        .into_iter()
+                        .map(|old_pinpad| old_pinpad.pinpad_pgarbled_cid)
+                        .collect(),
+                )
+            } else {
+                None
+            };
+
+        Ok(old_pinpad_pgarbled_cids.encode())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    pub fn post_upgrade<T: Config>(state: Vec<u8>) -> Result<(), &'static str> {
+        ensure!(
+            Pallet::<T>::on_chain_storage_version() >= 4,
+            "[ocw-garble] migration v4: wrong on-chain version"
+        );
+        ensure!(
+            !<OldPinpadPool<T>>::exists(),
+            "[ocw-garble] migration v4: the pool was not removed"
+        );
+        ensure!(
+            !<OldVerifiedDisplaySkcdPackageCache<T>>::exists(),
+            "[ocw-garble] migration v4: the cache was not removed"
+        );
+
+        let old_pinpad_pgarbled_cids: Option<Vec<BoundedVec<u8, ConstU32<64>>>> =
+            Decode::decode(&mut state.as_slice()).map_err(|_err| "invalid pre_upgrade state")?;
+        if let Some(old_pinpad_pgarbled_cids) = old_pinpad_pgarbled_cids {
+            let unpin_queue = <UnpinQueue<T>>::get();
+            // NOTE: a full queue is NOT an error; cf `migrate`
+            ensure!(
+                unpin_queue.is_full()
+                    || old_pinpad_pgarbled_cids
+                        .iter()
+                        .all(|cid| unpin_queue.contains(cid)),
+                "[ocw-garble] migration v4: some pre-garbled pinpads were not queued for unpinning"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use crate as pallet_ocw_garble;
 use crate::*;
 use frame_support::{
     parameter_types,
-    traits::{ConstU32, ConstU64},
+    traits::{ConstBool, ConstU32, ConstU64},
 };
 use httpmock::prelude::*;
 use serde_json::json;
@@ -16,6 +16,7 @@ use sp_runtime::{
     testing::{Header, TestXt},
     traits::{BlakeTwo256, Extrinsic as ExtrinsicT, IdentifyAccount, IdentityLookup, Verify},
 };
+use std::cell::RefCell;
 use std::io::Cursor;
 use tests_utils::foreign_ipfs;
 use tests_utils::foreign_ipfs::IpfsApi;
@@ -41,10 +42,25 @@ impl pallet_ocw_circuits::Config for Test {
     type RuntimeEvent = RuntimeEvent;
     type RuntimeCall = RuntimeCall;
     type AuthorityId = crypto::TestAuthId;
+    type IpfsRootUrl = ();
+    type IpfsRequestTimeoutMs = ConstU64<10_000>;
+    type OnNewDisplaySkcdPackage = OcwGarble;
+    type CircuitGenerator = ();
+    type GenerationServiceUrl = ();
+    type CircuitGeneratorAvailable = ConstBool<true>;
+    type UnsignedPriority = ConstU64<{ 1 << 20 }>;
+    type DisplayProfile = circuits_storage_common::DecimalDisplayProfile<2>;
 }
 
 impl pallet_tx_validation::Config for Test {
     type RuntimeEvent = RuntimeEvent;
+    type Executor = ();
+    type OnCircuitValidated = OcwGarble;
+    type MobileKeyVerifier = ();
+    type UnsignedLongevity = ConstU64<10>;
+    type MaxMessageDigits = ConstU32<{ circuits_storage_common::MAX_MESSAGE_NB_DIGITS }>;
+    type MaxPinpadDigits = ConstU32<{ circuits_storage_common::MAX_ALPHABET_LEN }>;
+    type CommitmentSecret = MockCommitmentSecret;
 }
 
 impl frame_system::Config for Test {
@@ -107,15 +123,109 @@ where
 
 parameter_types! {
     pub const UnsignedPriority: u64 = 1 << 20;
+    pub const MockRandomSeed: [u8; 32] = [42u8; 32];
+    pub const MockCommitmentSecret: [u8; 32] = [43u8; 32];
+    /// Simulate a bug in the garbler; cf MockGarbler::evaluate
+    pub static MockGarblerShowsWrongDigits: bool = false;
+    /// Use the real `LibGarbleRs` instead of `MockGarbler`; cf TestGarbler
+    pub static UseLibGarbleRs: bool = false;
 }
 
 const OVERWRITTEN_SERIALIZED_IPFS_ADD: &[u8] = &[42, 42];
 
-pub struct MyTestCallbackMock;
-impl MyTestCallback for MyTestCallbackMock {
-    fn my_test_hook(_input: Vec<u8>) -> Vec<u8> {
+pub(crate) const PENDING_CIRCUIT_LIFETIME: u64 = 100;
+
+thread_local! {
+    /// cf "fn fallback_rpc_ocw_circuits_storage_value"
+    static TRUSTED_HEADER: RefCell<Option<(H256, H256)>> = RefCell::new(None);
+}
+
+pub struct MockTrustedHeader;
+impl TrustedParentchainHeader for MockTrustedHeader {
+    fn trusted_header() -> Option<(H256, H256)> {
+        TRUSTED_HEADER.with(|trusted_header| *trusted_header.borrow())
+    }
+}
+
+/// Fast fake: NO garbling at all
+/// It only accepts the .skcd in tests/data; cf MockType::InvalidSkcd
+pub struct MockGarbler;
+impl MockGarbler {
+    fn check_skcd(skcd_buf: &[u8]) -> Result<(), String> {
+        match skcd_buf == include_bytes!("../tests/data/result_display_message_120x52_2digits.postcard.bin")
+            || skcd_buf == include_bytes!("../tests/data/display_pinpad_590x50.skcd.postcard.bin")
+        {
+            true => Ok(()),
+            false => Err("MockGarbler: unknown .skcd".to_string()),
+        }
+    }
+}
+impl Garbler for MockGarbler {
+    fn garble(skcd_buf: &[u8], _garbler_inputs: &[u8]) -> Result<Vec<u8>, String> {
+        Self::check_skcd(skcd_buf)?;
         // MUST match mock_ipfs_add_response
-        OVERWRITTEN_SERIALIZED_IPFS_ADD.to_vec()
+        Ok(OVERWRITTEN_SERIALIZED_IPFS_ADD.to_vec())
+    }
+
+    fn garble_and_strip(
+        skcd_buf: &[u8],
+        digits: &[u8],
+        _tx_msg: &str,
+    ) -> Result<Vec<u8>, String> {
+        Self::check_skcd(skcd_buf)?;
+        // "package" = OVERWRITTEN_SERIALIZED_IPFS_ADD + the digits; cf `evaluate`
+        Ok([OVERWRITTEN_SERIALIZED_IPFS_ADD, digits].concat())
+    }
+
+    /// Draw the digits stored in the package by `garble_and_strip` in `layout`; NOT an evaluation
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        _nb_frames: u32,
+        (width, height, digits_bboxes): &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String> {
+        let digits = serialized_package_for_eval
+            .strip_prefix(OVERWRITTEN_SERIALIZED_IPFS_ADD)
+            .ok_or_else(|| "MockGarbler: not a package from garble_and_strip".to_string())?;
+
+        let mut frame = Frame::new(*width, *height);
+        for (bbox, digit) in digits_bboxes.chunks_exact(4).zip(digits.iter().copied()) {
+            let digit = match MockGarblerShowsWrongDigits::get() {
+                true => (digit + 1) % 10,
+                false => digit,
+            };
+            circuits_storage_common::seven_segments::draw_digit(&mut frame, bbox, digit);
+        }
+
+        Ok(frame)
+    }
+}
+
+/// `MockGarbler` by default; the real `LibGarbleRs` if `UseLibGarbleRs`(eg the e2e tests)
+pub struct TestGarbler;
+impl Garbler for TestGarbler {
+    fn garble(skcd_buf: &[u8], garbler_inputs: &[u8]) -> Result<Vec<u8>, String> {
+        match UseLibGarbleRs::get() {
+            true => LibGarbleRs::garble(skcd_buf, garbler_inputs),
+            false => MockGarbler::garble(skcd_buf, garbler_inputs),
+        }
+    }
+
+    fn garble_and_strip(skcd_buf: &[u8], digits: &[u8], tx_msg: &str) -> Result<Vec<u8>, String> {
+        match UseLibGarbleRs::get() {
+            true => LibGarbleRs::garble_and_strip(skcd_buf, digits, tx_msg),
+            false => MockGarbler::garble_and_strip(skcd_buf, digits, tx_msg),
+        }
+    }
+
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        nb_frames: u32,
+        layout: &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String> {
+        match UseLibGarbleRs::get() {
+            true => LibGarbleRs::evaluate(serialized_package_for_eval, nb_frames, layout),
+            false => MockGarbler::evaluate(serialized_package_for_eval, nb_frames, layout),
+        }
     }
 }
 
@@ -123,14 +233,24 @@ impl pallet_ocw_garble::Config for Test {
     type RuntimeEvent = RuntimeEvent;
     type RuntimeCall = RuntimeCall;
     type AuthorityId = crypto::TestAuthId;
-    type HookCallGrpGarbleAndStripSerializedPackageForEval = MyTestCallbackMock;
+    type GarbleRandomness = DeterministicRandomness<MockRandomSeed>;
+    type ValidationMetadata = TxValidation;
+    type PendingCircuitLifetime = ConstU64<PENDING_CIRCUIT_LIFETIME>;
+    type TrustedParentchainHeader = MockTrustedHeader;
+    type IpfsRootUrl = ();
+    type NodeUri = ();
+    type IpfsRequestTimeoutMs = ConstU64<10_000>;
+    type PinSweepInterval = ConstU64<10>;
+    type MaxBatchSize = ConstU32<4>;
+    type Garbler = TestGarbler;
+    type SelfCheckGarbledPackages = SelfCheckInDebugBuilds;
 }
 
 pub(crate) enum MockType {
     /// standard use case; two valid .skcd already present in IPFS via pallet-ocw-circuits
     RpcOcwCircuitsStorageValid,
     /// error case: the IPFS hash point to something that is NOT a .skcd
-    /// it SHOULD fail at "lib_garble_rs::garble_skcd"
+    /// it SHOULD fail at "Garbler::garble*"
     InvalidSkcd,
     /// error case: CAN NOT connect to the node
     RpcOcwCircuitsStorageNoResponse,
@@ -141,8 +261,16 @@ pub(crate) enum MockType {
     /// FALLBACK for https://github.com/Interstellar-Network/roadmap/issues/73
     /// If the storage can not be raed directly(ie in `integritee-worker`) we MUST fallback to using the RPC
     FallbackStorageNotWorkingInIntegriteeWorker,
+    /// error case: same as FallbackStorageNotWorkingInIntegriteeWorker but the RPC answer does NOT
+    /// match the trusted header
+    FallbackStorageInvalidProof,
+    /// the first IPFS endpoint is down; the second one is OK
+    IpfsFailover,
 }
 
+/// Not listening; cf MockType::IpfsDown and MockType::IpfsFailover
+pub(crate) const MOCK_IPFS_DOWN_URL: &str = "http://127.0.0.1:4242";
+
 /// Build genesis storage according to the mock runtime.
 ///
 /// should_mock_rpc_ocw_circuits_storage_valid:
@@ -157,10 +285,25 @@ pub(crate) async fn new_test_ext(
     // NOTE: PORT hardcoded in lib.rs so we can use a dynamic one
     let (foreign_node, ipfs_reference_client) = foreign_ipfs::run_ipfs_in_background(None);
 
+    TRUSTED_HEADER.with(|trusted_header| *trusted_header.borrow_mut() = None);
+    crate::skcd_cache::invalidate();
+    crate::verified_package_cache::invalidate();
+    UseLibGarbleRs::set(false);
+    circuits_storage_common::ipfs_failover::InMemoryFailoverEnv::reset();
+
     match mock_type {
         MockType::IpfsDown => {
             // "Kill the server": use a bad env var "IPFS_ROOT_URL"
-            std::env::set_var("IPFS_ROOT_URL", format!("http://127.0.0.1:{}", "4242"));
+            std::env::set_var("IPFS_ROOT_URL", MOCK_IPFS_DOWN_URL);
+        }
+        MockType::IpfsFailover => {
+            std::env::set_var(
+                "IPFS_ROOT_URL",
+                format!(
+                    "{},http://127.0.0.1:{}",
+                    MOCK_IPFS_DOWN_URL, foreign_node.api_port
+                ),
+            );
         }
         _ => {
             std::env::set_var(
@@ -173,8 +316,10 @@ pub(crate) async fn new_test_ext(
     match mock_type {
         MockType::RpcOcwCircuitsStorageValid
         | MockType::IpfsDown
+        | MockType::IpfsFailover
         | MockType::InvalidSkcd
-        | MockType::FallbackStorageNotWorkingInIntegriteeWorker => {
+        | MockType::FallbackStorageNotWorkingInIntegriteeWorker
+        | MockType::FallbackStorageInvalidProof => {
             // IPFS ADD the .skcd needed
             // let content = &[65u8, 90, 97, 122]; // AZaz
             let cursor = match mock_type {
@@ -200,6 +345,14 @@ pub(crate) async fn new_test_ext(
                     fallback_rpc_ocw_circuits_storage_value(
                         ipfs_add_response_1.hash,
                         ipfs_add_response_2.hash,
+                        true,
+                    );
+                }
+                MockType::FallbackStorageInvalidProof => {
+                    fallback_rpc_ocw_circuits_storage_value(
+                        ipfs_add_response_1.hash,
+                        ipfs_add_response_2.hash,
+                        false,
                     );
                 }
                 _ => {
@@ -234,7 +387,7 @@ pub(crate) async fn new_test_ext(
 /// cf "fn get_ocw_circuits_storage_value"
 ///
 /// based on https://github.com/paritytech/substrate/blob/e9b0facf70eeb08032cc7e83548c62f0b4a24bb1/frame/examples/offchain-worker/src/tests.rs#L385
-fn set_ocw_circuits_storage_direct(
+pub fn set_ocw_circuits_storage_direct(
     message_skcd_cid: String,
     pinpad_skcd_cid: String,
     t: &mut sp_io::TestExternalities,
@@ -251,6 +404,9 @@ fn set_ocw_circuits_storage_direct(
             )
             .unwrap(),
             pinpad_skcd_server_metadata_nb_digits: 10,
+            alphabet: circuits_storage_common::DisplayProfile::decimal(2).alphabet,
+            pinpad_layout: circuits_storage_common::PinpadLayout::row(10),
+            message_capacity: Default::default(),
         };
         let display_skcd_package_encoded = display_skcd_package.encode();
         // DO NOT hex encode!
@@ -271,40 +427,64 @@ fn set_ocw_circuits_storage_direct(
 /// we query the storage using a HTTP request; so we need to Mock it
 /// cf "fn get_ocw_circuits_storage_value"
 ///
+/// The "parentchain" is simulated using a separate TestExternalities: that way we can generate
+/// a real storage proof, and its state root is used as the trusted header.
+///
+/// param: is_trusted: if false the trusted header does NOT match the proof
+///
 /// based on https://github.com/paritytech/substrate/blob/e9b0facf70eeb08032cc7e83548c62f0b4a24bb1/frame/examples/offchain-worker/src/tests.rs#L385
-fn fallback_rpc_ocw_circuits_storage_value(message_skcd_cid: String, pinpad_skcd_cid: String) {
+fn fallback_rpc_ocw_circuits_storage_value(
+    message_skcd_cid: String,
+    pinpad_skcd_cid: String,
+    is_trusted: bool,
+) {
     // MOCK "integritee-node" RPC
     let mock_server_uri_node = MockServer::start();
     std::env::set_var("INTERSTELLAR_URI_NODE", mock_server_uri_node.base_url());
 
+    let block_hash = H256::repeat_byte(42);
     let body_json = json!({
         "jsonrpc": "2.0",
         "id": "1",
-        "method":"state_getStorage",
-        "params": [compute_storage_hash_hex_for_rpc("OcwCircuits", "DisplaySkcdPackageValue")]
+        "method":"state_getReadProof",
+        "params": [
+            [compute_storage_hash_hex_for_rpc("OcwCircuits", "DisplaySkcdPackageValue")],
+            "0x".to_string() + &hex::encode(block_hash.as_bytes()),
+        ]
     });
     let body = serde_json::to_string(&body_json).unwrap();
 
-    let display_skcd_package = circuits_storage_common::DisplaySkcdPackage {
-        message_skcd_cid: TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(
-            message_skcd_cid.as_bytes().to_vec(),
-        )
-        .unwrap(),
-        message_skcd_server_metadata_nb_digits: 2,
-        pinpad_skcd_cid: TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(
-            pinpad_skcd_cid.as_bytes().to_vec(),
-        )
-        .unwrap(),
-        pinpad_skcd_server_metadata_nb_digits: 10,
+    // "parentchain": set the Storage then generate the proof
+    let mut parentchain_ext = sp_io::TestExternalities::default();
+    set_ocw_circuits_storage_direct(message_skcd_cid, pinpad_skcd_cid, &mut parentchain_ext);
+    parentchain_ext.commit_all().unwrap();
+    let state_root = *parentchain_ext.backend.root();
+    let (_, proof) = parentchain_ext.execute_and_prove(|| {
+        frame_support::storage::unhashed::get_raw(&frame_support::storage::storage_prefix(
+            "OcwCircuits".as_bytes(),
+            "DisplaySkcdPackageValue".as_bytes(),
+        ))
+    });
+
+    let trusted_state_root = if is_trusted {
+        state_root
+    } else {
+        H256::repeat_byte(0)
     };
-    let display_skcd_package_encoded = display_skcd_package.encode();
-    let display_skcd_package_encoded_hex =
-        "0x".to_string() + &hex::encode(display_skcd_package_encoded);
+    TRUSTED_HEADER.with(|trusted_header| {
+        *trusted_header.borrow_mut() = Some((block_hash, trusted_state_root))
+    });
 
     let response_body_json = json!({
         "id": "1",
         "jsonrpc": "2.0",
-        "result": display_skcd_package_encoded_hex,
+        "result": {
+            "at": "0x".to_string() + &hex::encode(block_hash.as_bytes()),
+            "proof": proof
+                .into_iter_nodes()
+                .map(|node| "0x".to_string() + &hex::encode(node))
+                .collect::<Vec<String>>(),
+        },
     });
     let response_body = serde_json::to_vec(&response_body_json).unwrap();
 
@@ -315,46 +495,8 @@ fn fallback_rpc_ocw_circuits_storage_value(message_skcd_cid: String, pinpad_skcd
             .body(&body);
         then.status(200)
             .header("content-type", "application/json; charset=utf-8")
-            // cf "fn decode_rpc_json" for the expected format
-            // MUST match the param passed to "mock_ipfs_cat_response"
             .body(response_body);
     });
-
-    // TODO? when using feature "with_sp_offchain"
-    // make sure it's sent correctly
-    // state.fulfill_pending_request(
-    //     0,
-    //     testing::PendingRequest {
-    //         method: "POST".into(),
-    //         uri: MOCK_INTERSTELLAR_URI_NODE.to_string(),
-    //         headers: vec![(
-    //             "Content-Type".into(),
-    //             "application/json;charset=utf-8".into(),
-    //         )],
-    //         sent: true,
-    //         ..Default::default()
-    //     },
-    //     response_body,
-    //     vec![(
-    //         "content-type".into(),
-    //         "application/json; charset=utf-8".into(),
-    //     )],
-    // );
-    // state.expect_request(testing::PendingRequest {
-    //     method: "POST".into(),
-    //     uri: MOCK_INTERSTELLAR_URI_NODE.to_string(),
-    //     headers: vec![(
-    //         "Content-Type".into(),
-    //         "application/json;charset=utf-8".into(),
-    //     )],
-    //     body: body,
-    //     response: Some(response_body),
-    //     response_headers: vec![(
-    //         "content-type".into(),
-    //         "application/json; charset=utf-8".into(),
-    //     )],
-    //     ..Default::default()
-    // });
 }
 
 #[cfg(test)]
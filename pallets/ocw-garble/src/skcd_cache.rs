@@ -0,0 +1,97 @@
+//! In-process cache of the .skcd downloaded from IPFS, keyed by IPFS cid
+//!
+//! `DisplaySkcdPackageValue` rarely changes so there is no point in re-downloading the same
+//! .skcd for every `garble_and_strip_display_circuits_package_signed`.
+//! The cache is cleared when pallet-ocw-circuits has a new package; cf `OnNewDisplaySkcdPackage`.
+//!
+//! NOTE: the raw bytes are cached, not the parsed circuit: `Garbler` takes bytes.
+//! IMPORTANT: `spin::Mutex` instead of `std::sync::Mutex`: the SAME code is used with "std"(node, tests)
+//! and "sgx"(the enclave; no_std + sgx_tstd). In a wasm runtime the static only lives for the call.
+use sp_std::vec::Vec;
+
+const MAX_ENTRIES: usize = 8;
+const MAX_TOTAL_BYTES: usize = 64 * 1024 * 1024;
+
+/// LRU: the most recently used entry is at the END of `entries`
+pub(crate) struct SkcdCache {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    max_entries: usize,
+    max_total_bytes: usize,
+}
+
+impl SkcdCache {
+    pub(crate) const fn new(max_entries: usize, max_total_bytes: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            max_entries,
+            max_total_bytes,
+        }
+    }
+
+    pub(crate) fn get(&mut self, skcd_cid: &[u8]) -> Option<Vec<u8>> {
+        let position = self
+            .entries
+            .iter()
+            .position(|(cid, _)| cid.as_slice() == skcd_cid)?;
+        let entry = self.entries.remove(position);
+        let skcd_buf = entry.1.clone();
+        self.entries.push(entry);
+
+        Some(skcd_buf)
+    }
+
+    pub(crate) fn insert(&mut self, skcd_cid: Vec<u8>, skcd_buf: Vec<u8>) {
+        self.entries.retain(|(cid, _)| *cid != skcd_cid);
+        // would evict everything else; and still be too big
+        if skcd_buf.len() > self.max_total_bytes || self.max_entries == 0 {
+            return;
+        }
+        self.entries.push((skcd_cid, skcd_buf));
+
+        while self.entries.len() > self.max_entries || self.total_bytes() > self.max_total_bytes {
+            self.entries.remove(0);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(_, skcd_buf)| skcd_buf.len())
+            .sum()
+    }
+}
+
+static SKCD_CACHE: spin::Mutex<SkcdCache> =
+    spin::Mutex::new(SkcdCache::new(MAX_ENTRIES, MAX_TOTAL_BYTES));
+
+/// NOTE: `f` MUST NOT block; the other threads are spinning
+fn with_cache<R>(f: impl FnOnce(&mut SkcdCache) -> R) -> R {
+    f(&mut SKCD_CACHE.lock())
+}
+
+/// return: the cached .skcd, or the result of `fetch` which is then cached
+///
+/// NOTE: the lock is NOT held during `fetch`
+pub(crate) fn get_or_fetch<E>(
+    skcd_cid: &[u8],
+    fetch: impl FnOnce() -> Result<Vec<u8>, E>,
+) -> Result<Vec<u8>, E> {
+    if let Some(skcd_buf) = with_cache(|cache| cache.get(skcd_cid)) {
+        log::debug!("[ocw-garble] skcd cache hit");
+        return Ok(skcd_buf);
+    }
+
+    let skcd_buf = fetch()?;
+
+    with_cache(|cache| cache.insert(skcd_cid.to_vec(), skcd_buf.clone()));
+
+    Ok(skcd_buf)
+}
+
+pub(crate) fn invalidate() {
+    with_cache(|cache| cache.clear());
+}
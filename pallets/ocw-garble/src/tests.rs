@@ -1,6 +1,8 @@
 /// https://github.com/paritytech/substrate/blob/monthly-2022-10/frame/examples/offchain-worker/src/tests.rs
 use crate::mock::*;
 use frame_support::pallet_prelude::DispatchError;
+use frame_support::pallet_prelude::Hooks;
+use circuits_storage_common::PinpadLayout;
 use frame_support::{assert_err, assert_ok};
 use sp_runtime::ModuleError;
 
@@ -8,6 +10,15 @@ fn test_pub() -> sp_core::sr25519::Public {
     sp_core::sr25519::Public::from_raw([1u8; 32])
 }
 
+fn test_tx_intent() -> pallet_tx_validation::TxIntent<sp_core::sr25519::Public> {
+    pallet_tx_validation::TxIntent {
+        recipient: sp_core::sr25519::Public::from_raw([2u8; 32]),
+        amount: 42,
+        asset_id: None,
+        memo: Default::default(),
+    }
+}
+
 async fn test_garble_and_strip_display_circuits_package_signed(
     mock_type: MockType,
 ) -> Result<(), sp_runtime::DispatchError> {
@@ -18,7 +29,7 @@ async fn test_garble_and_strip_display_circuits_package_signed(
         // Dispatch a signed extrinsic.
         OcwGarble::garble_and_strip_display_circuits_package_signed(
             RuntimeOrigin::signed(account_id),
-            vec![42],
+            test_tx_intent(),
         )
         // TODO how to CHECK "append_or_replace_verilog_hash"
         // System::assert_last_event(crate::Event::NewMobileRegistered { account_id: 1 }.into());
@@ -81,6 +92,521 @@ async fn test_rpc_ocw_circuits_ipfs_down_does_not_panic() {
     );
 }
 
+/// If the first IPFS endpoint is down, the next one MUST be used; and the failure recorded
+#[tokio::test]
+#[serial_test::serial]
+async fn test_ipfs_failover() {
+    use circuits_storage_common::ipfs_failover::{FailoverEnv, InMemoryFailoverEnv};
+
+    let (mut t, foreign_node) = new_test_ext(MockType::IpfsFailover).await;
+    t.execute_with(|| {
+        assert_ok!(
+            OcwGarble::garble_and_strip_display_circuits_package_signed(
+                RuntimeOrigin::signed(test_pub()),
+                test_tx_intent(),
+            )
+        );
+
+        assert!(InMemoryFailoverEnv::get_health(MOCK_IPFS_DOWN_URL).consecutive_failures > 0);
+        assert_eq!(
+            InMemoryFailoverEnv::get_health(&format!("http://127.0.0.1:{}", foreign_node.api_port))
+                .consecutive_failures,
+            0
+        );
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+/// The IPFS calls are made from the dispatchables: the health tracking MUST NOT need the offchain API
+#[test]
+fn test_ipfs_failover_env_without_offchain_ext() {
+    use circuits_storage_common::ipfs_failover::{FailoverEnv, InMemoryFailoverEnv};
+
+    InMemoryFailoverEnv::reset();
+    sp_io::TestExternalities::default().execute_with(|| {
+        let mut health = InMemoryFailoverEnv::get_health("http://a");
+        health.consecutive_failures = 2;
+        InMemoryFailoverEnv::set_health("http://a", health);
+
+        assert_eq!(
+            InMemoryFailoverEnv::get_health("http://a").consecutive_failures,
+            2
+        );
+        assert_eq!(
+            InMemoryFailoverEnv::get_health("http://b").consecutive_failures,
+            0
+        );
+    });
+    InMemoryFailoverEnv::reset();
+}
+
+thread_local! {
+    static TEST_FAILOVER_NOW_MS: core::cell::Cell<u64> = core::cell::Cell::new(0);
+}
+
+/// `FailoverEnv` with a manual clock
+struct TestFailoverEnv;
+
+impl circuits_storage_common::ipfs_failover::FailoverEnv for TestFailoverEnv {
+    fn now_ms() -> u64 {
+        TEST_FAILOVER_NOW_MS.with(|now_ms| now_ms.get())
+    }
+
+    fn get_health(_endpoint: &str) -> circuits_storage_common::ipfs_failover::EndpointHealth {
+        Default::default()
+    }
+
+    fn set_health(
+        _endpoint: &str,
+        _health: circuits_storage_common::ipfs_failover::EndpointHealth,
+    ) {
+    }
+}
+
+/// An answer after the deadline MUST only be used if no other endpoint answers in time
+#[test]
+fn test_ipfs_failover_request_deadline() {
+    use circuits_storage_common::ipfs_failover::{with_failover, FailoverConfig};
+
+    let config = FailoverConfig {
+        request_timeout_ms: 1_000,
+        ..Default::default()
+    };
+    // "slow" takes 5s, "fast" 10ms
+    let call = |endpoint: &str, timeout: core::time::Duration| -> Result<String, ()> {
+        assert_eq!(timeout, core::time::Duration::from_millis(1_000));
+        let duration_ms = if endpoint == "http://slow" { 5_000 } else { 10 };
+        TEST_FAILOVER_NOW_MS.with(|now_ms| now_ms.set(now_ms.get() + duration_ms));
+        Ok(endpoint.to_string())
+    };
+
+    assert_eq!(
+        with_failover::<TestFailoverEnv, _, _, _>(
+            &["http://slow".to_string(), "http://fast".to_string()],
+            &config,
+            call,
+        ),
+        Ok("http://fast".to_string())
+    );
+    assert_eq!(
+        with_failover::<TestFailoverEnv, _, _, _>(&["http://slow".to_string()], &config, call),
+        Ok("http://slow".to_string())
+    );
+    assert_eq!(
+        with_failover::<TestFailoverEnv, _, _, _>(
+            &["http://slow".to_string(), "http://error".to_string()],
+            &config,
+            |endpoint, timeout| match endpoint {
+                "http://error" => Err(()),
+                _ => call(endpoint, timeout),
+            },
+        ),
+        Ok("http://slow".to_string())
+    );
+}
+
+/// Each circuit MUST have its own pinpad(ie its own secret permutation); garbled on demand
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_pinpad_not_reused() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        let account_id = test_pub();
+        for _ in 0..2 {
+            assert_ok!(OcwGarble::garble_and_strip_display_circuits_package_signed(
+                RuntimeOrigin::signed(account_id),
+                test_tx_intent(),
+            ));
+        }
+
+        let pending_circuits = OcwGarble::get_pending_circuits_for_account(account_id);
+        assert_eq!(pending_circuits.len(), 2);
+        assert_ne!(
+            pending_circuits[0].pinpad_pgarbled_cid,
+            pending_circuits[1].pinpad_pgarbled_cid
+        );
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+/// v3 -> v4: the digits were public, so the entries MUST NOT be used anymore
+#[test]
+fn test_migration_v4_discards_pinpad_pool() {
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    new_test_ext_no_ipfs().execute_with(|| {
+        StorageVersion::new(3).put::<OcwGarble>();
+        crate::migrations::v4::OldPinpadPool::<Test>::put(
+            sp_core::bounded::BoundedVec::try_from(vec![
+                crate::migrations::v4::OldPinpadPoolEntry {
+                    pinpad_skcd_cid: to_cid(b"PINPAD_SKCD_CID"),
+                    pinpad_pgarbled_cid: to_cid(b"PINPAD_CID"),
+                    pinpad_digits: vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9].try_into().unwrap(),
+                },
+            ])
+            .unwrap(),
+        );
+
+        crate::migrations::v4::migrate::<Test>();
+
+        assert_eq!(OcwGarble::on_chain_storage_version(), 4);
+        assert!(!crate::migrations::v4::OldPinpadPool::<Test>::exists());
+        assert_eq!(
+            OcwGarble::get_unpin_queue().into_inner(),
+            vec![to_cid(b"PINPAD_CID")]
+        );
+    });
+}
+
+/// v1 -> v4: the pending circuits MUST still be there(with a decimal pinpad on one row),
+/// and the removed `VerifiedDisplaySkcdPackageCache` MUST be gone
+#[test]
+fn test_migrations_from_v1_translate_pending_circuits() {
+    use crate::migrations::v2::{
+        OldAccountToPendingCircuitsMap, OldDisplayStrippedCircuitsPackage,
+    };
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        StorageVersion::new(1).put::<OcwGarble>();
+        OldAccountToPendingCircuitsMap::<Test>::insert(
+            account_id,
+            sp_core::bounded::BoundedVec::try_from(vec![OldDisplayStrippedCircuitsPackage {
+                message_pgarbled_cid: to_cid(b"MESSAGE_CID"),
+                pinpad_pgarbled_cid: to_cid(b"PINPAD_CID"),
+                message_nb_digits: 2,
+            }])
+            .unwrap(),
+        );
+        crate::migrations::v4::OldVerifiedDisplaySkcdPackageCache::<Test>::put(vec![1, 2, 3]);
+
+        <OcwGarble as Hooks<u64>>::on_runtime_upgrade();
+
+        assert_eq!(OcwGarble::on_chain_storage_version(), 4);
+        assert_eq!(
+            OcwGarble::get_pending_circuits_for_account(account_id).into_inner(),
+            vec![crate::DisplayStrippedCircuitsPackage {
+                message_pgarbled_cid: to_cid(b"MESSAGE_CID"),
+                pinpad_pgarbled_cid: to_cid(b"PINPAD_CID"),
+                message_nb_digits: 2,
+                pinpad_nb_digits: 10,
+                pinpad_layout: PinpadLayout::row(10),
+            }]
+        );
+        assert!(!crate::migrations::v4::OldVerifiedDisplaySkcdPackageCache::<Test>::exists());
+    });
+}
+
+/// v2 -> v4: a v2 pinpad could have another alphabet; still one row
+#[test]
+fn test_migrations_from_v2_keep_pinpad_nb_digits() {
+    use crate::migrations::v3::{
+        OldAccountToPendingCircuitsMap, OldDisplayStrippedCircuitsPackage,
+    };
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        StorageVersion::new(2).put::<OcwGarble>();
+        OldAccountToPendingCircuitsMap::<Test>::insert(
+            account_id,
+            sp_core::bounded::BoundedVec::try_from(vec![OldDisplayStrippedCircuitsPackage {
+                message_pgarbled_cid: to_cid(b"MESSAGE_CID"),
+                pinpad_pgarbled_cid: to_cid(b"PINPAD_CID"),
+                message_nb_digits: 4,
+                pinpad_nb_digits: 16,
+            }])
+            .unwrap(),
+        );
+
+        <OcwGarble as Hooks<u64>>::on_runtime_upgrade();
+
+        assert_eq!(OcwGarble::on_chain_storage_version(), 4);
+        let pending_circuits = OcwGarble::get_pending_circuits_for_account(account_id);
+        assert_eq!(pending_circuits.len(), 1);
+        assert_eq!(pending_circuits[0].pinpad_nb_digits, 16);
+        assert_eq!(pending_circuits[0].pinpad_layout, PinpadLayout::row(16));
+    });
+}
+
+fn get_last_batch_event() -> (
+    Vec<(u64, crate::DisplayStrippedCircuitsPackage)>,
+    Vec<(u32, sp_runtime::DispatchError)>,
+) {
+    System::events()
+        .into_iter()
+        .rev()
+        .find_map(|record| match record.event {
+            RuntimeEvent::OcwGarble(crate::Event::NewGarbleAndStrippedBatch {
+                packages,
+                failed,
+                ..
+            }) => Some((packages, failed)),
+            _ => None,
+        })
+        .expect("no NewGarbleAndStrippedBatch event")
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_batch_ok() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        System::set_block_number(1);
+        let account_id = test_pub();
+
+        assert_ok!(OcwGarble::garble_and_strip_batch_signed(
+            RuntimeOrigin::signed(account_id),
+            vec![test_tx_intent(), test_tx_intent()],
+        ));
+
+        let (packages, failed) = get_last_batch_event();
+        assert_eq!(
+            packages
+                .iter()
+                .map(|(request_id, _)| *request_id)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert!(failed.is_empty());
+        assert_eq!(
+            OcwGarble::get_pending_circuits_for_account(account_id).len(),
+            2
+        );
+        // ONE event for the whole batch
+        assert!(!System::events().into_iter().any(|record| matches!(
+            record.event,
+            RuntimeEvent::OcwGarble(crate::Event::NewGarbleAndStrippedIpfsCid { .. })
+        )));
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+/// A failed item MUST be rolled back and reported; the others MUST still be pending
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_batch_partial_failure() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        System::set_block_number(1);
+        let account_id = test_pub();
+
+        // leave room for only ONE more expiring circuit, spill included
+        fill_expiring_circuits(account_id, 1);
+
+        assert_ok!(OcwGarble::garble_and_strip_batch_signed(
+            RuntimeOrigin::signed(account_id),
+            vec![test_tx_intent(), test_tx_intent()],
+        ));
+
+        let (packages, failed) = get_last_batch_event();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(
+            failed,
+            vec![(1, crate::Error::<Test>::TooManyExpiringCircuits.into())]
+        );
+        assert_eq!(
+            OcwGarble::get_pending_circuits_for_account(account_id),
+            packages
+                .into_iter()
+                .map(|(_, package)| package)
+                .collect::<Vec<_>>()
+        );
+        // the message and the pinpad of the failed one were uploaded: they MUST be unpinned
+        let unpin_queue = OcwGarble::get_unpin_queue();
+        assert_eq!(unpin_queue.len(), 2);
+        assert!(unpin_queue
+            .iter()
+            .all(|pgarbled_cid| !crate::PinnedCidsMap::<Test>::contains_key(pgarbled_cid)));
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+/// Each `TxIntent` is garbled: the weight MUST grow with the batch
+#[test]
+fn test_garble_and_strip_batch_weight() {
+    use frame_support::dispatch::GetDispatchInfo;
+
+    let batch_weight = |nb_tx_intents: usize| {
+        crate::Call::<Test>::garble_and_strip_batch_signed {
+            tx_intents: (0..nb_tx_intents).map(|_| test_tx_intent()).collect(),
+        }
+        .get_dispatch_info()
+        .weight
+        .ref_time()
+    };
+
+    assert_eq!(batch_weight(3), 3 * batch_weight(1));
+    assert_eq!(batch_weight(0), batch_weight(1));
+}
+
+/// The longest `TxIntent`: a u128 amount, an asset id, and a full memo
+/// NOTE: it does NOT fit on the mock package(cf `MessageCapacity::default` ie a package from before v4)
+fn test_tx_intent_too_long() -> pallet_tx_validation::TxIntent<sp_core::sr25519::Public> {
+    pallet_tx_validation::TxIntent {
+        amount: u128::MAX,
+        asset_id: Some(u32::MAX),
+        memo: frame_support::BoundedVec::truncate_from(vec![b'x'; 32]),
+        ..test_tx_intent()
+    }
+}
+
+/// The message circuits of lib_circuits_rs MUST show any `TxIntent`; else some transfers could never be confirmed
+#[test]
+fn test_lib_circuits_rs_message_capacity_fits_tx_intent() {
+    let tx_msg = test_tx_intent_too_long().render_display_text();
+    assert_eq!(tx_msg.chars().count(), 109);
+
+    for message_nb_digits in [2, 6, 10] {
+        let (width, _height, _digits_bboxes) =
+            pallet_ocw_circuits::display_layout(true, message_nb_digits);
+        let message_capacity = pallet_ocw_circuits::lib_circuits_rs_message_capacity(width);
+
+        assert_eq!(message_capacity.check(&tx_msg), Ok(()));
+        assert_eq!(
+            message_capacity.check(&test_tx_intent().render_display_text()),
+            Ok(())
+        );
+    }
+}
+
+/// The `tx_msg` is checked BEFORE garbling; else it would be truncated on screen
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_message_too_long() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        let account_id = test_pub();
+
+        assert_err!(
+            OcwGarble::garble_and_strip_display_circuits_package_signed(
+                RuntimeOrigin::signed(account_id),
+                test_tx_intent_too_long(),
+            ),
+            DispatchError::Module(ModuleError {
+                index: 3,
+                error: [20, 0, 0, 0],
+                message: Some("MessageTooLong")
+            }),
+        );
+        assert!(OcwGarble::get_pending_circuits_for_account(account_id).is_empty());
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+/// The message circuit only draws printable ASCII; cf `MessageCapacity::default`
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_unsupported_character() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        let account_id = test_pub();
+
+        assert_err!(
+            OcwGarble::garble_and_strip_display_circuits_package_signed(
+                RuntimeOrigin::signed(account_id),
+                pallet_tx_validation::TxIntent {
+                    memo: frame_support::BoundedVec::truncate_from("café".as_bytes().to_vec()),
+                    ..test_tx_intent()
+                },
+            ),
+            DispatchError::Module(ModuleError {
+                index: 3,
+                error: [21, 0, 0, 0],
+                message: Some("UnsupportedCharacter")
+            }),
+        );
+        assert!(OcwGarble::get_pending_circuits_for_account(account_id).is_empty());
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+/// In a batch a `tx_msg` too long is a partial failure, like the others
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_batch_message_too_long() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        System::set_block_number(1);
+        let account_id = test_pub();
+
+        assert_ok!(OcwGarble::garble_and_strip_batch_signed(
+            RuntimeOrigin::signed(account_id),
+            vec![test_tx_intent_too_long(), test_tx_intent()],
+        ));
+
+        let (packages, failed) = get_last_batch_event();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(
+            failed,
+            vec![(0, crate::Error::<Test>::MessageTooLong.into())]
+        );
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+#[test]
+fn test_garble_and_strip_batch_invalid_size() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+
+        assert_err!(
+            OcwGarble::garble_and_strip_batch_signed(RuntimeOrigin::signed(account_id), vec![]),
+            crate::Error::<Test>::InvalidBatchSize
+        );
+        assert_err!(
+            OcwGarble::garble_and_strip_batch_signed(
+                RuntimeOrigin::signed(account_id),
+                vec![test_tx_intent(); 5],
+            ),
+            crate::Error::<Test>::InvalidBatchSize
+        );
+        // and the index MUST be stable
+        assert_err!(
+            OcwGarble::garble_and_strip_batch_signed(RuntimeOrigin::signed(account_id), vec![]),
+            DispatchError::Module(ModuleError {
+                index: 3,
+                error: [18, 0, 0, 0],
+                message: Some("InvalidBatchSize")
+            })
+        );
+    });
+}
+
+/// The WHOLE batch MUST fit in `AccountToPendingCircuitsMap`
+#[test]
+fn test_garble_and_strip_batch_too_many_pending_circuits() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        for i in 0..15 {
+            add_pending_circuit(account_id, format!("MESSAGE_CID_{i}").as_bytes());
+        }
+
+        assert_err!(
+            OcwGarble::garble_and_strip_batch_signed(
+                RuntimeOrigin::signed(account_id),
+                vec![test_tx_intent(), test_tx_intent()],
+            ),
+            crate::Error::<Test>::TooManyPendingCircuits
+        );
+    });
+}
+
 /// If the .skcd are not valid; it MUST NOT panic/crash/etc
 #[tokio::test]
 #[serial_test::serial]
@@ -107,6 +633,47 @@ async fn test_rpc_fallback() {
     assert_ok!(res);
 }
 
+/// The RPC answer MUST match the trusted header; else it is rejected
+#[tokio::test]
+#[serial_test::serial]
+async fn test_rpc_fallback_invalid_proof() {
+    let res =
+        test_garble_and_strip_display_circuits_package_signed(MockType::FallbackStorageInvalidProof)
+            .await;
+    assert_err!(
+        res,
+        DispatchError::Module(ModuleError {
+            index: 3,
+            error: [15, 0, 0, 0],
+            message: Some("StorageProofError")
+        }),
+    );
+}
+
+/// The verified package is only valid for its block; and shared by every thread(eg in the enclave)
+#[test]
+#[serial_test::serial]
+fn test_verified_package_cache_keyed_by_block_hash() {
+    let package = circuits_storage_common::DisplaySkcdPackage::default();
+    crate::verified_package_cache::invalidate();
+
+    let package_clone = package.clone();
+    std::thread::spawn(move || {
+        crate::verified_package_cache::put(sp_core::H256::repeat_byte(1), package_clone);
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(
+        crate::verified_package_cache::get(&sp_core::H256::repeat_byte(1)),
+        Some(package)
+    );
+    assert!(crate::verified_package_cache::get(&sp_core::H256::repeat_byte(2)).is_none());
+
+    crate::verified_package_cache::invalidate();
+    assert!(crate::verified_package_cache::get(&sp_core::H256::repeat_byte(1)).is_none());
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_garble_and_strip_display_circuits_package_signed_ok() {
@@ -115,3 +682,964 @@ async fn test_garble_and_strip_display_circuits_package_signed_ok() {
             .await;
     assert_ok!(res);
 }
+
+/// End-to-end: play the mobile role(cf reference-evaluator) then confirm with the digits shown
+/// across ocw-garble -> IPFS -> tx-validation
+/// With the real garbler and evaluator(NOT `MockGarbler`); NOTE: slow
+#[tokio::test]
+#[serial_test::serial]
+async fn test_e2e_read_otp_and_check_input_tx_pass() {
+    let (mut t, foreign_node) = new_test_ext(MockType::RpcOcwCircuitsStorageValid).await;
+    t.execute_with(|| {
+        UseLibGarbleRs::set(true);
+        // else no events
+        System::set_block_number(1);
+        let account_id = test_pub();
+
+        assert_ok!(
+            OcwGarble::garble_and_strip_display_circuits_package_signed(
+                RuntimeOrigin::signed(account_id),
+                test_tx_intent(),
+            )
+        );
+        let package = OcwGarble::get_pending_circuits_for_account(account_id)[0].clone();
+
+        let store =
+            reference_evaluator::IpfsStore::new(&std::env::var("IPFS_ROOT_URL").unwrap()).unwrap();
+        let evaluated = reference_evaluator::evaluate_package::<
+            reference_evaluator::LibGarbleRsEvaluator,
+            _,
+        >(&store, &package)
+        .unwrap();
+        let positions = evaluated
+            .pinpad_positions(&evaluated.message_digits)
+            .unwrap();
+
+        assert_ok!(TxValidation::check_input(
+            RuntimeOrigin::signed(account_id),
+            package.message_pgarbled_cid.to_vec(),
+            positions,
+            pallet_tx_validation::InputEncoding::RawIndices,
+        ));
+        assert!(System::events().iter().any(|record| record.event
+            == RuntimeEvent::TxValidation(pallet_tx_validation::Event::TxPass { account_id })));
+
+        UseLibGarbleRs::set(false);
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+}
+
+async fn test_garble_standard_signed(
+    mock_type: MockType,
+) -> Result<(), sp_runtime::DispatchError> {
+    let (mut t, foreign_node) = new_test_ext(mock_type).await;
+    let res = t.execute_with(|| {
+        let account_id = test_pub();
+
+        // NOTE: any .skcd works here; so we reuse the "message" one set by "new_test_ext"
+        let skcd_cid = pallet_ocw_circuits::get_display_circuits_package::<Test>()
+            .unwrap()
+            .message_skcd_cid
+            .to_vec();
+
+        // Dispatch a signed extrinsic.
+        OcwGarble::garble_standard_signed(RuntimeOrigin::signed(account_id), skcd_cid, vec![])
+    });
+
+    // Needed to keep the server alive?
+    assert!(foreign_node.daemon.id() > 0);
+
+    res
+}
+
+/// [generic] If IPFS is down; it MUST NOT panic/crash/etc
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_standard_signed_ipfs_down_does_not_panic() {
+    let res = test_garble_standard_signed(MockType::IpfsDown).await;
+    assert_err!(
+        res,
+        DispatchError::Module(ModuleError {
+            index: 3,
+            error: [9, 0, 0, 0],
+            message: Some("IpfsCallError")
+        }),
+    );
+}
+
+/// [generic] If the .skcd is not valid; it MUST NOT panic/crash/etc
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_standard_signed_invalid_skcd_does_not_panic() {
+    let res = test_garble_standard_signed(MockType::InvalidSkcd).await;
+    assert_err!(
+        res,
+        DispatchError::Module(ModuleError {
+            index: 3,
+            error: [10, 0, 0, 0],
+            message: Some("GarblerError")
+        }),
+    );
+}
+
+const DECIMAL_ALPHABET: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+fn generate_random_digits_fresh_ext(nb_calls: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| {
+        (0..nb_calls)
+            .map(|_| crate::Pallet::<Test>::generate_random_digits(&DECIMAL_ALPHABET, 2))
+            .collect()
+    })
+}
+
+/// With `DeterministicRandomness` the digits MUST be reproducible(ie same seed + same nonce)
+/// but successive calls(ie different nonces) MUST NOT give the same digits
+#[test]
+fn test_generate_random_digits_deterministic() {
+    let first_run = generate_random_digits_fresh_ext(2);
+    let second_run = generate_random_digits_fresh_ext(2);
+
+    assert_eq!(first_run, second_run);
+    assert_ne!(first_run[0], first_run[1]);
+}
+
+/// The pinpad MUST be a permutation of the alphabet and the message digits MUST be in [0-9]
+#[test]
+fn test_generate_random_digits_valid() {
+    for (message_digits, pinpad_digits) in generate_random_digits_fresh_ext(8) {
+        assert_eq!(message_digits.len(), 2);
+        assert!(message_digits.iter().all(|digit| *digit < 10));
+
+        let mut sorted_pinpad_digits = pinpad_digits.clone();
+        sorted_pinpad_digits.sort();
+        assert_eq!(sorted_pinpad_digits, (0..10).collect::<Vec<u8>>());
+    }
+}
+
+/// eg 6-digit hexadecimal codes with a 16 cells pinpad
+#[test]
+fn test_generate_random_digits_hexadecimal() {
+    let profile = circuits_storage_common::DisplayProfile::hexadecimal(6);
+    new_test_ext_no_ipfs().execute_with(|| {
+        let (message_digits, pinpad_digits) =
+            crate::Pallet::<Test>::generate_random_digits(&profile.alphabet, 6);
+
+        assert_eq!(message_digits.len(), 6);
+        assert!(message_digits
+            .iter()
+            .all(|digit| profile.alphabet.contains(digit)));
+
+        let mut sorted_pinpad_digits = pinpad_digits.clone();
+        sorted_pinpad_digits.sort();
+        assert_eq!(sorted_pinpad_digits, profile.alphabet.to_vec());
+    });
+}
+
+/// Add a pending circuit WITHOUT garbling(ie no IPFS needed)
+fn add_pending_circuit(account_id: sp_core::sr25519::Public, message_pgarbled_cid: &[u8]) {
+    assert_ok!(crate::Pallet::<Test>::callback_new_garbled_and_strip_signed(
+        account_id,
+        crate::Pallet::<Test>::next_request_id(),
+        message_pgarbled_cid.to_vec(),
+        vec![3, 4],
+        [b"PINPAD_", message_pgarbled_cid].concat(),
+        vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9],
+        PinpadLayout::row(10),
+        test_tx_intent(),
+    ));
+}
+
+fn new_test_ext_no_ipfs() -> sp_io::TestExternalities {
+    let mut t = sp_io::TestExternalities::default();
+    t.execute_with(|| System::set_block_number(1));
+    t
+}
+
+fn assert_pending_circuit_removed(
+    account_id: sp_core::sr25519::Public,
+    message_pgarbled_cid: &[u8],
+    reason: crate::PendingCircuitRemovalReason,
+) {
+    System::assert_last_event(
+        crate::Event::PendingCircuitRemoved {
+            account_id,
+            message_pgarbled_cid: message_pgarbled_cid.to_vec(),
+            reason,
+        }
+        .into(),
+    );
+    assert!(OcwGarble::get_pending_circuits_for_account(account_id).is_empty());
+}
+
+/// The event MUST contain everything the wallet app needs; ie no need to query the storage
+#[test]
+fn test_new_garble_and_stripped_event_is_complete() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+
+        for (request_id, message_pgarbled_cid) in [&b"MESSAGE_CID_0"[..], b"MESSAGE_CID_1"]
+            .into_iter()
+            .enumerate()
+        {
+            add_pending_circuit(account_id, message_pgarbled_cid);
+
+            System::assert_last_event(
+                crate::Event::NewGarbleAndStrippedIpfsCid {
+                    account_id,
+                    package: crate::DisplayStrippedCircuitsPackage {
+                        message_pgarbled_cid: to_cid(message_pgarbled_cid),
+                        pinpad_pgarbled_cid: to_cid(&[b"PINPAD_", message_pgarbled_cid].concat()),
+                        message_nb_digits: 2,
+                        pinpad_nb_digits: 10,
+                        pinpad_layout: PinpadLayout::row(10),
+                    },
+                    created_at: 1,
+                    request_id: request_id as u64,
+                }
+                .into(),
+            );
+        }
+    });
+}
+
+#[test]
+fn test_cancel_pending_circuit_ok() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+        assert_eq!(OcwGarble::get_pending_circuits_for_account(account_id).len(), 1);
+
+        assert_ok!(OcwGarble::cancel_pending_circuit(
+            RuntimeOrigin::signed(account_id),
+            b"MESSAGE_CID".to_vec()
+        ));
+
+        assert_pending_circuit_removed(
+            account_id,
+            b"MESSAGE_CID",
+            crate::PendingCircuitRemovalReason::Cancelled,
+        );
+    });
+}
+
+#[test]
+fn test_cancel_pending_circuit_not_found() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+
+        assert_err!(
+            OcwGarble::cancel_pending_circuit(
+                RuntimeOrigin::signed(account_id),
+                b"OTHER_CID".to_vec()
+            ),
+            crate::Error::<Test>::PendingCircuitNotFound
+        );
+    });
+}
+
+/// MUST NOT panic when the account already has the max number of pending circuits
+#[test]
+fn test_too_many_pending_circuits() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        for i in 0..16 {
+            add_pending_circuit(account_id, format!("MESSAGE_CID_{i}").as_bytes());
+        }
+
+        assert_err!(
+            crate::Pallet::<Test>::callback_new_garbled_and_strip_signed(
+                account_id,
+                0,
+                b"MESSAGE_CID_16".to_vec(),
+                vec![3, 4],
+                b"PINPAD_CID".to_vec(),
+                vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9],
+                PinpadLayout::row(10),
+                test_tx_intent(),
+            ),
+            crate::Error::<Test>::TooManyPendingCircuits
+        );
+    });
+}
+
+/// Fill `ExpiringCircuitsMap` for every block a circuit added now could expire at;
+/// except `nb_free_slots` in the last one
+fn fill_expiring_circuits(account_id: sp_core::sr25519::Public, nb_free_slots: u32) {
+    let expiry_block_number = System::block_number() + PENDING_CIRCUIT_LIFETIME;
+    let last_block_number = expiry_block_number + u64::from(crate::MAX_EXPIRY_SPILL_BLOCKS);
+    for block_number in expiry_block_number..=last_block_number {
+        crate::ExpiringCircuitsMap::<Test>::mutate(block_number, |expiring_circuits| {
+            let nb_free_slots = if block_number == last_block_number {
+                nb_free_slots
+            } else {
+                0
+            };
+            for i in 0..crate::MAX_NUMBER_EXPIRING_CIRCUITS_PER_BLOCK - nb_free_slots {
+                expiring_circuits
+                    .try_push((
+                        account_id,
+                        to_cid(format!("OTHER_CID_{block_number}_{i}").as_bytes()),
+                    ))
+                    .unwrap();
+            }
+        });
+    }
+}
+
+/// A full expiry block MUST NOT be an error; the circuit expires a bit later instead
+#[test]
+fn test_pending_circuit_expiry_spills_into_next_block() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        let other_account_id = sp_core::sr25519::Public::from_raw([3u8; 32]);
+        let expiry_block_number = System::block_number() + PENDING_CIRCUIT_LIFETIME;
+        crate::ExpiringCircuitsMap::<Test>::mutate(expiry_block_number, |expiring_circuits| {
+            for i in 0..crate::MAX_NUMBER_EXPIRING_CIRCUITS_PER_BLOCK {
+                expiring_circuits
+                    .try_push((other_account_id, to_cid(format!("OTHER_CID_{i}").as_bytes())))
+                    .unwrap();
+            }
+        });
+
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+
+        OcwGarble::on_initialize(expiry_block_number);
+        assert_eq!(OcwGarble::get_pending_circuits_for_account(account_id).len(), 1);
+
+        OcwGarble::on_initialize(expiry_block_number + 1);
+        assert_pending_circuit_removed(
+            account_id,
+            b"MESSAGE_CID",
+            crate::PendingCircuitRemovalReason::Expired,
+        );
+    });
+}
+
+/// NOT `TooManyPendingCircuits`: the account itself has no pending circuit
+#[test]
+fn test_too_many_expiring_circuits() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        fill_expiring_circuits(sp_core::sr25519::Public::from_raw([3u8; 32]), 0);
+
+        assert_err!(
+            crate::Pallet::<Test>::callback_new_garbled_and_strip_signed(
+                account_id,
+                0,
+                b"MESSAGE_CID".to_vec(),
+                vec![3, 4],
+                b"PINPAD_CID".to_vec(),
+                vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9],
+                PinpadLayout::row(10),
+                test_tx_intent(),
+            ),
+            crate::Error::<Test>::TooManyExpiringCircuits
+        );
+    });
+}
+
+/// "garble then cancel" in a loop MUST NOT fill `ExpiringCircuitsMap` for everyone else
+#[test]
+fn test_too_many_expiring_circuits_for_account() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        for i in 0..crate::MAX_NUMBER_EXPIRING_CIRCUITS_PER_ACCOUNT {
+            let message_pgarbled_cid = format!("MESSAGE_CID_{i}");
+            add_pending_circuit(account_id, message_pgarbled_cid.as_bytes());
+            assert_ok!(OcwGarble::cancel_pending_circuit(
+                RuntimeOrigin::signed(account_id),
+                message_pgarbled_cid.into_bytes()
+            ));
+        }
+
+        assert_err!(
+            crate::Pallet::<Test>::callback_new_garbled_and_strip_signed(
+                account_id,
+                0,
+                b"MESSAGE_CID".to_vec(),
+                vec![3, 4],
+                b"PINPAD_CID".to_vec(),
+                vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9],
+                PinpadLayout::row(10),
+                test_tx_intent(),
+            ),
+            crate::Error::<Test>::TooManyExpiringCircuitsForAccount
+        );
+        // the other accounts are NOT affected
+        add_pending_circuit(sp_core::sr25519::Public::from_raw([3u8; 32]), b"OTHER_CID");
+
+        // and the slots are freed at expiry
+        OcwGarble::on_initialize(1 + PENDING_CIRCUIT_LIFETIME);
+        assert_eq!(
+            crate::AccountToNbExpiringCircuitsMap::<Test>::get(account_id),
+            0
+        );
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+    });
+}
+
+#[test]
+fn test_pending_circuit_expires() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+
+        OcwGarble::on_initialize(PENDING_CIRCUIT_LIFETIME);
+        assert_eq!(OcwGarble::get_pending_circuits_for_account(account_id).len(), 1);
+
+        OcwGarble::on_initialize(1 + PENDING_CIRCUIT_LIFETIME);
+        assert_pending_circuit_removed(
+            account_id,
+            b"MESSAGE_CID",
+            crate::PendingCircuitRemovalReason::Expired,
+        );
+    });
+}
+
+/// Calling check_input(TxPass or TxFail) MUST remove the pending circuit
+#[test]
+fn test_pending_circuit_removed_when_validated() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+
+        assert_ok!(TxValidation::check_input(
+            RuntimeOrigin::signed(account_id),
+            b"MESSAGE_CID".to_vec(),
+            // the digits are random; but any valid input works: TxPass or TxFail
+            vec![0, 0],
+            pallet_tx_validation::InputEncoding::RawIndices,
+        ));
+
+        assert_pending_circuit_removed(
+            account_id,
+            b"MESSAGE_CID",
+            crate::PendingCircuitRemovalReason::Validated,
+        );
+    });
+}
+
+const TEST_ENDPOINT_ENV_VAR: &str = "OCW_GARBLE_TEST_ENDPOINT";
+
+fn new_test_ext_offchain() -> sp_io::TestExternalities {
+    let mut t = new_test_ext_no_ipfs();
+    let (offchain, _state) = sp_core::offchain::testing::TestOffchainExt::new();
+    t.register_extension(sp_core::offchain::OffchainWorkerExt::new(offchain));
+    t
+}
+
+fn get_test_endpoint(
+    from_local_storage: Option<&str>,
+    config_value: Option<&str>,
+) -> Result<String, crate::Error<Test>> {
+    crate::get_endpoint::<Test>(
+        from_local_storage.map(|value| value.as_bytes().to_vec()),
+        config_value.map(|value| value.as_bytes().to_vec()),
+        TEST_ENDPOINT_ENV_VAR,
+    )
+}
+
+#[test]
+#[serial_test::serial]
+fn test_endpoint_priority_local_storage_then_config_then_env() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        std::env::set_var(TEST_ENDPOINT_ENV_VAR, "http://env");
+        assert_eq!(get_test_endpoint(None, None).unwrap(), "http://env");
+        assert_eq!(
+            get_test_endpoint(None, Some("http://config")).unwrap(),
+            "http://config"
+        );
+        assert_eq!(
+            get_test_endpoint(Some("http://local-storage"), Some("http://config")).unwrap(),
+            "http://local-storage"
+        );
+
+        std::env::remove_var(TEST_ENDPOINT_ENV_VAR);
+    });
+}
+
+#[test]
+#[serial_test::serial]
+fn test_endpoint_not_configured() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        std::env::remove_var(TEST_ENDPOINT_ENV_VAR);
+        assert!(matches!(
+            get_test_endpoint(None, None),
+            Err(crate::Error::<Test>::EndpointNotConfigured)
+        ));
+        // empty values are NOT valid endpoints
+        assert!(matches!(
+            get_test_endpoint(Some(""), Some("")),
+            Err(crate::Error::<Test>::EndpointNotConfigured)
+        ));
+    });
+}
+
+#[test]
+fn test_offchain_local_storage_get() {
+    new_test_ext_offchain().execute_with(|| {
+        assert_eq!(
+            crate::offchain_local_storage_get(crate::IPFS_ROOT_URL_LOCAL_STORAGE_KEY),
+            None
+        );
+        sp_io::offchain::local_storage_set(
+            sp_core::offchain::StorageKind::PERSISTENT,
+            crate::IPFS_ROOT_URL_LOCAL_STORAGE_KEY,
+            b"http://local-storage",
+        );
+        assert_eq!(
+            crate::offchain_local_storage_get(crate::IPFS_ROOT_URL_LOCAL_STORAGE_KEY),
+            Some(b"http://local-storage".to_vec())
+        );
+    });
+}
+
+/// The dispatchables MUST NOT touch the offchain local storage: there is none during block import
+#[test]
+#[serial_test::serial]
+fn test_dispatch_ipfs_uris_without_offchain_ext() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        std::env::set_var("IPFS_ROOT_URL", MOCK_IPFS_DOWN_URL);
+        assert_eq!(
+            crate::get_ipfs_uris::<Test>(None).unwrap(),
+            vec![MOCK_IPFS_DOWN_URL.to_string()]
+        );
+    });
+}
+
+fn to_cid(cid: &[u8]) -> sp_core::bounded::BoundedVec<u8, frame_support::traits::ConstU32<64>> {
+    cid.to_vec().try_into().unwrap()
+}
+
+/// Removing a pending circuit(here: cancel) MUST queue both its cids for unpinning
+#[test]
+fn test_removed_pending_circuit_is_queued_for_unpin() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+        assert!(OcwGarble::get_unpin_queue().is_empty());
+
+        assert_ok!(OcwGarble::cancel_pending_circuit(
+            RuntimeOrigin::signed(account_id),
+            b"MESSAGE_CID".to_vec()
+        ));
+
+        assert_eq!(
+            OcwGarble::get_unpin_queue().into_inner(),
+            vec![to_cid(b"MESSAGE_CID"), to_cid(b"PINPAD_MESSAGE_CID")]
+        );
+    });
+}
+
+/// What the offchain worker sends in `unpin_released_circuits`
+fn confirm_unpinned_call(
+    pair: &sp_core::sr25519::Pair,
+    public: sp_core::sr25519::Public,
+    pgarbled_cids: Vec<Vec<u8>>,
+) -> crate::Call<Test> {
+    use sp_core::{Encode, Pair};
+
+    let payload = crate::ConfirmUnpinnedPayload {
+        pgarbled_cids,
+        block_number: 1,
+        public,
+    };
+    let signature = pair.sign(&payload.encode());
+    crate::Call::confirm_unpinned_unsigned { payload, signature }
+}
+
+fn validate_unsigned(
+    call: &crate::Call<Test>,
+) -> sp_runtime::transaction_validity::TransactionValidity {
+    <OcwGarble as frame_support::unsigned::ValidateUnsigned>::validate_unsigned(
+        sp_runtime::transaction_validity::TransactionSource::Local,
+        call,
+    )
+}
+
+/// Else anyone could remove the cids from `PinnedCidsMap`: they would never be unpinned
+#[test]
+fn test_validate_unsigned_confirm_unpinned() {
+    use sp_core::Pair;
+    use sp_runtime::transaction_validity::InvalidTransaction;
+
+    new_test_ext_no_ipfs().execute_with(|| {
+        let authority = sp_core::sr25519::Pair::from_seed(&[1; 32]);
+        let other = sp_core::sr25519::Pair::from_seed(&[2; 32]);
+        let cids = vec![b"MESSAGE_CID".to_vec()];
+        let authority_call = confirm_unpinned_call(&authority, authority.public(), cids.clone());
+
+        assert_eq!(
+            validate_unsigned(&authority_call),
+            Err(InvalidTransaction::BadSigner.into())
+        );
+
+        assert_ok!(pallet_ocw_circuits::Pallet::<Test>::set_ocw_authorities(
+            RuntimeOrigin::root(),
+            vec![authority.public()]
+        ));
+        assert!(validate_unsigned(&authority_call).is_ok());
+        assert_eq!(
+            validate_unsigned(&confirm_unpinned_call(&other, other.public(), cids.clone())),
+            Err(InvalidTransaction::BadSigner.into())
+        );
+        // signed by other, claiming to be the authority
+        assert_eq!(
+            validate_unsigned(&confirm_unpinned_call(&other, authority.public(), cids)),
+            Err(InvalidTransaction::BadProof.into())
+        );
+    });
+}
+
+/// confirm_unpinned_unsigned MUST NOT release the cids of a circuit still pending
+#[test]
+fn test_confirm_unpinned_only_removes_released_cids() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        add_pending_circuit(account_id, b"MESSAGE_CID_1");
+        add_pending_circuit(account_id, b"MESSAGE_CID_2");
+        assert_ok!(OcwGarble::cancel_pending_circuit(
+            RuntimeOrigin::signed(account_id),
+            b"MESSAGE_CID_1".to_vec()
+        ));
+
+        // NOTE: the signature is checked in `validate_unsigned`, NOT in the call itself
+        let pair = <sp_core::sr25519::Pair as sp_core::Pair>::from_seed(&[1; 32]);
+        let call = confirm_unpinned_call(
+            &pair,
+            test_pub(),
+            vec![
+                b"MESSAGE_CID_1".to_vec(),
+                b"PINPAD_MESSAGE_CID_1".to_vec(),
+                b"MESSAGE_CID_2".to_vec(),
+            ],
+        );
+        assert_ok!(
+            frame_support::dispatch::UnfilteredDispatchable::dispatch_bypass_filter(
+                call,
+                RuntimeOrigin::none()
+            )
+        );
+
+        assert!(OcwGarble::get_unpin_queue().is_empty());
+        assert!(!crate::PinnedCidsMap::<Test>::contains_key(to_cid(b"MESSAGE_CID_1")));
+        assert!(!crate::PinnedCidsMap::<Test>::contains_key(to_cid(b"PINPAD_MESSAGE_CID_1")));
+        assert!(crate::PinnedCidsMap::<Test>::contains_key(to_cid(b"MESSAGE_CID_2")));
+        assert!(crate::PinnedCidsMap::<Test>::contains_key(to_cid(b"PINPAD_MESSAGE_CID_2")));
+    });
+}
+
+/// A cid has ONE owner: else the second one could get it unpinned while the first still uses it
+#[test]
+fn test_register_already_pinned_cid_err() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        let other_account_id = sp_core::sr25519::Public::from_raw([3u8; 32]);
+        add_pending_circuit(account_id, b"MESSAGE_CID");
+
+        assert_err!(
+            crate::Pallet::<Test>::callback_new_garbled_and_strip_signed(
+                other_account_id,
+                crate::Pallet::<Test>::next_request_id(),
+                b"OTHER_MESSAGE_CID".to_vec(),
+                vec![3, 4],
+                b"MESSAGE_CID".to_vec(),
+                vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9],
+                PinpadLayout::row(10),
+                test_tx_intent(),
+            ),
+            crate::Error::<Test>::CidAlreadyPinned
+        );
+        assert_err!(
+            crate::Pallet::<Test>::register_garbled_circuit(
+                other_account_id,
+                0,
+                b"PINPAD_MESSAGE_CID".to_vec()
+            ),
+            crate::Error::<Test>::CidAlreadyPinned
+        );
+
+        assert_eq!(
+            crate::PinnedCidsMap::<Test>::get(to_cid(b"MESSAGE_CID")),
+            Some((account_id, to_cid(b"MESSAGE_CID")))
+        );
+        assert!(OcwGarble::get_pending_circuits_for_account(other_account_id).is_empty());
+    });
+}
+
+/// The .skcd of ocw-circuits MUST NEVER be unpinned by this pallet; nor registered as a garbled one
+#[test]
+fn test_display_skcd_cids_are_never_released() {
+    let mut t = new_test_ext_no_ipfs();
+    set_ocw_circuits_storage_direct(
+        "MESSAGE_SKCD".to_string(),
+        "PINPAD_SKCD".to_string(),
+        &mut t,
+    );
+    t.execute_with(|| {
+        let account_id = test_pub();
+        assert_err!(
+            crate::Pallet::<Test>::register_garbled_circuit(account_id, 0, b"PINPAD_SKCD".to_vec()),
+            crate::Error::<Test>::CidAlreadyPinned
+        );
+
+        assert!(crate::Pallet::<Test>::is_cid_in_use(b"MESSAGE_SKCD"));
+        assert!(crate::Pallet::<Test>::is_cid_in_use(b"PINPAD_SKCD"));
+        assert!(!crate::Pallet::<Test>::is_pinned_cid_releasable(
+            b"MESSAGE_SKCD"
+        ));
+        assert!(!crate::Pallet::<Test>::is_cid_in_use(b"UNKNOWN_CID"));
+    });
+}
+
+/// The "generic" circuits MUST be pinned until they expire; then queued for unpinning
+#[test]
+fn test_garbled_circuit_expires_and_is_queued_for_unpin() {
+    new_test_ext_no_ipfs().execute_with(|| {
+        let account_id = test_pub();
+        // NOTE: as done by `garble_standard_signed` after the upload
+        assert_ok!(crate::Pallet::<Test>::register_garbled_circuit(
+            account_id,
+            0,
+            b"GARBLED_CID".to_vec()
+        ));
+        assert_eq!(
+            crate::PinnedCidsMap::<Test>::get(to_cid(b"GARBLED_CID")),
+            Some((account_id, to_cid(b"GARBLED_CID")))
+        );
+
+        OcwGarble::on_initialize(PENDING_CIRCUIT_LIFETIME);
+        assert_eq!(
+            OcwGarble::get_garbled_circuits_for_account(account_id).len(),
+            1
+        );
+        assert!(OcwGarble::get_unpin_queue().is_empty());
+
+        OcwGarble::on_initialize(1 + PENDING_CIRCUIT_LIFETIME);
+        assert!(OcwGarble::get_garbled_circuits_for_account(account_id).is_empty());
+        assert_eq!(
+            OcwGarble::get_unpin_queue().into_inner(),
+            vec![to_cid(b"GARBLED_CID")]
+        );
+        // it can now be released; cf `confirm_unpinned_unsigned`
+        assert!(crate::Pallet::<Test>::is_pinned_cid_releasable(
+            b"GARBLED_CID"
+        ));
+    });
+}
+
+/// unpin_cid MUST succeed if at least one of the IPFS endpoints unpinned it
+#[test]
+#[serial_test::serial]
+fn test_unpin_cid() {
+    let mock_server_ipfs = httpmock::MockServer::start();
+    let unpin_mock = mock_server_ipfs.mock(|when, then| {
+        when.method(httpmock::Method::POST)
+            .path("/api/v0/pin/rm")
+            .query_param("arg", "QmPGARBLED");
+        then.status(200).body(r#"{"Pins":["QmPGARBLED"]}"#);
+    });
+
+    new_test_ext_offchain().execute_with(|| {
+        let ipfs_uris = vec![MOCK_IPFS_DOWN_URL.to_string()];
+        assert!(crate::unpin_cid::<Test>(&ipfs_uris, b"QmPGARBLED").is_err());
+
+        let ipfs_uris = vec![MOCK_IPFS_DOWN_URL.to_string(), mock_server_ipfs.base_url()];
+        assert!(crate::unpin_cid::<Test>(&ipfs_uris, b"QmPGARBLED").is_ok());
+    });
+
+    unpin_mock.assert();
+}
+
+#[test]
+fn test_skcd_cache_lru_eviction() {
+    let mut cache = crate::skcd_cache::SkcdCache::new(2, 1024);
+    cache.insert(b"CID_1".to_vec(), vec![1]);
+    cache.insert(b"CID_2".to_vec(), vec![2]);
+    // CID_1 is now the most recently used; so CID_2 is evicted
+    assert_eq!(cache.get(b"CID_1"), Some(vec![1]));
+    cache.insert(b"CID_3".to_vec(), vec![3]);
+
+    assert_eq!(cache.get(b"CID_2"), None);
+    assert_eq!(cache.get(b"CID_1"), Some(vec![1]));
+    assert_eq!(cache.get(b"CID_3"), Some(vec![3]));
+}
+
+#[test]
+fn test_skcd_cache_size_bounded() {
+    let mut cache = crate::skcd_cache::SkcdCache::new(8, 10);
+    cache.insert(b"CID_1".to_vec(), vec![1; 6]);
+    cache.insert(b"CID_2".to_vec(), vec![2; 6]);
+    assert_eq!(cache.get(b"CID_1"), None);
+    assert_eq!(cache.get(b"CID_2"), Some(vec![2; 6]));
+
+    // too big to ever fit
+    cache.insert(b"CID_3".to_vec(), vec![3; 11]);
+    assert_eq!(cache.get(b"CID_3"), None);
+    assert_eq!(cache.get(b"CID_2"), Some(vec![2; 6]));
+}
+
+/// A new package in pallet-ocw-circuits MUST invalidate the cache
+#[test]
+#[serial_test::serial]
+fn test_skcd_cache_invalidated_on_new_display_skcd_package() {
+    let fetch_count = std::cell::Cell::new(0);
+    let fetch = || -> Result<Vec<u8>, ()> {
+        fetch_count.set(fetch_count.get() + 1);
+        Ok(vec![42])
+    };
+
+    new_test_ext_no_ipfs().execute_with(|| {
+        crate::skcd_cache::invalidate();
+        assert_eq!(crate::skcd_cache::get_or_fetch(b"CID", fetch), Ok(vec![42]));
+        assert_eq!(crate::skcd_cache::get_or_fetch(b"CID", fetch), Ok(vec![42]));
+        assert_eq!(fetch_count.get(), 1);
+
+        // NOTE: the signature is checked in `validate_unsigned`, NOT in the call itself
+        assert_ok!(
+            pallet_ocw_circuits::Pallet::<Test>::callback_new_display_circuits_package_unsigned(
+                RuntimeOrigin::none(),
+                pallet_ocw_circuits::NewDisplayCircuitsPackagePayload {
+                    message_skcd_cid: b"MESSAGE_SKCD_CID".to_vec(),
+                    message_nb_digits: 2,
+                    pinpad_skcd_cid: b"PINPAD_SKCD_CID".to_vec(),
+                    pinpad_nb_digits: 10,
+                    alphabet: DECIMAL_ALPHABET.to_vec(),
+                    pinpad_layout: PinpadLayout::row(10),
+                    message_capacity: Default::default(),
+                    request_id: 0,
+                    public: test_pub(),
+                },
+                sp_core::sr25519::Signature::from_raw([0; 64]),
+            )
+        );
+
+        assert_eq!(crate::skcd_cache::get_or_fetch(b"CID", fetch), Ok(vec![42]));
+        assert_eq!(fetch_count.get(), 2);
+    });
+}
+
+/// The enclave garbles from several threads; they MUST share the same cache
+#[test]
+#[serial_test::serial]
+fn test_skcd_cache_shared_between_threads() {
+    crate::skcd_cache::invalidate();
+    std::thread::spawn(|| {
+        assert_eq!(
+            crate::skcd_cache::get_or_fetch(b"CID", || Ok::<_, ()>(vec![42])),
+            Ok(vec![42])
+        );
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(
+        crate::skcd_cache::get_or_fetch(b"CID", || -> Result<Vec<u8>, ()> {
+            panic!("MUST be a cache hit")
+        }),
+        Ok(vec![42])
+    );
+    crate::skcd_cache::invalidate();
+}
+
+/// A garbler bug MUST NOT be published: the users would be locked out
+#[tokio::test]
+#[serial_test::serial]
+async fn test_garble_and_strip_self_check_failed() {
+    MockGarblerShowsWrongDigits::set(true);
+    let res =
+        test_garble_and_strip_display_circuits_package_signed(MockType::RpcOcwCircuitsStorageValid)
+            .await;
+    MockGarblerShowsWrongDigits::set(false);
+
+    assert_err!(
+        res,
+        DispatchError::Module(ModuleError {
+            index: 3,
+            error: [19, 0, 0, 0],
+            message: Some("GarbledPackageSelfCheckFailed")
+        }),
+    );
+}
+
+/// The real garbler with a real .skcd: the evaluated frame MUST show the digits garbled in;
+/// ie what the self-check relies on. NOTE: slow
+#[cfg(all(feature = "lib-garble-rs", not(target_family = "wasm")))]
+#[test]
+fn test_lib_garble_rs_garble_and_evaluate_pinpad() {
+    use crate::Garbler;
+    use circuits_storage_common::seven_segments::read_digits;
+
+    let skcd_buf = include_bytes!("../tests/data/display_pinpad_590x50.skcd.postcard.bin");
+    let pinpad_digits = vec![4, 5, 6, 0, 1, 2, 3, 7, 8, 9];
+
+    let package = crate::LibGarbleRs::garble_and_strip(skcd_buf, &pinpad_digits, "").unwrap();
+    let layout = pallet_ocw_circuits::pinpad_display_layout(&PinpadLayout::row(10));
+    // same number of frames as the self-check
+    let frame = crate::LibGarbleRs::evaluate(&package, 16, &layout).unwrap();
+
+    let (width, height, digits_bboxes) = layout.clone();
+    assert_eq!((frame.width, frame.height), (width, height));
+    assert_eq!(
+        read_digits(&frame, &digits_bboxes),
+        pinpad_digits
+            .iter()
+            .map(|digit| Some(*digit))
+            .collect::<Vec<_>>()
+    );
+
+    // NOT the layout the circuit was generated with
+    let wrong_layout = (width / 2, height, digits_bboxes);
+    assert!(crate::LibGarbleRs::evaluate(&package, 16, &wrong_layout).is_err());
+
+    assert!(crate::LibGarbleRs::evaluate(&[42, 42], 16, &layout).is_err());
+}
+
+/// The self-check reads back what is drawn; with the real layouts
+/// eg the default profile, 6 hexadecimal digits with a 16 cells pinpad, and the pinpad grids
+#[test]
+fn test_seven_segments_read_back_all_digits() {
+    use circuits_storage_common::seven_segments::{
+        draw_digit, read_digits, Frame, SEGMENTS_BY_SYMBOL,
+    };
+
+    let layouts = [
+        pallet_ocw_circuits::display_layout(true, 2),
+        pallet_ocw_circuits::display_layout(false, 10),
+        pallet_ocw_circuits::display_layout(true, 6),
+        pallet_ocw_circuits::display_layout(false, 16),
+        pallet_ocw_circuits::pinpad_display_layout(&PinpadLayout::phone()),
+        pallet_ocw_circuits::pinpad_display_layout(&PinpadLayout {
+            gutter_permille: 200,
+            ..PinpadLayout::grid(4, 4)
+        }),
+    ];
+    for (width, height, digits_bboxes) in layouts {
+        let nb_digits = digits_bboxes.len() / 4;
+        let nb_symbols = SEGMENTS_BY_SYMBOL.len() as u8;
+
+        for first_digit in 0..nb_symbols {
+            let digits: Vec<u8> = (0..nb_digits as u8)
+                .map(|i| (first_digit + i) % nb_symbols)
+                .collect();
+            let mut frame = Frame::new(width, height);
+            for (bbox, digit) in digits_bboxes.chunks_exact(4).zip(&digits) {
+                draw_digit(&mut frame, bbox, *digit);
+            }
+
+            assert_eq!(
+                read_digits(&frame, &digits_bboxes),
+                digits.into_iter().map(Some).collect::<Vec<_>>()
+            );
+        }
+    }
+    // nothing drawn: not a digit
+    assert_eq!(
+        read_digits(&Frame::new(10, 10), &[0.0, 0.0, 1.0, 1.0]),
+        vec![None]
+    );
+}
@@ -0,0 +1,27 @@
+//! In-process cache of the last `DisplaySkcdPackage` read(and verified) via RPC
+//! cf `get_ocw_circuits_storage_value_rpc`
+//!
+//! It used to be a `StorageValue`; but it is only an optimisation of the current node/enclave,
+//! it MUST NOT be part of the state.
+//! NOTE: `spin::Mutex` for the same reason as `skcd_cache`: also enabled in the enclave(sgx, no_std)
+use circuits_storage_common::DisplaySkcdPackage;
+
+static VERIFIED_PACKAGE_CACHE: spin::Mutex<Option<(sp_core::H256, DisplaySkcdPackage)>> =
+    spin::Mutex::new(None);
+
+/// return: the cached package iff it was verified against `block_hash`
+pub(crate) fn get(block_hash: &sp_core::H256) -> Option<DisplaySkcdPackage> {
+    VERIFIED_PACKAGE_CACHE
+        .lock()
+        .as_ref()
+        .filter(|(cached_block_hash, _)| cached_block_hash == block_hash)
+        .map(|(_, package)| package.clone())
+}
+
+pub(crate) fn put(block_hash: sp_core::H256, package: DisplaySkcdPackage) {
+    *VERIFIED_PACKAGE_CACHE.lock() = Some((block_hash, package));
+}
+
+pub(crate) fn invalidate() {
+    *VERIFIED_PACKAGE_CACHE.lock() = None;
+}
@@ -14,10 +14,20 @@ mod tests;
 #[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
 
+pub mod migrations;
+
+extern crate alloc;
+
 #[frame_support::pallet]
 pub mod pallet {
+    use alloc::format;
+    use alloc::string::String;
     use frame_support::pallet_prelude::*;
+    use frame_support::traits::{Currency, ExistenceRequirement};
     use frame_system::pallet_prelude::*;
+    use sp_runtime::traits::{BlakeTwo256, Hash, Saturating, TrailingZeroInput, Zero};
+    use sp_runtime::ArithmeticError;
+    use sp_runtime::SaturatedConversion;
     use sp_std::vec::Vec;
 
     /// Configure the pallet by specifying the parameters and types on which it depends.
@@ -25,6 +35,144 @@ pub mod pallet {
     pub trait Config: frame_system::Config + 'static {
         /// The overarching event type.
         type RuntimeEvent: From<Event<Self>> + IsType<<Self as frame_system::Config>::RuntimeEvent>;
+        /// What to do with the `TxIntent` once the user has confirmed it(ie on TxPass)
+        type Executor: TxIntentExecutor<Self::AccountId>;
+        /// Notified at the end of `check_input`(both TxPass and TxFail); eg pallet-ocw-garble
+        /// uses it to cleanup its pending circuits
+        type OnCircuitValidated: OnCircuitValidated<Self::AccountId>;
+        /// Check the signature of `check_input_unsigned`; typically pallet-mobile-registry
+        type MobileKeyVerifier: MobileKeyVerifier<Self::AccountId>;
+        /// Max number of blocks a `CheckInputPayload` is valid for; cf `CheckInputPayload::valid_until`
+        #[pallet::constant]
+        type UnsignedLongevity: Get<Self::BlockNumber>;
+        /// Max length of the code(ie the message digits); eg `circuits_storage_common::MAX_MESSAGE_NB_DIGITS`
+        #[pallet::constant]
+        type MaxMessageDigits: Get<u32>;
+        /// Max number of pinpad cells(ie the alphabet size); eg `circuits_storage_common::MAX_ALPHABET_LEN`
+        /// NOTE: `InputEncoding::Ascii` can only address 36 cells
+        #[pallet::constant]
+        type MaxPinpadDigits: Get<u32>;
+        /// Key of the commitments; cf `compute_commitment`
+        /// IMPORTANT: MUST be backed by a secret sealed in the enclave(eg provisioned to the workers);
+        /// NEVER a constant of a public runtime, nor anything in the Storage. Whoever knows it can
+        /// brute-force the expected inputs of every circuit. NOT a `pallet::constant` for the same reason.
+        type CommitmentSecret: Get<[u8; 32]>;
+    }
+
+    /// cf `Config::MobileKeyVerifier`; with `()` `check_input_unsigned` is always rejected
+    pub use mobile_registry_common::MobileKeyVerifier;
+
+    /// cf `Config::OnCircuitValidated`
+    pub trait OnCircuitValidated<AccountId> {
+        fn on_circuit_validated(who: &AccountId, message_pgarbled_cid: &[u8], is_tx_pass: bool);
+    }
+
+    impl<AccountId> OnCircuitValidated<AccountId> for () {
+        fn on_circuit_validated(_who: &AccountId, _message_pgarbled_cid: &[u8], _is_tx_pass: bool) {
+        }
+    }
+
+    /// max length of `TxIntent::memo`
+    pub type MaxMemoLen = ConstU32<32>;
+
+    /// What a "message" circuit is actually authorizing.
+    ///
+    /// It is stored under the circuit's cid(cf `TxIntentMap`) and rendered on the "message" circuit
+    /// using `render_display_text`; that way what is displayed to the user IS what is executed.
+    #[derive(
+        Clone,
+        Encode,
+        Decode,
+        Eq,
+        PartialEq,
+        RuntimeDebug,
+        Default,
+        scale_info::TypeInfo,
+        MaxEncodedLen,
+    )]
+    pub struct TxIntent<AccountId> {
+        pub recipient: AccountId,
+        pub amount: u128,
+        /// None: native currency
+        pub asset_id: Option<u32>,
+        pub memo: BoundedVec<u8, MaxMemoLen>,
+    }
+
+    /// Number of bytes of `TxIntent::recipient_checksum`; ie 80 bits
+    pub const RECIPIENT_CHECKSUM_LEN: usize = 10;
+
+    impl<AccountId: Encode> TxIntent<AccountId> {
+        /// Deterministic text displayed on the "message" circuit
+        ///
+        /// eg "42 #7 to ~d43593c715fdd31c61e1 coffee"
+        /// NOTE: the full recipient does not fit on the display, so it is shown as its checksum(cf
+        /// `recipient_checksum`); the wallet app MUST show the same one next to the recipient.
+        pub fn render_display_text(&self) -> String {
+            let recipient_checksum: String = self
+                .recipient_checksum()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+
+            let mut text = format!("{}", self.amount);
+            if let Some(asset_id) = self.asset_id {
+                text += &format!(" #{asset_id}");
+            }
+            text += &format!(" to ~{recipient_checksum}");
+            if !self.memo.is_empty() {
+                text += " ";
+                text += &String::from_utf8_lossy(&self.memo);
+            }
+
+            text
+        }
+
+        /// The first `RECIPIENT_CHECKSUM_LEN` bytes of blake2_256(SCALE encoded recipient)
+        ///
+        /// IMPORTANT: it MUST be long enough that nobody can grind an address with the same checksum;
+        /// eg a 4 bytes prefix of the address itself can be found in a few minutes.
+        pub fn recipient_checksum(&self) -> [u8; RECIPIENT_CHECKSUM_LEN] {
+            let hash = BlakeTwo256::hash_of(&self.recipient);
+            let mut checksum = [0u8; RECIPIENT_CHECKSUM_LEN];
+            checksum.copy_from_slice(&hash.as_ref()[..RECIPIENT_CHECKSUM_LEN]);
+            checksum
+        }
+    }
+
+    /// Called on TxPass with the `TxIntent` bound to the circuit; typically dispatch a transfer
+    pub trait TxIntentExecutor<AccountId> {
+        fn execute(who: &AccountId, tx_intent: &TxIntent<AccountId>) -> DispatchResult;
+    }
+
+    /// No-op: only emit TxPass
+    /// NOTE: ONLY for tests, or if the `TxIntent` is executed elsewhere; use eg `CurrencyExecutor`
+    impl<AccountId> TxIntentExecutor<AccountId> for () {
+        fn execute(_who: &AccountId, _tx_intent: &TxIntent<AccountId>) -> DispatchResult {
+            Ok(())
+        }
+    }
+
+    /// Transfer `amount` of the native currency from the confirming account to the recipient
+    /// `asset_id` MUST be None: there is no assets pallet in the runtime(yet).
+    pub struct CurrencyExecutor<Currency>(PhantomData<Currency>);
+
+    impl<AccountId, C: Currency<AccountId>> TxIntentExecutor<AccountId> for CurrencyExecutor<C> {
+        fn execute(who: &AccountId, tx_intent: &TxIntent<AccountId>) -> DispatchResult {
+            if tx_intent.asset_id.is_some() {
+                return Err(DispatchError::Other(
+                    "CurrencyExecutor: assets are not supported",
+                ));
+            }
+            let amount =
+                C::Balance::try_from(tx_intent.amount).map_err(|_err| ArithmeticError::Overflow)?;
+
+            C::transfer(
+                who,
+                &tx_intent.recipient,
+                amount,
+                ExistenceRequirement::KeepAlive,
+            )
+        }
     }
 
     // TODO proper structs instead of tuples for the StorageMap(both key and value)
@@ -84,6 +232,13 @@ pub mod pallet {
     // }
 
     /// Easy way to make a link b/w a "message" and "pinpad" circuits
+    ///
+    /// IMPORTANT: the digits themselves are NOT stored; the Storage is readable by anyone via the RPC.
+    /// Instead we store a keyed commitment of the "expected inputs", ie the pinpad indexes the
+    /// user MUST tap to enter the code(cf `compute_expected_inputs`).
+    /// With only a few digits the inputs space is tiny: the salt is NOT stored, it is derived from
+    /// the public `nonce` and `Config::CommitmentSecret`; so the commitment can NOT be brute-forced
+    /// from the Storage alone.
     #[derive(
         Clone,
         Encode,
@@ -95,13 +250,71 @@ pub mod pallet {
         scale_info::TypeInfo,
         MaxEncodedLen,
     )]
-    pub struct DisplayValidationPackage {
-        // usually only 2-4 digits for the message, and always 10 for the pinpad
-        // but we can take some margin
-        pub message_digits: BoundedVec<u8, ConstU32<10>>,
-        pub pinpad_digits: BoundedVec<u8, ConstU32<10>>,
+    pub struct DisplayValidationPackage<Hash> {
+        /// cf `compute_commitment`
+        pub commitment: Hash,
+        /// NOT secret; unique per circuit so that the same inputs do NOT give the same commitment
+        pub nonce: [u8; 32],
+        /// needed to check the inputs length; this is NOT secret(cf `DisplayStrippedCircuitsPackage`)
+        pub message_nb_digits: u32,
+        /// needed to check the inputs are valid pinpad indexes(ie NOT the same thing as a wrong code)
+        pub pinpad_nb_digits: u32,
+    }
+
+    /// How `check_input`'s "input_digits" are encoded
+    #[derive(
+        Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, scale_info::TypeInfo, MaxEncodedLen,
+    )]
+    pub enum InputEncoding {
+        /// ['0';'9'] ie when giving "35" in the text box of a front-end, we get [51,53]
+        /// then ['a';'z'](or uppercase) for the indexes >= 10 ie pinpads with more than 10 cells
+        /// Useful for testing/demo
+        Ascii,
+        /// [0;pinpad_nb_digits[ the pinpad indexes as-is(cf `PinpadCell::Digit` for grids); PROD ie from Android
+        RawIndices,
+    }
+
+    impl InputEncoding {
+        /// Convert the raw inputs to pinpad indexes
+        /// NOTE: only checks the encoding; NOT the pinpad range
+        pub fn decode_inputs(&self, input_digits: &[u8]) -> Option<Vec<u8>> {
+            match self {
+                InputEncoding::Ascii => input_digits
+                    .iter()
+                    .map(|c| char::from(*c).to_digit(36).map(|digit| digit as u8))
+                    .collect(),
+                InputEncoding::RawIndices => Some(input_digits.to_vec()),
+            }
+        }
+    }
+
+    /// What the mobile signs for `check_input_unsigned`; cf `check_input` for the fields
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, scale_info::TypeInfo)]
+    pub struct CheckInputPayload<AccountId, BlockNumber> {
+        pub who: AccountId,
+        pub ipfs_cid: Vec<u8>,
+        pub input_digits: Vec<u8>,
+        pub input_encoding: InputEncoding,
+        /// MUST be `MobileNonces[who]`; replay protection
+        pub nonce: u64,
+        /// last block(included) where the payload is valid;
+        /// at most `Config::UnsignedLongevity` blocks in the future
+        pub valid_until: BlockNumber,
     }
 
+    impl<AccountId: Encode, BlockNumber: Encode> CheckInputPayload<AccountId, BlockNumber> {
+        /// The bytes actually signed by the mobile
+        /// NOTE: the genesis hash is included so that a payload can not be replayed on another chain
+        pub fn signing_message<Hash: Encode>(&self, genesis_hash: &Hash) -> Vec<u8> {
+            (CHECK_INPUT_SIGNING_CONTEXT, genesis_hash, self).encode()
+        }
+    }
+
+    pub const CHECK_INPUT_SIGNING_CONTEXT: &[u8] = b"tx-validation::check_input_unsigned";
+
+    /// `InvalidTransaction::Custom`: `valid_until` is further than `Config::UnsignedLongevity`
+    pub const INVALID_TX_VALID_UNTIL_TOO_FAR: u8 = 1;
+
     /// Store account -> ipfs_hash -> CircuitServerMetadata; typically at least the OTP/digits/permutation
     /// This will be checked against user input to pass/fail the current tx
     // #[pallet::storage]
@@ -120,15 +333,35 @@ pub mod pallet {
         // TODO for now we reference the whole "DisplayStrippedCircuitsPackage" by just using the message_pgarbled_cid;
         //      do we need to use the 4 field as the key?
         BoundedVec<u8, ConstU32<64>>,
-        //  Struct containing both message_digits and pinpad_digits
-        DisplayValidationPackage,
+        //  Struct containing the commitment of both message_digits and pinpad_digits
+        DisplayValidationPackage<T::Hash>,
         // TODO?
         // ValueQuery,
     >;
 
+    /// Store account -> ipfs_hash -> TxIntent
+    /// Same keys as `CircuitServerMetadataMap`; optional b/c not every circuit authorizes a transfer
+    #[pallet::storage]
+    #[pallet::getter(fn tx_intent_map)]
+    pub(super) type TxIntentMap<T: Config> = StorageDoubleMap<
+        _,
+        Twox128,
+        T::AccountId,
+        Twox128,
+        BoundedVec<u8, ConstU32<64>>,
+        TxIntent<T::AccountId>,
+    >;
+
+    /// Next expected `CheckInputPayload::nonce` for each account
+    #[pallet::storage]
+    #[pallet::getter(fn mobile_nonce)]
+    pub(super) type MobileNonces<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u64, ValueQuery>;
+
     /// The current storage version.
+    /// v2: `DisplayValidationPackage` stores a commitment instead of the digits; cf `migrations::v2`
     const STORAGE_VERSION: frame_support::traits::StorageVersion =
-        frame_support::traits::StorageVersion::new(1);
+        frame_support::traits::StorageVersion::new(2);
 
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
@@ -147,7 +380,20 @@ pub mod pallet {
         TxFail {
             account_id: T::AccountId,
         },
+        /// Emitted after TxPass when a `TxIntent` was bound to the circuit
+        TxIntentExecuted {
+            account_id: T::AccountId,
+            result: DispatchResult,
+        },
+        /// `check_input_unsigned` only: the inputs were invalid(cf `check_input` Errors)
+        /// The nonce is still consumed; the mobile MUST sign a new payload to retry.
+        CheckInputRejected {
+            account_id: T::AccountId,
+            error: DispatchError,
+        },
         /// DEBUG ONLY
+        /// NEVER enable feature "debug-events" in PROD: it leaks the digits!
+        #[cfg(feature = "debug-events")]
         DEBUGNewDigitsSet {
             message_digits: Vec<u8>,
             pinpad_digits: Vec<u8>,
@@ -160,20 +406,141 @@ pub mod pallet {
     pub enum Error<T> {
         // The given ipfs_cid was NOT present in CircuitServerMetadataMap
         CircuitNotFound,
-        // inputs SHOULD be [0;9] or ['0';'9']
+        // inputs MUST be [0;pinpad_nb_digits[ or ['0';'9']+['a';'z'] depending on the given InputEncoding
         // and inputs length MUST match expected length
         TxInvalidInputsGiven,
         /// Errors should have helpful documentation associated with them.
         StorageOverflow,
+        // store_metadata_aux: "message_digits" MUST all be present in "pinpad_digits"
+        // and the lengths MUST be within `Config::MaxMessageDigits`/`Config::MaxPinpadDigits`
+        InvalidMetadata,
+    }
+
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_runtime_upgrade() -> Weight {
+            crate::migrations::v2::migrate::<T>()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn pre_upgrade() -> Result<Vec<u8>, &'static str> {
+            crate::migrations::v2::pre_upgrade::<T>()
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn post_upgrade(state: Vec<u8>) -> Result<(), &'static str> {
+            crate::migrations::v2::post_upgrade::<T>(state)
+        }
+    }
+
+    /// Compute the "expected inputs" from the digits: that is the index in the pinpad of each
+    /// digit of the message.
+    ///
+    /// eg message_digits:       [3,4]
+    /// eg pinpad_digits:        [4,5,6,0,1,2,3,7,8,9]
+    /// expected inputs -->      [6,0]
+    pub fn compute_expected_inputs<T: Config>(
+        message_digits: &[u8],
+        pinpad_digits: &[u8],
+    ) -> Result<Vec<u8>, Error<T>> {
+        message_digits
+            .iter()
+            .map(|digit| {
+                pinpad_digits
+                    .iter()
+                    .position(|pinpad_digit| pinpad_digit == digit)
+                    .and_then(|idx| u8::try_from(idx).ok())
+                    .ok_or(Error::<T>::InvalidMetadata)
+            })
+            .collect()
+    }
+
+    /// Commitment stored in `DisplayValidationPackage`
+    /// ie `T::Hashing` of (salt, inputs) with salt = `T::Hashing` of (`Config::CommitmentSecret`, nonce)
+    pub fn compute_commitment<T: Config>(nonce: &[u8; 32], inputs: &[u8]) -> T::Hash {
+        let salt = T::Hashing::hash_of(&(T::CommitmentSecret::get(), nonce));
+        T::Hashing::hash_of(&(salt, inputs))
+    }
+
+    /// Privileged way for other pallets(eg pallet-ocw-garble) to inject the expected metadata
+    /// for a given circuit package.
+    ///
+    /// It is NOT exposed as a Call: a user MUST NOT be able to set its own expected digits!
+    pub trait ValidationMetadataInjector<AccountId> {
+        /// for now we reference the whole "DisplayStrippedCircuitsPackage" by just using the message_pgarbled_cid
+        ///
+        /// param: nonce: MUST be unique; cf `DisplayValidationPackage`
+        fn inject_metadata(
+            who: &AccountId,
+            message_pgarbled_cid: Vec<u8>,
+            message_digits: Vec<u8>,
+            pinpad_digits: Vec<u8>,
+            nonce: [u8; 32],
+        ) -> DispatchResult;
+
+        /// Bind a `TxIntent` to the circuit package; it will be executed on TxPass
+        fn inject_tx_intent(
+            who: &AccountId,
+            message_pgarbled_cid: Vec<u8>,
+            tx_intent: TxIntent<AccountId>,
+        ) -> DispatchResult;
+
+        /// Remove everything stored for the circuit package; eg when cancelled or expired
+        fn remove_metadata(who: &AccountId, message_pgarbled_cid: &[u8]);
+    }
+
+    impl<T: Config> ValidationMetadataInjector<T::AccountId> for Pallet<T> {
+        fn inject_metadata(
+            who: &T::AccountId,
+            message_pgarbled_cid: Vec<u8>,
+            message_digits: Vec<u8>,
+            pinpad_digits: Vec<u8>,
+            nonce: [u8; 32],
+        ) -> DispatchResult {
+            store_metadata_aux::<T>(
+                who,
+                message_pgarbled_cid,
+                message_digits,
+                pinpad_digits,
+                nonce,
+            )
+        }
+
+        fn inject_tx_intent(
+            who: &T::AccountId,
+            message_pgarbled_cid: Vec<u8>,
+            tx_intent: TxIntent<T::AccountId>,
+        ) -> DispatchResult {
+            <TxIntentMap<T>>::insert(
+                who,
+                TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(message_pgarbled_cid)
+                    .map_err(|_err| Error::<T>::InvalidMetadata)?,
+                tx_intent,
+            );
+
+            Ok(())
+        }
+
+        fn remove_metadata(who: &T::AccountId, message_pgarbled_cid: &[u8]) {
+            if let Ok(message_pgarbled_cid) =
+                TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(message_pgarbled_cid.to_vec())
+            {
+                <CircuitServerMetadataMap<T>>::remove(who, &message_pgarbled_cid);
+                <TxIntentMap<T>>::remove(who, &message_pgarbled_cid);
+            }
+        }
     }
 
     /// for now we reference the whole "DisplayStrippedCircuitsPackage" by just using the message_pgarbled_cid
     /// so we only pass "message_pgarbled_cid"
-    pub fn store_metadata_aux<T: Config>(
+    ///
+    /// param: nonce: MUST be unique; cf `DisplayValidationPackage`
+    pub(crate) fn store_metadata_aux<T: Config>(
         who: &T::AccountId,
         message_pgarbled_cid: Vec<u8>,
         message_digits: Vec<u8>,
         pinpad_digits: Vec<u8>,
+        nonce: [u8; 32],
     ) -> DispatchResult {
         // TODO TOREMOVE
         // Check that the extrinsic was signed and get the signer.
@@ -181,28 +548,49 @@ pub mod pallet {
         // https://docs.substrate.io/v3/runtime/origins
         // let who = ensure_signed(origin)?;
 
+        // NOTE: validate BEFORE logging; the log! args are NOT evaluated when the level is disabled
+        let message_pgarbled_cid_str = sp_std::str::from_utf8(&message_pgarbled_cid)
+            .map_err(|_err| Error::<T>::InvalidMetadata)?;
         log::info!(
-            "[tx-validation] store_metadata_aux: who = {:?}, message_pgarbled_cid = {:?}, message_digits = {:?}, pinpad_digits = {:?}",
+            "[tx-validation] store_metadata_aux: who = {:?}, message_pgarbled_cid = {:?}",
             who,
-            sp_std::str::from_utf8(&message_pgarbled_cid).expect("message_pgarbled_cid utf8"),
-            &message_digits,
-            &pinpad_digits,
+            message_pgarbled_cid_str,
         );
+        let message_pgarbled_cid: BoundedVec<u8, ConstU32<64>> = message_pgarbled_cid
+            .try_into()
+            .map_err(|_err| Error::<T>::InvalidMetadata)?;
 
+        #[cfg(feature = "debug-events")]
         crate::Pallet::<T>::deposit_event(Event::DEBUGNewDigitsSet {
             message_digits: message_digits.clone(),
             pinpad_digits: pinpad_digits.clone(),
         });
 
+        // cf `Config::MaxMessageDigits` and `Config::MaxPinpadDigits`
+        if message_digits.is_empty()
+            || message_digits.len() > T::MaxMessageDigits::get() as usize
+            || pinpad_digits.len() > T::MaxPinpadDigits::get() as usize
+        {
+            return Err(Error::<T>::InvalidMetadata.into());
+        }
+
+        let expected_inputs = compute_expected_inputs::<T>(&message_digits, &pinpad_digits)?;
+
         // Update storage.
         <CircuitServerMetadataMap<T>>::insert(
             who,
-            TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(message_pgarbled_cid).unwrap(),
+            message_pgarbled_cid,
             DisplayValidationPackage {
-                message_digits: TryInto::<BoundedVec<u8, ConstU32<10>>>::try_into(message_digits)
-                    .unwrap(),
-                pinpad_digits: TryInto::<BoundedVec<u8, ConstU32<10>>>::try_into(pinpad_digits)
-                    .unwrap(),
+                commitment: compute_commitment::<T>(&nonce, &expected_inputs),
+                nonce,
+                message_nb_digits: message_digits
+                    .len()
+                    .try_into()
+                    .map_err(|_err| Error::<T>::InvalidMetadata)?,
+                pinpad_nb_digits: pinpad_digits
+                    .len()
+                    .try_into()
+                    .map_err(|_err| Error::<T>::InvalidMetadata)?,
             },
         );
         log::info!("[tx-validation] store_metadata_aux: done!");
@@ -215,105 +603,268 @@ pub mod pallet {
     // Dispatchable functions must be annotated with a weight and must return a DispatchResult.
     #[pallet::call]
     impl<T: Config> Pallet<T> {
-        // TODO remove call? how to properly handle calling store_metadata_aux from pallet-ocw-garble???
-        // NOTE: this is needed only for tests...
+        /// Set the expected metadata for a given account and circuit package.
+        ///
+        /// Root ONLY: else any user could set its own expected digits and then trivially pass
+        /// `check_input`. Other pallets SHOULD use `ValidationMetadataInjector` instead.
         #[pallet::call_index(0)]
         #[pallet::weight(10_000)] // TODO + T::DbWeight::get().writes(1)
         pub fn store_metadata(
             origin: OriginFor<T>,
+            who: T::AccountId,
             message_pgarbled_cid: Vec<u8>,
             message_digits: Vec<u8>,
             pinpad_digits: Vec<u8>,
         ) -> DispatchResult {
-            // Check that the extrinsic was signed and get the signer.
-            // This function will return an error if the extrinsic is not signed.
-            // https://docs.substrate.io/v3/runtime/origins
-            let who = ensure_signed(origin)?;
+            ensure_root(origin)?;
+
+            // unique per (account, cid, block); NOT random but it is public anyway
+            let nonce_hash = T::Hashing::hash_of(&(
+                &who,
+                &message_pgarbled_cid,
+                <frame_system::Pallet<T>>::parent_hash(),
+            ));
+            let nonce = <[u8; 32]>::decode(&mut TrailingZeroInput::new(nonce_hash.as_ref()))
+                .expect("input is padded with zeroes; qed");
 
-            store_metadata_aux::<T>(&who, message_pgarbled_cid, message_digits, pinpad_digits)
+            store_metadata_aux::<T>(
+                &who,
+                message_pgarbled_cid,
+                message_digits,
+                pinpad_digits,
+                nonce,
+            )
         }
 
-        // NOTE: for now this extrinsic is called from the front-end so input_digits is ascii
-        // ie when giving "35" in the text box, we get [51,53]
+        /// Check the user inputs(ie the indexes tapped on the pinpad) against the expected ones
+        ///
+        /// param: input_encoding: cf `InputEncoding`; there is NO guessing: eg giving ['6','0'] with
+        /// `InputEncoding::RawIndices` is invalid.
+        ///
+        /// Invalid inputs(wrong encoding, out of the pinpad range, wrong length) return an Err,
+        /// which means the user CAN retry. A wrong code is NOT an Err, cf `Event::TxFail`.
         #[pallet::call_index(1)]
         #[pallet::weight(10_000)] // TODO + T::DbWeight::get().writes(1)
         pub fn check_input(
             origin: OriginFor<T>,
             ipfs_cid: Vec<u8>,
             input_digits: Vec<u8>,
+            input_encoding: InputEncoding,
         ) -> DispatchResult {
             // Check that the extrinsic was signed and get the signer.
             // This function will return an error if the extrinsic is not signed.
             // https://docs.substrate.io/v3/runtime/origins
             let who = ensure_signed(origin)?;
-            log::info!(
-                "[tx-validation] check_input: who = {:?}, ipfs_cid = {:?}, input_digits = {:?}",
-                &who,
-                sp_std::str::from_utf8(&ipfs_cid).expect("ipfs_cid utf8"),
-                input_digits,
-            );
 
-            // Compare with storage
-            let display_validation_package = <CircuitServerMetadataMap<T>>::get(
-                who.clone(),
-                TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(ipfs_cid).unwrap(),
-            )
-            .ok_or(Error::<T>::CircuitNotFound)?;
-
-            // convert ascii to digits
-            // first step: Vec<u8> to str; that way we can then use "to_digit"
-            // DO NOT convert if inputs are [0;9] only convert if they are ['0';'9']
-            // That way if works both using a front-end(usuful for testing/demo) and directly using API/cli(PROD, ie from Android)
-            // TODO test from Android
-            let input_digits_str =
-                sp_std::str::from_utf8(&input_digits).expect("input_digits utf8");
-            let input_digits_int: Vec<u8> = input_digits_str
-                .chars()
-                .map(|c| u8::try_from(c.to_digit(10u32).unwrap_or(c as u32)).unwrap())
-                .collect();
+            check_input_aux::<T>(who, ipfs_cid, input_digits, input_encoding)
+        }
 
-            // use permutation(ie pinpad_digits)
-            let pinpad_permutation = display_validation_package.pinpad_digits;
-            log::info!(
-                "[tx-validation] check_input: input_digits_str = {:?}, input_digits_int = {:?}, pinpad_permutation = {:?}",
-                input_digits_str,
-                input_digits_int,
-                pinpad_permutation,
-            );
+        /// Same as `check_input` but feeless: the payload is signed by the mobile registered for
+        /// `payload.who`(cf `Config::MobileKeyVerifier`) instead of the account itself.
+        /// That way the confirming device does not need to hold funds.
+        ///
+        /// The signature, nonce and `valid_until` are checked in `validate_unsigned`.
+        /// NOTE: invalid inputs are NOT an Err here; cf `Event::CheckInputRejected`
+        #[pallet::call_index(2)]
+        #[pallet::weight(10_000)] // TODO + T::DbWeight::get().writes(2)
+        pub fn check_input_unsigned(
+            origin: OriginFor<T>,
+            payload: CheckInputPayload<T::AccountId, T::BlockNumber>,
+            _signature: Vec<u8>,
+        ) -> DispatchResult {
+            ensure_none(origin)?;
+
+            <MobileNonces<T>>::mutate(&payload.who, |nonce| *nonce = nonce.wrapping_add(1));
+
+            // IMPORTANT: DO NOT return the Err: that would also rollback the nonce, and the same
+            // payload could then be replayed for free(the call is feeless)
+            let who = payload.who.clone();
+            let result = frame_support::storage::with_transaction(|| {
+                let result = check_input_aux::<T>(
+                    payload.who,
+                    payload.ipfs_cid,
+                    payload.input_digits,
+                    payload.input_encoding,
+                );
+                match result {
+                    Ok(()) => sp_runtime::TransactionOutcome::Commit(Ok(())),
+                    Err(err) => sp_runtime::TransactionOutcome::Rollback(Err(err)),
+                }
+            });
+            if let Err(error) = result {
+                log::info!("[tx-validation] check_input_unsigned: rejected: {:?}", error);
+                crate::Pallet::<T>::deposit_event(Event::CheckInputRejected {
+                    account_id: who,
+                    error,
+                });
+            }
 
-            // map user inputs -> true inputs
-            // eg user inputs:          [0,1]
-            // eg pinpad_permutation:   [9,8,7,0,1,...]
-            // true inputs -->          [9,8]
-            let computed_inputs_from_permutation: Result<Vec<u8>, Error<T>> = input_digits_int
-                .into_iter()
-                .map(|pinpad_index| {
-                    pinpad_permutation
-                        .get(pinpad_index as usize)
-                        .ok_or(Error::<T>::TxInvalidInputsGiven)
-                        .map(|idx| *idx)
-                })
-                .collect();
-            let computed_inputs_from_permutation = computed_inputs_from_permutation?;
-            log::info!(
-                "[tx-validation] check_input: computed_inputs_from_permutation = {:?}, message_digits = {:?}",
-                &computed_inputs_from_permutation,
-                &display_validation_package.message_digits
+            Ok(())
+        }
+    }
+
+    /// Shared by `check_input` and `check_input_unsigned`
+    pub(crate) fn check_input_aux<T: Config>(
+        who: T::AccountId,
+        ipfs_cid: Vec<u8>,
+        input_digits: Vec<u8>,
+        input_encoding: InputEncoding,
+    ) -> DispatchResult {
+        log::info!(
+            "[tx-validation] check_input: who = {:?}, ipfs_cid = {:?}, input_digits = {:?}, input_encoding = {:?}",
+            &who,
+            sp_std::str::from_utf8(&ipfs_cid),
+            input_digits,
+            input_encoding,
+        );
+
+        // Compare with storage
+        let ipfs_cid = TryInto::<BoundedVec<u8, ConstU32<64>>>::try_into(ipfs_cid)
+            .map_err(|_err| Error::<T>::CircuitNotFound)?;
+        let display_validation_package =
+            <CircuitServerMetadataMap<T>>::get(who.clone(), ipfs_cid.clone())
+                .ok_or(Error::<T>::CircuitNotFound)?;
+
+        let input_digits_int = input_encoding
+            .decode_inputs(&input_digits)
+            .ok_or(Error::<T>::TxInvalidInputsGiven)?;
+
+        log::info!(
+            "[tx-validation] check_input: input_digits_int = {:?}",
+            input_digits_int,
+        );
+
+        // inputs length MUST match the number of digits of the message
+        if u32::try_from(input_digits_int.len()).ok()
+            != Some(display_validation_package.message_nb_digits)
+        {
+            return Err(Error::<T>::TxInvalidInputsGiven.into());
+        }
+
+        // inputs are pinpad indexes: they MUST be in the pinpad range
+        // NOTE: this is NOT the same thing as a wrong code, and the user is allowed to retry
+        if input_digits_int.iter().any(|pinpad_index| {
+            u32::from(*pinpad_index) >= display_validation_package.pinpad_nb_digits
+        }) {
+            return Err(Error::<T>::TxInvalidInputsGiven.into());
+        }
+
+        // compare the commitment of the user inputs with the one stored
+        // (ie the indexes the user SHOULD have tapped on the pinpad)
+        let computed_commitment =
+            compute_commitment::<T>(&display_validation_package.nonce, &input_digits_int);
+
+        // TODO remove the key from the map; we DO NOT want to allow retrying
+        let is_tx_pass = display_validation_package.commitment == computed_commitment;
+        if is_tx_pass {
+            log::info!("[tx-validation] TxPass",);
+            crate::Pallet::<T>::deposit_event(Event::TxPass {
+                account_id: who.clone(),
+            });
+
+            // the pinpad confirmation authorizes what was displayed, and ONLY once
+            if let Some(tx_intent) = <TxIntentMap<T>>::take(&who, &ipfs_cid) {
+                // DO NOT propagate the Err: that would rollback the TxPass
+                let result = T::Executor::execute(&who, &tx_intent);
+                log::info!("[tx-validation] TxIntent executed: {:?}", result);
+                crate::Pallet::<T>::deposit_event(Event::TxIntentExecuted {
+                    account_id: who.clone(),
+                    result,
+                });
+            }
+        } else {
+            log::info!("[tx-validation] TxFail",);
+            crate::Pallet::<T>::deposit_event(Event::TxFail {
+                account_id: who.clone(),
+            });
+            // DO NOT return an Err; that would rollback the tx and allow the user to retry
+            // this is NOT what we want!
+            // We only want to retry if the input are invalid(eg not in [0-9]) NOT if a wrong code is given
+            //
+            // TODO in this case we SHOULD NOT allow the user to retry; ie cleanup Storage etc
+        }
+
+        T::OnCircuitValidated::on_circuit_validated(&who, &ipfs_cid, is_tx_pass);
+
+        Ok(())
+    }
+
+    impl<T: Config> Pallet<T> {
+        /// Everything except the nonce ordering; cf `validate_unsigned` and `pre_dispatch`
+        ///
+        /// return: the longevity(in blocks)
+        fn validate_check_input_payload(
+            payload: &CheckInputPayload<T::AccountId, T::BlockNumber>,
+            signature: &[u8],
+        ) -> Result<T::BlockNumber, TransactionValidityError> {
+            let current_block = <frame_system::Pallet<T>>::block_number();
+            if payload.valid_until < current_block {
+                return Err(InvalidTransaction::Stale.into());
+            }
+            let longevity = payload.valid_until.saturating_sub(current_block);
+            if longevity > T::UnsignedLongevity::get() {
+                return Err(InvalidTransaction::Custom(INVALID_TX_VALID_UNTIL_TOO_FAR).into());
+            }
+
+            if payload.nonce < <MobileNonces<T>>::get(&payload.who) {
+                return Err(InvalidTransaction::Stale.into());
+            }
+
+            let genesis_hash = <frame_system::Pallet<T>>::block_hash(T::BlockNumber::zero());
+            if !T::MobileKeyVerifier::verify(
+                &payload.who,
+                &payload.signing_message(&genesis_hash),
+                signature,
+            ) {
+                return Err(InvalidTransaction::BadProof.into());
+            }
+
+            Ok(longevity)
+        }
+    }
+
+    #[pallet::validate_unsigned]
+    impl<T: Config> ValidateUnsigned for Pallet<T> {
+        type Call = Call<T>;
+
+        /// Only `check_input_unsigned` is allowed; cf `validate_check_input_payload`
+        ///
+        /// Like frame_system's CheckNonce: a payload with a future nonce is kept in the pool
+        /// until the previous one is included.
+        fn validate_unsigned(source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+            let (payload, signature) = match call {
+                Call::check_input_unsigned { payload, signature } => (payload, signature),
+                _ => return InvalidTransaction::Call.into(),
+            };
+            log::debug!(
+                "[tx-validation] validate_unsigned: check_input_unsigned from {:?}",
+                source
             );
 
-            // TODO remove the key from the map; we DO NOT want to allow retrying
-            if display_validation_package.message_digits == computed_inputs_from_permutation {
-                log::info!("[tx-validation] TxPass",);
-                crate::Pallet::<T>::deposit_event(Event::TxPass { account_id: who });
-                // TODO on success: call next step/callback (ie pallet-tx-XXX)
-            } else {
-                log::info!("[tx-validation] TxFail",);
-                crate::Pallet::<T>::deposit_event(Event::TxFail { account_id: who });
-                // DO NOT return an Err; that would rollback the tx and allow the user to retry
-                // this is NOT what we want!
-                // We only want to retry if the input are invalid(eg not in [0-9]) NOT if a wrong code is given
-                //
-                // TODO in this case we SHOULD NOT allow the user to retry; ie cleanup Storage etc
+            let longevity = Self::validate_check_input_payload(payload, signature)?;
+
+            let mut builder = ValidTransaction::with_tag_prefix("TxValidationCheckInput")
+                .priority(TransactionPriority::max_value() / 2)
+                .and_provides((payload.who.clone(), payload.nonce))
+                .longevity(longevity.saturated_into::<u64>().saturating_add(1))
+                .propagate(true);
+            if payload.nonce > <MobileNonces<T>>::get(&payload.who) {
+                builder = builder.and_requires((payload.who.clone(), payload.nonce - 1));
+            }
+
+            builder.build()
+        }
+
+        /// At block execution the nonce MUST be exactly the expected one
+        fn pre_dispatch(call: &Self::Call) -> Result<(), TransactionValidityError> {
+            let (payload, signature) = match call {
+                Call::check_input_unsigned { payload, signature } => (payload, signature),
+                _ => return Err(InvalidTransaction::Call.into()),
+            };
+
+            Self::validate_check_input_payload(payload, signature)?;
+            if payload.nonce != <MobileNonces<T>>::get(&payload.who) {
+                return Err(InvalidTransaction::Future.into());
             }
 
             Ok(())
@@ -0,0 +1,124 @@
+//! Storage migrations; called from `Hooks::on_runtime_upgrade`
+
+/// v1 -> v2: `DisplayValidationPackage` stores a commitment instead of the digits
+pub mod v2 {
+    use crate::pallet::*;
+    use frame_support::pallet_prelude::*;
+    use frame_support::traits::{GetStorageVersion, StorageVersion};
+    use sp_runtime::traits::{Hash, TrailingZeroInput};
+    #[cfg(feature = "try-runtime")]
+    use sp_std::vec::Vec;
+
+    /// v1 `DisplayValidationPackage`: the digits in clear
+    #[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen)]
+    pub struct OldDisplayValidationPackage {
+        pub message_digits: BoundedVec<u8, ConstU32<10>>,
+        pub pinpad_digits: BoundedVec<u8, ConstU32<10>>,
+    }
+
+    /// `CircuitServerMetadataMap` with its v1 value
+    #[frame_support::storage_alias]
+    pub(crate) type OldCircuitServerMetadataMap<T: Config> = StorageDoubleMap<
+        Pallet<T>,
+        Twox128,
+        <T as frame_system::Config>::AccountId,
+        Twox128,
+        BoundedVec<u8, ConstU32<64>>,
+        OldDisplayValidationPackage,
+    >;
+
+    /// None if the entry can not be translated(ie `store_metadata_aux` would have rejected it)
+    fn translate<T: Config>(
+        old: OldDisplayValidationPackage,
+        nonce: [u8; 32],
+    ) -> Option<DisplayValidationPackage<T::Hash>> {
+        if old.message_digits.is_empty() {
+            return None;
+        }
+        let expected_inputs =
+            compute_expected_inputs::<T>(&old.message_digits, &old.pinpad_digits).ok()?;
+
+        Some(DisplayValidationPackage {
+            commitment: compute_commitment::<T>(&nonce, &expected_inputs),
+            nonce,
+            message_nb_digits: old.message_digits.len() as u32,
+            pinpad_nb_digits: old.pinpad_digits.len() as u32,
+        })
+    }
+
+    /// Translate each entry of `CircuitServerMetadataMap`; so the pending circuits stay valid.
+    /// An invalid entry is removed: `check_input` then fails with `CircuitNotFound`.
+    ///
+    /// NOTE: the nonce is NOT random(same as `store_metadata`); it is public anyway
+    pub fn migrate<T: Config>() -> Weight {
+        let on_chain_version = Pallet::<T>::on_chain_storage_version();
+        if on_chain_version >= 2 {
+            log::info!(
+                "[tx-validation] migration v2: skipped; on-chain version {:?}",
+                on_chain_version
+            );
+            return T::DbWeight::get().reads(1);
+        }
+
+        let parent_hash = <frame_system::Pallet<T>>::parent_hash();
+        let mut nb_entries: u64 = 0;
+        let mut nb_removed: u64 = 0;
+        <CircuitServerMetadataMap<T>>::translate_values::<OldDisplayValidationPackage, _>(|old| {
+            nb_entries += 1;
+            let nonce_hash = T::Hashing::hash_of(&(b"tx-validation::v2", nb_entries, parent_hash));
+            let nonce = <[u8; 32]>::decode(&mut TrailingZeroInput::new(nonce_hash.as_ref()))
+                .expect("input is padded with zeroes; qed");
+
+            let new = translate::<T>(old, nonce);
+            if new.is_none() {
+                nb_removed += 1;
+            }
+            new
+        });
+        StorageVersion::new(2).put::<Pallet<T>>();
+
+        log::info!(
+            "[tx-validation] migration v2: {} entries translated, {} removed",
+            nb_entries - nb_removed,
+            nb_removed
+        );
+
+        T::DbWeight::get().reads_writes(1 + nb_entries, 1 + nb_entries)
+    }
+
+    /// return: the number of entries that SHOULD be left after the migration; None if skipped
+    #[cfg(feature = "try-runtime")]
+    pub fn pre_upgrade<T: Config>() -> Result<Vec<u8>, &'static str> {
+        let expected_nb_entries: Option<u64> = if Pallet::<T>::on_chain_storage_version() < 2 {
+            Some(
+                <OldCircuitServerMetadataMap<T>>::iter_values()
+                    .filter(|old| translate::<T>(old.clone(), [0; 32]).is_some())
+                    .count() as u64,
+            )
+        } else {
+            None
+        };
+
+        Ok(expected_nb_entries.encode())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    pub fn post_upgrade<T: Config>(state: Vec<u8>) -> Result<(), &'static str> {
+        ensure!(
+            Pallet::<T>::on_chain_storage_version() >= 2,
+            "[tx-validation] migration v2: wrong on-chain version"
+        );
+
+        let expected_nb_entries: Option<u64> =
+            Decode::decode(&mut state.as_slice()).map_err(|_err| "invalid pre_upgrade state")?;
+        if let Some(expected_nb_entries) = expected_nb_entries {
+            // NOTE: `iter_values` skips the values that can not be decoded
+            ensure!(
+                <CircuitServerMetadataMap<T>>::iter_values().count() as u64 == expected_nb_entries,
+                "[tx-validation] migration v2: some entries were not translated"
+            );
+        }
+
+        Ok(())
+    }
+}
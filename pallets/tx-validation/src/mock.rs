@@ -1,5 +1,10 @@
 use crate as pallet_tx_validation;
-use frame_support::traits::{ConstU16, ConstU64};
+use crate::{MobileKeyVerifier, TxIntent, TxIntentExecutor};
+use codec::Encode;
+use frame_support::dispatch::DispatchResult;
+use frame_support::parameter_types;
+use frame_support::traits::{ConstU16, ConstU32, ConstU64};
+use std::cell::RefCell;
 use frame_system as system;
 use sp_core::H256;
 use sp_runtime::{
@@ -18,6 +23,7 @@ frame_support::construct_runtime!(
         UncheckedExtrinsic = UncheckedExtrinsic,
     {
         System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+        Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
         TxValidation: pallet_tx_validation,
     }
 );
@@ -40,7 +46,7 @@ impl system::Config for Test {
     type BlockHashCount = ConstU64<250>;
     type Version = ();
     type PalletInfo = PalletInfo;
-    type AccountData = ();
+    type AccountData = pallet_balances::AccountData<u64>;
     type OnNewAccount = ();
     type OnKilledAccount = ();
     type SystemWeightInfo = ();
@@ -49,8 +55,61 @@ impl system::Config for Test {
     type MaxConsumers = frame_support::traits::ConstU32<16>;
 }
 
+impl pallet_balances::Config for Test {
+    type MaxLocks = ();
+    type MaxReserves = ();
+    type ReserveIdentifier = [u8; 8];
+    type Balance = u64;
+    type RuntimeEvent = RuntimeEvent;
+    type DustRemoval = ();
+    type ExistentialDeposit = ConstU64<1>;
+    type AccountStore = System;
+    type WeightInfo = ();
+}
+
+/// cf `CurrencyExecutor`
+pub const INITIAL_BALANCE: u64 = 100;
+
+thread_local! {
+    /// Every `TxIntent` given to `MockExecutor`
+    pub static EXECUTED_TX_INTENTS: RefCell<Vec<(u64, TxIntent<u64>)>> = RefCell::new(vec![]);
+}
+
+pub struct MockExecutor;
+impl TxIntentExecutor<u64> for MockExecutor {
+    fn execute(who: &u64, tx_intent: &TxIntent<u64>) -> DispatchResult {
+        EXECUTED_TX_INTENTS.with(|executed| executed.borrow_mut().push((*who, tx_intent.clone())));
+        Ok(())
+    }
+}
+
+/// Stands for pallet-mobile-registry: the "signature" is `mock_mobile_signature`
+pub struct MockMobileKeyVerifier;
+impl MobileKeyVerifier<u64> for MockMobileKeyVerifier {
+    fn verify(who: &u64, message: &[u8], signature: &[u8]) -> bool {
+        signature == mock_mobile_signature(*who, message)
+    }
+}
+
+pub fn mock_mobile_signature(who: u64, message: &[u8]) -> Vec<u8> {
+    sp_io::hashing::blake2_256(&(who, message).encode()).to_vec()
+}
+
+pub const UNSIGNED_LONGEVITY: u64 = 10;
+
 impl pallet_tx_validation::Config for Test {
     type RuntimeEvent = RuntimeEvent;
+    type Executor = MockExecutor;
+    type OnCircuitValidated = ();
+    type MobileKeyVerifier = MockMobileKeyVerifier;
+    type UnsignedLongevity = ConstU64<UNSIGNED_LONGEVITY>;
+    type MaxMessageDigits = ConstU32<8>;
+    type MaxPinpadDigits = ConstU32<16>;
+    type CommitmentSecret = MockCommitmentSecret;
+}
+
+parameter_types! {
+    pub const MockCommitmentSecret: [u8; 32] = [42u8; 32];
 }
 
 // Build genesis storage according to the mock runtime.
@@ -60,14 +119,14 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
     //     .unwrap()
     //     .into()
 
-    let t = frame_system::GenesisConfig::default()
+    let mut t = frame_system::GenesisConfig::default()
         .build_storage::<Test>()
         .unwrap();
-    // pallet_tx_validation::GenesisConfig::<Test> {
-    //     balances: vec![(1, 10), (2, 10), (3, 10), (4, 10), (5, 2)],
-    // }
-    // .assimilate_storage(&mut t)
-    // .unwrap();
+    pallet_balances::GenesisConfig::<Test> {
+        balances: vec![(1, INITIAL_BALANCE)],
+    }
+    .assimilate_storage(&mut t)
+    .unwrap();
 
     let mut ext = sp_io::TestExternalities::new(t);
     ext.execute_with(|| System::set_block_number(1));
@@ -0,0 +1,171 @@
+//! Plays the mobile role in the end-to-end tests:
+//! - fetch the garbled circuits of a `DisplayStrippedCircuitsPackage` from the store(ie IPFS)
+//! - evaluate them like the phone does, into in-memory frames
+//! - read the digits shown: the OTP(message) and the pinpad layout
+//!
+//! Then a test can "tap" the pinpad(`EvaluatedPackage::pinpad_positions`) and call
+//! `pallet_tx_validation::check_input` with `InputEncoding::RawIndices`.
+//!
+//! NOT for production: it is std only, and NOT hardened in any way.
+
+#[cfg(test)]
+mod tests;
+
+use circuits_storage_common::seven_segments;
+use circuits_storage_common::DisplayStrippedCircuitsPackage;
+use interstellar_ipfs_client::IpfsClient;
+use std::collections::HashMap;
+
+pub use circuits_storage_common::seven_segments::Frame;
+
+/// Same as the self-check in pallet-ocw-garble
+pub const NB_FRAMES: u32 = 16;
+
+#[derive(Debug)]
+pub enum Error {
+    Store(String),
+    Evaluation(String),
+    /// the segments shown in a cell do not match any digit
+    UnreadableDigit {
+        is_message: bool,
+        index: usize,
+    },
+}
+
+/// Where the .pgarbled are downloaded from
+pub trait PackageStore {
+    fn get(&self, cid: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+pub struct IpfsStore {
+    client: interstellar_ipfs_client::IpfsClientHttpReq,
+}
+
+impl IpfsStore {
+    /// param: root_url: IPFS API eg "http://127.0.0.1:5001"
+    pub fn new(root_url: &str) -> Result<Self, Error> {
+        let client = interstellar_ipfs_client::IpfsClientHttpReq::new(root_url)
+            .map_err(|err| Error::Store(format!("{err:?}")))?;
+        Ok(Self { client })
+    }
+}
+
+impl PackageStore for IpfsStore {
+    fn get(&self, cid: &[u8]) -> Result<Vec<u8>, Error> {
+        let cid = std::str::from_utf8(cid).map_err(|err| Error::Store(err.to_string()))?;
+        self.client
+            .ipfs_cat(cid)
+            .map_err(|err| Error::Store(format!("{err:?}")))
+    }
+}
+
+/// In-memory store: cid -> .pgarbled
+impl PackageStore for HashMap<Vec<u8>, Vec<u8>> {
+    fn get(&self, cid: &[u8]) -> Result<Vec<u8>, Error> {
+        self.get(cid)
+            .cloned()
+            .ok_or_else(|| Error::Store(format!("unknown cid: {cid:?}")))
+    }
+}
+
+/// Evaluate a serialized evaluator package(.pgarbled) into a frame
+pub trait DisplayEvaluator {
+    /// param: layout: (width, height, digits_bboxes) the circuit was generated with; cf
+    ///   `pallet_ocw_circuits::display_layout`. A real evaluator gets them from the circuit,
+    ///   and MUST fail if they do not match.
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        nb_frames: u32,
+        layout: &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String>;
+}
+
+/// What the phone does; same as `pallet_ocw_garble::LibGarbleRs::evaluate`
+pub struct LibGarbleRsEvaluator;
+
+impl DisplayEvaluator for LibGarbleRsEvaluator {
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        nb_frames: u32,
+        (width, height, _digits_bboxes): &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String> {
+        // like the phone: a random "rndswitch" per frame
+        circuits_storage_common::evaluator::evaluate_display(
+            serialized_package_for_eval,
+            nb_frames,
+            (*width, *height),
+            &mut rand::thread_rng(),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct EvaluatedPackage {
+    pub message_frame: Frame,
+    pub pinpad_frame: Frame,
+    /// the OTP; NOTE: symbols cf `circuits_storage_common::DisplayProfile`
+    pub message_digits: Vec<u8>,
+    /// the symbol shown in each pinpad digit cell; in pinpad order cf `PinpadLayout::digit_cells`
+    pub pinpad_digits: Vec<u8>,
+}
+
+impl EvaluatedPackage {
+    /// The pinpad positions to tap to enter `digits`; None if a digit is not on the pinpad
+    pub fn pinpad_positions(&self, digits: &[u8]) -> Option<Vec<u8>> {
+        digits
+            .iter()
+            .map(|digit| {
+                self.pinpad_digits
+                    .iter()
+                    .position(|pinpad_digit| pinpad_digit == digit)
+                    .map(|position| position as u8)
+            })
+            .collect()
+    }
+}
+
+/// Fetch, evaluate and read both circuits of `package`
+pub fn evaluate_package<E: DisplayEvaluator, S: PackageStore>(
+    store: &S,
+    package: &DisplayStrippedCircuitsPackage,
+) -> Result<EvaluatedPackage, Error> {
+    let (message_frame, message_digits) = evaluate_and_read::<E, S>(
+        store,
+        &package.message_pgarbled_cid,
+        true,
+        pallet_ocw_circuits::display_layout(true, package.message_nb_digits),
+    )?;
+    let (pinpad_frame, pinpad_digits) = evaluate_and_read::<E, S>(
+        store,
+        &package.pinpad_pgarbled_cid,
+        false,
+        pallet_ocw_circuits::pinpad_display_layout(&package.pinpad_layout),
+    )?;
+
+    Ok(EvaluatedPackage {
+        message_frame,
+        pinpad_frame,
+        message_digits,
+        pinpad_digits,
+    })
+}
+
+fn evaluate_and_read<E: DisplayEvaluator, S: PackageStore>(
+    store: &S,
+    pgarbled_cid: &[u8],
+    is_message: bool,
+    layout: (u32, u32, Vec<f32>),
+) -> Result<(Frame, Vec<u8>), Error> {
+    let serialized_package_for_eval = store.get(pgarbled_cid)?;
+    let frame =
+        E::evaluate(&serialized_package_for_eval, NB_FRAMES, &layout).map_err(Error::Evaluation)?;
+    let (_width, _height, digits_bboxes) = layout;
+
+    let digits = seven_segments::read_digits(&frame, &digits_bboxes)
+        .into_iter()
+        .enumerate()
+        .map(|(index, digit)| digit.ok_or(Error::UnreadableDigit { is_message, index }))
+        .collect::<Result<Vec<u8>, Error>>()?;
+
+    Ok((frame, digits))
+}
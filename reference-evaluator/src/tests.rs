@@ -0,0 +1,95 @@
+use crate::*;
+use circuits_storage_common::seven_segments::draw_digit;
+use frame_support::BoundedVec;
+
+/// Fake: the "package" is directly the digits to show, drawn in `layout`
+struct FakeEvaluator;
+impl DisplayEvaluator for FakeEvaluator {
+    fn evaluate(
+        serialized_package_for_eval: &[u8],
+        _nb_frames: u32,
+        (width, height, digits_bboxes): &(u32, u32, Vec<f32>),
+    ) -> Result<Frame, String> {
+        let mut frame = Frame::new(*width, *height);
+        for (bbox, digit) in digits_bboxes.chunks_exact(4).zip(serialized_package_for_eval) {
+            draw_digit(&mut frame, bbox, *digit);
+        }
+        Ok(frame)
+    }
+}
+
+fn test_package(message_nb_digits: u32, pinpad_nb_digits: u32) -> DisplayStrippedCircuitsPackage {
+    DisplayStrippedCircuitsPackage {
+        message_pgarbled_cid: BoundedVec::try_from(b"MESSAGE_CID".to_vec()).unwrap(),
+        pinpad_pgarbled_cid: BoundedVec::try_from(b"PINPAD_CID".to_vec()).unwrap(),
+        message_nb_digits,
+        pinpad_nb_digits,
+        pinpad_layout: circuits_storage_common::PinpadLayout::row(pinpad_nb_digits),
+    }
+}
+
+fn test_store(message_digits: &[u8], pinpad_digits: &[u8]) -> HashMap<Vec<u8>, Vec<u8>> {
+    HashMap::from([
+        (b"MESSAGE_CID".to_vec(), message_digits.to_vec()),
+        (b"PINPAD_CID".to_vec(), pinpad_digits.to_vec()),
+    ])
+}
+
+const PINPAD_DIGITS: [u8; 10] = [3, 1, 4, 0, 5, 9, 2, 6, 8, 7];
+
+#[test]
+fn test_evaluate_package_ok() {
+    let evaluated = evaluate_package::<FakeEvaluator, _>(
+        &test_store(&[4, 2], &PINPAD_DIGITS),
+        &test_package(2, 10),
+    )
+    .unwrap();
+
+    assert_eq!(evaluated.message_digits, vec![4, 2]);
+    assert_eq!(evaluated.pinpad_digits, PINPAD_DIGITS.to_vec());
+    assert_eq!(
+        evaluated.pinpad_positions(&evaluated.message_digits),
+        Some(vec![2, 6])
+    );
+}
+
+/// The pinpad is read with the layout of the package; eg a 4x3 grid
+#[test]
+fn test_evaluate_package_phone_pinpad_ok() {
+    let package = DisplayStrippedCircuitsPackage {
+        pinpad_layout: circuits_storage_common::PinpadLayout::phone(),
+        ..test_package(2, 10)
+    };
+    let evaluated =
+        evaluate_package::<FakeEvaluator, _>(&test_store(&[4, 2], &PINPAD_DIGITS), &package)
+            .unwrap();
+
+    assert_eq!(evaluated.pinpad_digits, PINPAD_DIGITS.to_vec());
+}
+
+#[test]
+fn test_evaluate_package_unknown_cid_err() {
+    let store = HashMap::from([(b"MESSAGE_CID".to_vec(), vec![4, 2])]);
+    assert!(matches!(
+        evaluate_package::<FakeEvaluator, _>(&store, &test_package(2, 10)),
+        Err(Error::Store(_))
+    ));
+}
+
+/// eg 6 hexadecimal digits with a 16 cells pinpad
+#[test]
+fn test_evaluate_package_hexadecimal_ok() {
+    let pinpad_digits = [15, 0, 14, 1, 13, 2, 12, 3, 11, 4, 10, 5, 9, 6, 8, 7];
+    let evaluated = evaluate_package::<FakeEvaluator, _>(
+        &test_store(&[10, 11, 12, 13, 14, 15], &pinpad_digits),
+        &test_package(6, 16),
+    )
+    .unwrap();
+
+    assert_eq!(evaluated.message_digits, vec![10, 11, 12, 13, 14, 15]);
+    assert_eq!(evaluated.pinpad_digits, pinpad_digits.to_vec());
+    assert_eq!(
+        evaluated.pinpad_positions(&evaluated.message_digits),
+        Some(vec![10, 8, 6, 4, 2, 0])
+    );
+}